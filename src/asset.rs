@@ -1,7 +1,12 @@
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
 use std::{fs, path};
 
+use chrono::{DateTime, Utc};
 use failure::ResultExt;
 use regex::Regex;
+use reqwest::{redirect, Url};
 use serde_json::Value;
 #[cfg(feature = "cli")]
 use structopt::StructOpt;
@@ -10,11 +15,18 @@ use bitcoin_hashes::{hex::FromHex, hex::ToHex, sha256, Hash};
 use elements::{issuance::ContractHash, AssetId, OutPoint};
 use secp256k1::Secp256k1;
 
-use crate::chain::{verify_asset_issuance_tx, ChainQuery};
-use crate::entity::{verify_asset_link, AssetEntity};
-use crate::errors::{OptionExt, Result};
+use crate::chain::{verify_asset_issuance_tx, ChainQuery, IssuanceAmount, IssuanceDetails};
+use crate::entity::{
+    http_client_builder, verification_methods, verify_asset_link, AssetEntity, DnsResolver,
+    DomainVerificationMethod, HttpProofVersion, ProofLocation, VerifiedLink,
+};
+use crate::errors::{Error, OptionExt, Result};
+use crate::psl;
+use crate::schema;
+use crate::tls::{TlsCertificate, TlsPin};
 use crate::util::{
-    serde_from_hex, serde_to_hex, verify_bitcoin_msg, verify_domain_name, verify_pubkey, TxInput,
+    reverse_asset_id, serde_from_hex32_opt, serde_from_pubkey, serde_to_hex, serde_to_hex32_opt,
+    verify_bitcoin_msg, verify_domain_name_with_limits, verify_domain_tld, verify_pubkey, TxInput,
 };
 
 lazy_static! {
@@ -22,6 +34,7 @@ lazy_static! {
     static ref RE_NAME: Regex = Regex::new(r"^[[:ascii:]]{1,255}$").unwrap();
     static ref RE_TICKER: Regex = Regex::new(r"^[a-zA-Z0-9.\-]{3,24}$").unwrap();
     static ref RE_COLLECTION: Regex = Regex::new(r"^[[:ascii:]]{1,255}$").unwrap();
+    static ref RE_DESCRIPTION: Regex = Regex::new(r"^[[:ascii:]]{1,1000}$").unwrap();
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -32,11 +45,66 @@ pub struct Asset {
     pub issuance_txin: TxInput,
     pub issuance_prevout: OutPoint,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuance_amount: Option<IssuanceAmount>,
+
+    // Whether the blinded amounts of a confidential issuance carry structurally valid rangeproofs.
+    // Only set for confidential issuances.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_issuance_proof: Option<bool>,
+
+    // The issuance block, type and reissuance token, derived when verifying the issuance
+    // transaction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuance_details: Option<IssuanceDetails>,
+
     #[serde(flatten)]
     pub fields: AssetFields,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
+
+    // Bitcoin message signature by the issuer_pubkey over the canonical contract json
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract_signature: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain_verification_method: Option<DomainVerificationMethod>,
+
+    // When the entity link was verified by the registry, per verification method
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_verified_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_verified_at: Option<DateTime<Utc>>,
+    // The version of the HTTP proof path the entity link was verified at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_proof_version: Option<HttpProofVersion>,
+    // The host that served the HTTP proof, when it's not the entity domain itself (with
+    // --http-proof-www-fallback). Re-verification fetches the proof from the same host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_proof_host: Option<String>,
+    // Where the proofs were found by each verification method (the proof page url, or the name of
+    // the TXT record), so that re-verification can go straight to them. Derived during
+    // verification, not part of the contract.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proof_locations: Vec<ProofLocation>,
+    // The TLS certificate served by the entity domain when its HTTP proof was verified, recorded
+    // for auditing with --record-tls-certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_certificate: Option<TlsCertificate>,
+
+    // When the asset was registered. Persisted in the asset file (unlike the filesystem mtime,
+    // which doesn't survive git checkouts and backup restores).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+// The outcome of a successful verification, recorded on the asset with `record_verification()`
+#[derive(Debug, Clone)]
+pub struct Verification {
+    // only set when the issuance was verified on-chain
+    pub issuance: Option<IssuanceDetails>,
+    pub link: VerifiedLink,
 }
 
 // Issuer-supplied fields
@@ -44,7 +112,10 @@ pub struct Asset {
 pub struct AssetFields {
     pub version: u8,
 
-    #[serde(deserialize_with = "serde_from_hex", serialize_with = "serde_to_hex")]
+    #[serde(
+        deserialize_with = "serde_from_pubkey",
+        serialize_with = "serde_to_hex"
+    )]
     pub issuer_pubkey: Vec<u8>,
 
     pub name: String,
@@ -53,44 +124,415 @@ pub struct AssetFields {
 
     pub collection: Option<String>,
 
+    // not present in older contracts, omitted from the serialization when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    // an icon for the asset, committed to by the sha256 of its contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "serde_from_hex32_opt",
+        serialize_with = "serde_to_hex32_opt"
+    )]
+    pub icon_sha256: Option<[u8; 32]>,
+
     #[serde(default = "default_precision")]
     pub precision: u8,
 
+    // `version` 1 contracts may omit the entity, see `AssetEntity::None`
+    #[serde(default)]
     pub entity: AssetEntity,
 }
 
+// Operator-configured validation rules, layered on top of the built-in ones.
+// Configured limits can only tighten the built-in rules, never loosen them: a limit
+// above the built-in maximum has no effect since the base regexes are always applied.
+#[cfg_attr(feature = "cli", derive(StructOpt))]
+#[derive(Debug, Clone, Default)]
+pub struct ValidationConfig {
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-name-length",
+            env,
+            help = "Maximum length for asset names (can only tighten the built-in limit of 255)"
+        )
+    )]
+    pub max_name_length: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-ticker-length",
+            env,
+            help = "Maximum length for asset tickers (can only tighten the built-in limit of 24)"
+        )
+    )]
+    pub max_ticker_length: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-collection-length",
+            env,
+            help = "Maximum length for asset collections (can only tighten the built-in limit of 255)"
+        )
+    )]
+    pub max_collection_length: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "allowed-tlds",
+            env,
+            use_delimiter = true,
+            number_of_values = 1,
+            help = "Only accept entity domains under these TLDs (comma separated, accepts all if unset)"
+        )
+    )]
+    pub allowed_tlds: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-domain-length",
+            env,
+            help = "Maximum length for entity domain names (can only tighten the built-in limit of 255)"
+        )
+    )]
+    pub max_domain_length: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-domain-labels",
+            env,
+            help = "Maximum number of labels in entity domain names (can only tighten the built-in limit of 127)"
+        )
+    )]
+    pub max_domain_labels: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "public-suffix-list",
+            env,
+            parse(from_os_str),
+            help = "Only accept entity domains registrable under a public suffix from this list (in the publicsuffix.org format, reloaded when modified)"
+        )
+    )]
+    pub public_suffix_list: Option<path::PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "require-both-proofs",
+            help = "Require both the HTTP and DNS domain proofs, regardless of the requested verification method"
+        )
+    )]
+    pub require_both_proofs: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "default-verification-method",
+            env,
+            help = "The domain verification method to use for assets that don't specify one (http or dns, defaults to http)"
+        )
+    )]
+    pub default_verification_method: Option<DomainVerificationMethod>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "dns-resolver",
+            env,
+            help = "The DNS-over-HTTPS resolver for looking up DNS proofs (google, cloudflare or the https url of a resolver with the same JSON api, defaults to google)"
+        )
+    )]
+    pub dns_resolver: Option<DnsResolver>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "allow-entityless-assets",
+            help = "Accept self-declared assets without a linked entity (version 1 contracts). These must carry a contract signature and can't claim a ticker"
+        )
+    )]
+    pub allow_entityless_assets: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "allow-unconfirmed",
+            help = "Accept assets whose issuance transaction is still unconfirmed, as long as it can't be replaced (doesn't signal BIP 125 RBF and has no unconfirmed inputs)"
+        )
+    )]
+    pub allow_unconfirmed: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "require-issuance-proofs",
+            help = "Require confidential issuances to carry structurally valid rangeproofs for their blinded amounts"
+        )
+    )]
+    pub require_issuance_proofs: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "contract-schema",
+            env,
+            parse(from_os_str),
+            help = "Also require contracts to match this JSON Schema, for registry-specific fields (reloaded when modified)"
+        )
+    )]
+    pub contract_schema: Option<path::PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "tls-pin",
+            env,
+            use_delimiter = true,
+            number_of_values = 1,
+            help = "Require the entity domain to serve a TLS certificate with this SHA256 fingerprint when verifying its HTTP proof, as <domain>=<hex> (comma separated, multiple pins for the same domain accept any of them)"
+        )
+    )]
+    pub tls_pins: Vec<TlsPin>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "record-tls-certificate",
+            help = "Record the subject, issuer and fingerprint of the entity domain's TLS certificate on assets verified over HTTP, for auditing"
+        )
+    )]
+    pub record_tls_certificate: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "http-proof-www-fallback",
+            help = "Look for the HTTP proof on the entity domain with its www. prefix added or removed when not found on the domain itself, accepting a proof served by this canonical host"
+        )
+    )]
+    pub http_proof_www_fallback: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "tor-proxy",
+            env,
+            help = "Fetch from .onion hosts through this Tor SOCKS5 proxy, as <host>:<port> (like 127.0.0.1:9050)"
+        )
+    )]
+    pub tor_proxy: Option<String>,
+}
+
+// The steps of verifying an asset registration. Failures are reported prefixed by the step's
+// context message, which allows clients to tell which step failed (see client::RegistrationError).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationStep {
+    ContractFields,
+    IssuanceCommitment,
+    AssetFields,
+    ContractSignature,
+    OnChainIssuance,
+    Icon,
+    LinkedEntity,
+}
+
+impl VerificationStep {
+    pub const ALL: [VerificationStep; 7] = [
+        VerificationStep::ContractFields,
+        VerificationStep::IssuanceCommitment,
+        VerificationStep::AssetFields,
+        VerificationStep::ContractSignature,
+        VerificationStep::OnChainIssuance,
+        VerificationStep::Icon,
+        VerificationStep::LinkedEntity,
+    ];
+
+    pub fn context(self) -> &'static str {
+        match self {
+            VerificationStep::ContractFields => "invalid contract fields",
+            VerificationStep::IssuanceCommitment => "failed verifying issuance commitment",
+            VerificationStep::AssetFields => "failed verifying asset fields",
+            VerificationStep::ContractSignature => "failed verifying contract signature",
+            VerificationStep::OnChainIssuance => "failed verifying on-chain issuance",
+            VerificationStep::Icon => "failed verifying asset icon",
+            VerificationStep::LinkedEntity => "failed verifying linked entity",
+        }
+    }
+
+    // A stable identifier for the step, as serialized
+    pub fn code(self) -> &'static str {
+        match self {
+            VerificationStep::ContractFields => "contract_fields",
+            VerificationStep::IssuanceCommitment => "issuance_commitment",
+            VerificationStep::AssetFields => "asset_fields",
+            VerificationStep::ContractSignature => "contract_signature",
+            VerificationStep::OnChainIssuance => "on_chain_issuance",
+            VerificationStep::Icon => "icon",
+            VerificationStep::LinkedEntity => "linked_entity",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<VerificationStep> {
+        VerificationStep::ALL
+            .iter()
+            .copied()
+            .find(|step| step.code() == code)
+    }
+
+    // The step that failed, identified by its context message in the error chain
+    pub fn of_error(err: &Error) -> Option<VerificationStep> {
+        err.iter_chain().find_map(|cause| {
+            let message = cause.to_string();
+            VerificationStep::ALL
+                .iter()
+                .copied()
+                .find(|step| step.context() == message)
+        })
+    }
+}
+
+// The contract fields without a default value. The `entity` is optional in `version` 1 contracts.
+const REQUIRED_CONTRACT_FIELDS: [&str; 4] = ["version", "issuer_pubkey", "name", "entity"];
+
+// The contract version for self-declared assets, which may omit the `entity`
+const ENTITYLESS_CONTRACT_VERSION: u8 = 1;
+
 impl AssetFields {
     fn from_contract(contract: &Value) -> Result<Self> {
+        // check for missing fields upfront, for a friendlier error than serde's
+        let contract_obj = contract
+            .as_object()
+            .or_err("contract must be a json object")?;
+        let entity_optional =
+            contract_obj.get("version") == Some(&Value::from(ENTITYLESS_CONTRACT_VERSION));
+        for field in &REQUIRED_CONTRACT_FIELDS {
+            if *field == "entity" && entity_optional {
+                continue;
+            }
+            ensure!(
+                contract_obj
+                    .get(*field)
+                    .map_or(false, |value| !value.is_null()),
+                "contract missing required field `{}`",
+                field
+            );
+        }
         Ok(serde_json::from_value(contract.clone())?)
     }
 
-    fn validate(&self) -> Result<()> {
-        ensure!(self.version == 0, "unknown `version`");
+    pub fn validate(&self, config: &ValidationConfig) -> Result<()> {
+        ensure!(
+            self.version == 0 || self.version == ENTITYLESS_CONTRACT_VERSION,
+            "unknown `version`"
+        );
 
         ensure!(self.precision <= 8, "`precision` out of range");
         ensure!(RE_NAME.is_match(&self.name), "invalid `name`");
+        check_max_length("name", &self.name, config.max_name_length)?;
 
         if let Some(ticker) = &self.ticker {
-            ensure!(RE_TICKER.is_match(ticker), "invalid `ticker`");
+            ensure!(is_valid_ticker(ticker), "invalid `ticker`");
+            check_max_length("ticker", ticker, config.max_ticker_length)?;
         }
 
         if let Some(collection) = &self.collection {
             ensure!(RE_COLLECTION.is_match(collection), "invalid `collection`");
+            check_max_length("collection", collection, config.max_collection_length)?;
+        }
+
+        if let Some(description) = &self.description {
+            ensure!(
+                RE_DESCRIPTION.is_match(description),
+                "invalid `description`"
+            );
+        }
+
+        match (&self.icon_url, &self.icon_sha256) {
+            (Some(icon_url), Some(_)) => verify_icon_url(icon_url).context("invalid `icon_url`")?,
+            (None, None) => (),
+            _ => bail!("`icon_url` and `icon_sha256` must be provided together"),
         }
 
         verify_pubkey(&self.issuer_pubkey).context("invalid `issuer_pubkey`")?;
 
-        let AssetEntity::DomainName(domain) = &self.entity;
-        verify_domain_name(domain).context("invalid `entity` domain name")?;
+        let domain = match &self.entity {
+            AssetEntity::DomainName(domain) => {
+                ensure!(
+                    self.version == 0,
+                    "`version` {} is reserved for contracts without an `entity`",
+                    ENTITYLESS_CONTRACT_VERSION
+                );
+                domain
+            }
+            AssetEntity::None => return self.validate_entityless(config),
+        };
+
+        verify_domain_name_with_limits(domain, config.max_domain_length, config.max_domain_labels)
+            .context("invalid `entity` domain name")?;
+        verify_domain_tld(domain, &config.allowed_tlds).context("invalid `entity` domain name")?;
+
+        // onion addresses are not part of the public suffix list
+        if let Some(list_path) = &config.public_suffix_list {
+            if !domain.trim_end_matches('.').ends_with(".onion") {
+                psl::load_cached(list_path)?
+                    .verify_domain(domain)
+                    .context("invalid `entity` domain name")?;
+            }
+        }
 
         Ok(())
     }
+
+    // Self-declared assets are subject to a stricter policy, since nothing ties them to their
+    // issuer: they must be explicitly allowed by the operator and can't reserve a ticker. The
+    // contract signature required for them is checked by `Asset::verify_offline`.
+    fn validate_entityless(&self, config: &ValidationConfig) -> Result<()> {
+        ensure!(
+            config.allow_entityless_assets,
+            "contracts without an `entity` are not accepted by this registry"
+        );
+        ensure!(
+            self.version == ENTITYLESS_CONTRACT_VERSION,
+            "contracts without an `entity` require `version` {}",
+            ENTITYLESS_CONTRACT_VERSION
+        );
+        ensure!(
+            self.ticker.is_none(),
+            "contracts without an `entity` can't claim a `ticker`"
+        );
+        Ok(())
+    }
 }
 
 fn default_precision() -> u8 {
     0
 }
 
+fn check_max_length(field: &str, value: &str, max_length: Option<usize>) -> Result<()> {
+    if let Some(max_length) = max_length {
+        ensure!(
+            value.len() <= max_length,
+            "`{}` exceeds the maximum length of {} characters",
+            field,
+            max_length
+        );
+    }
+    Ok(())
+}
+
 impl Asset {
     pub fn load(path: path::PathBuf) -> Result<Asset> {
         let contents = fs::read_to_string(path)?;
@@ -109,73 +551,267 @@ impl Asset {
         &self.fields.entity
     }
 
-    pub fn verify(&self, chain: Option<&ChainQuery>) -> Result<()> {
-        self.fields.validate()?;
+    // Verify the asset, returning the details derived along the way. The issuance is only
+    // verified on-chain when a chain is given.
+    pub fn verify(
+        &self,
+        chain: Option<&ChainQuery>,
+        config: &ValidationConfig,
+    ) -> Result<Verification> {
+        self.verify_offline(config)?;
+
+        let issuance = match chain {
+            Some(chain) => Some(
+                verify_asset_issuance_tx(chain, self, config)
+                    .context(VerificationStep::OnChainIssuance.context())?,
+            ),
+            None => None,
+        };
 
-        verify_asset_commitment(self).context("failed verifying issuance commitment")?;
+        verify_asset_icon(self, config).context(VerificationStep::Icon.context())?;
 
-        verify_asset_fields(self).context("failed verifying asset fields")?;
+        let link =
+            verify_asset_link(self, config).context(VerificationStep::LinkedEntity.context())?;
+        Ok(Verification { issuance, link })
+    }
 
-        if let Some(chain) = chain {
-            verify_asset_issuance_tx(chain, self).context("failed verifying on-chain issuance")?;
-            // XXX keep block id?
+    // Record the outcome of a successful verification on the asset: the on-chain issuance
+    // details and proof status, the verification time of each method, where the proofs were found, and the
+    // details of the HTTP proof
+    pub fn record_verification(&mut self, verification: Verification, config: &ValidationConfig) {
+        let verified_at = Utc::now();
+        let link = verification.link;
+        if let Some(issuance) = &verification.issuance {
+            self.has_issuance_proof = issuance.has_issuance_proof;
+        }
+        self.issuance_details = verification.issuance;
+        let http_proof = link.http_proof;
+        self.proof_locations = link.proofs;
+        self.http_proof_version = http_proof.as_ref().map(|proof| proof.version);
+        self.http_proof_host = http_proof.as_ref().and_then(|proof| proof.host.clone());
+        self.tls_certificate = http_proof.and_then(|proof| proof.tls_certificate);
+        for method in verification_methods(self, config) {
+            match method {
+                DomainVerificationMethod::Http => self.http_verified_at = Some(verified_at),
+                DomainVerificationMethod::Dns => self.dns_verified_at = Some(verified_at),
+            }
         }
+    }
+
+    // Where the proof of the given verification method was found when the asset was verified
+    pub fn proof_location(&self, method: DomainVerificationMethod) -> Option<&str> {
+        self.proof_locations
+            .iter()
+            .find(|proof| proof.method == method)
+            .map(|proof| proof.location.as_str())
+    }
+
+    // Verify what can be checked without network access: the fields and their commitment
+    pub fn verify_offline(&self, config: &ValidationConfig) -> Result<()> {
+        self.fields
+            .validate(config)
+            .and_then(|()| verify_contract_schema(&self.contract, config))
+            .context(VerificationStep::ContractFields.context())?;
 
-        verify_asset_link(self).context("failed verifying linked entity")?;
+        verify_asset_commitment(self).context(VerificationStep::IssuanceCommitment.context())?;
+
+        verify_asset_fields(self).context(VerificationStep::AssetFields.context())?;
+
+        verify_asset_contract_sig(self).context(VerificationStep::ContractSignature.context())?;
 
         Ok(())
     }
 
+    // Verify the issuer's authorization for deleting the asset. Both the current message form
+    // (bound to the contract hash) and the legacy one are accepted during the transition period.
     pub fn verify_deletion(&self, signature: &[u8]) -> Result<()> {
-        verify_bitcoin_msg(
+        let err = match verify_bitcoin_msg(
             &EC,
             &self.fields.issuer_pubkey,
             &signature,
-            &format_deletion_sig_msg(self),
+            &format_deletion_sig_msg(self, true)?,
+        ) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        if verify_bitcoin_msg(
+            &EC,
+            &self.fields.issuer_pubkey,
+            &signature,
+            &format_deletion_sig_msg(self, false)?,
         )
+        .is_ok()
+        {
+            debug!(
+                "deletion of {} authorized with the legacy message form",
+                self.asset_id.to_hex()
+            );
+            return Ok(());
+        }
+
+        Err(err)
     }
 
     pub fn contract_hash(&self) -> Result<ContractHash> {
         contract_json_hash(&self.contract)
     }
 
-    pub fn from_request(req: AssetRequest, chain: &ChainQuery) -> Result<Self> {
-        let mut asset_data = chain
-            .get_asset(&req.asset_id)?
-            .or_err("asset id not found")?;
-
-        let fields =
-            AssetFields::from_contract(&req.contract).context("invalid contract fields")?;
+    pub fn from_request(mut req: AssetRequest, chain: &ChainQuery) -> Result<Self> {
+        let issuance = match chain.get_issuance_info(&req.asset_id)? {
+            Some(issuance) => issuance,
+            None => {
+                // the asset id might've been provided in the wrong byte order, try the reverse
+                let reversed_id = reverse_asset_id(&req.asset_id);
+                let issuance = chain
+                    .get_issuance_info(&reversed_id)?
+                    .or_err("asset id not found")?;
+                warn!(
+                    "asset id {} not found, using its reversed byte order form {}",
+                    req.asset_id.to_hex(),
+                    reversed_id.to_hex()
+                );
+                req.asset_id = reversed_id;
+                issuance
+            }
+        };
 
-        let issuance_txin = serde_json::from_value(asset_data["issuance_txin"].take())?;
-        let issuance_prevout = serde_json::from_value(asset_data["issuance_prevout"].take())?;
+        let fields = AssetFields::from_contract(&req.contract)
+            .context(VerificationStep::ContractFields.context())?;
 
         Ok(Asset {
             asset_id: req.asset_id,
             contract: req.contract,
             fields,
-            issuance_txin,
-            issuance_prevout,
+            issuance_txin: issuance.issuance_txin,
+            issuance_prevout: issuance.issuance_prevout,
+            issuance_amount: issuance.issuance_amount,
+            has_issuance_proof: None,
+            issuance_details: None,
             signature: None,
+            contract_signature: req.contract_signature,
+            domain_verification_method: req.domain_verification_method,
+            http_verified_at: None,
+            dns_verified_at: None,
+            http_proof_version: None,
+            http_proof_host: None,
+            proof_locations: vec![],
+            tls_certificate: None,
+            created_at: None,
         })
     }
 
-    pub fn validate_contract(contract: &Value, contract_hash: &ContractHash) -> Result<()> {
-        AssetFields::from_contract(contract)?.validate()?;
+    // Validate the contract and its hash. When the issuance prevout is provided, also derive and
+    // return the asset id the contract would be issued as.
+    pub fn validate_contract(
+        contract: &Value,
+        contract_hash: &ContractHash,
+        prevout: Option<&OutPoint>,
+        config: &ValidationConfig,
+    ) -> Result<Option<AssetId>> {
+        AssetFields::from_contract(contract)?.validate(config)?;
+        verify_contract_schema(contract, config)?;
 
         let expected_hash = contract_json_hash(contract)?;
-        ensure!(
-            expected_hash == *contract_hash,
-            "contract hash mismatch, expected {}",
-            expected_hash
-        );
-        Ok(())
+        if expected_hash != *contract_hash {
+            ensure!(
+                contract_json_hash_sha256d(contract)? != *contract_hash,
+                DOUBLE_SHA256_ERR
+            );
+            bail!(
+                "contract hash mismatch, expected {} (the SHA256 of the canonical contract json: {})",
+                expected_hash,
+                contract_json_canonical(contract)?
+            );
+        }
+
+        Ok(prevout.map(|prevout| {
+            let entropy = AssetId::generate_asset_entropy(*prevout, expected_hash);
+            AssetId::from_entropy(entropy)
+        }))
+    }
+}
+
+// Check the ticker format (length limits configured by the operator aside)
+pub fn is_valid_ticker(ticker: &str) -> bool {
+    RE_TICKER.is_match(ticker)
+}
+
+// The characters tickers may safely use: ASCII letters (a single script, Latin) and digits, `.`
+// and `-`. This holds for all the tickers accepted by RE_TICKER, and is checked separately so that
+// widening the ticker rules can't let in mixed-script tickers.
+pub fn is_safe_ticker(ticker: &str) -> bool {
+    ticker
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+// Normalize the ticker into a form shared by the tickers that are easily confused with it:
+// case-insensitive, with the look-alike characters and sequences from Unicode's confusables
+// (like `0`/`O`, `1`/`I`/`l` and `rn`/`m`) mapped to a single form
+pub fn ticker_skeleton(ticker: &str) -> String {
+    // lowercased first so that the look-alikes map the same in either case, and the characters
+    // are mapped before the sequences so that these are matched regardless of case
+    let ticker: String = ticker
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| match c {
+            '0' | 'o' => 'o',
+            '1' | 'i' | 'l' | '|' => 'l',
+            c => c,
+        })
+        .collect();
+    ticker
+        .replace("rn", "m")
+        .replace("vv", "w")
+        .replace("cl", "d")
+}
+
+// The canonical contract json serialization, which gets hashed to produce the contract hash: no
+// whitespace, with object keys sorted lexicographically (by their UTF-8 bytes). The keys are sorted
+// explicitly rather than relying on the ordering of serde_json's map, which changes when its
+// `preserve_order` feature gets enabled.
+pub fn contract_json_canonical(contract: &Value) -> Result<String> {
+    let mut canonical = String::new();
+    write_canonical_json(contract, &mut canonical)?;
+    Ok(canonical)
+}
+
+fn write_canonical_json(value: &Value, out: &mut String) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key)?);
+                out.push(':');
+                write_canonical_json(value, out)?;
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical_json(item, out)?;
+            }
+            out.push(']');
+        }
+        // strings only have their quotes, backslashes and control characters escaped, with
+        // non-ascii characters kept as-is
+        scalar => out.push_str(&serde_json::to_string(scalar)?),
     }
+    Ok(())
 }
 
 pub fn contract_json_hash(contract: &Value) -> Result<ContractHash> {
-    // serde_json sorts keys lexicographically
-    let contract_str = serde_json::to_string(contract)?;
+    let contract_str = contract_json_canonical(contract)?;
 
     // use the ContractHash representation for correct (reverse) hex encoding,
     // but use a single SHA256 instead of the double hash assumed by ContractHash::hash()
@@ -183,8 +819,19 @@ pub fn contract_json_hash(contract: &Value) -> Result<ContractHash> {
     Ok(ContractHash::from_inner(hash.into_inner()))
 }
 
+// Some issuer tooling hashed the contract with a double SHA256 (like `ContractHash::hash()`)
+// rather than the single SHA256 expected by the registry. This is only used to detect that
+// mistake and report it with a specific error.
+pub fn contract_json_hash_sha256d(contract: &Value) -> Result<ContractHash> {
+    let contract_str = contract_json_canonical(contract)?;
+    Ok(ContractHash::hash(&contract_str.as_bytes()))
+}
+
+pub const DOUBLE_SHA256_ERR: &str =
+    "your tooling used a double SHA256 for the contract hash; the registry expects a single SHA256";
+
 #[cfg_attr(feature = "cli", derive(StructOpt))]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssetRequest {
     #[cfg_attr(
         feature = "cli",
@@ -205,6 +852,26 @@ pub struct AssetRequest {
         )
     )]
     pub contract: Value,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "domain-verification-method",
+            help = "The domain verification method to use (http or dns, defaults to http)"
+        )
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain_verification_method: Option<DomainVerificationMethod>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "contract-signature",
+            help = "Base64 signature by the issuer_pubkey over the canonical contract json (optional)"
+        )
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract_signature: Option<String>,
 }
 
 // Verify the asset id commits to the provided contract and prevout
@@ -213,7 +880,22 @@ fn verify_asset_commitment(asset: &Asset) -> Result<()> {
     let entropy = AssetId::generate_asset_entropy(asset.issuance_prevout, contract_hash);
     let asset_id = AssetId::from_entropy(entropy);
 
-    ensure!(asset.asset_id == asset_id, "invalid asset commitment");
+    if asset.asset_id != asset_id {
+        let sha256d_hash = contract_json_hash_sha256d(&asset.contract)?;
+        let sha256d_entropy = AssetId::generate_asset_entropy(asset.issuance_prevout, sha256d_hash);
+        ensure!(
+            asset.asset_id != AssetId::from_entropy(sha256d_entropy),
+            DOUBLE_SHA256_ERR
+        );
+        // the token id differs depending on whether the issuance amount is blinded
+        ensure!(
+            asset.asset_id != AssetId::reissuance_token_from_entropy(entropy, false)
+                && asset.asset_id != AssetId::reissuance_token_from_entropy(entropy, true),
+            "this is the reissuance token id, not the asset id (expected {})",
+            asset_id.to_hex()
+        );
+        bail!("invalid asset commitment");
+    }
 
     debug!(
         "verified asset commitment, asset id {} commits to prevout {:?} and contract hash {} ({:?})",
@@ -285,13 +967,203 @@ fn format_fields_sig_msg(asset_id: &AssetId, fields: &AssetFields) -> String {
 }
 */
 
-fn format_deletion_sig_msg(asset: &Asset) -> String {
-    format!("remove {} from registry", asset.asset_id)
+// Icons are fetched over https, or over plain http for .onion hosts (like the HTTP proof)
+fn verify_icon_url(icon_url: &str) -> Result<()> {
+    let url = Url::parse(icon_url)?;
+    let host = url.host_str().or_err("missing host")?;
+    ensure!(
+        url.scheme() == "https"
+            || (url.scheme() == "http"
+                && (host.ends_with(".onion") || cfg!(any(test, feature = "dev")))),
+        "expected an https url"
+    );
+    Ok(())
+}
+
+// The maximum size of the icon file
+const MAX_ICON_SIZE: u64 = 256 * 1024;
+
+// The time allowed for fetching the icon, including reading its contents
+const ICON_FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+// The icon is fetched from a host chosen by the issuer, so it must be a domain name that resolves to
+// public addresses only, to keep the registry from being used to reach its own internal network.
+// Onion hosts are resolved by the tor proxy and are exempt. The addresses are resolved again when
+// connecting, so this doesn't guard against a host that changes its records in between.
+fn ensure_public_icon_host(url: &Url) -> Result<()> {
+    let host = url.host_str().or_err("missing host")?;
+    ensure!(
+        host.trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_err(),
+        "the icon host must be a domain name"
+    );
+    if host.ends_with(".onion") {
+        return Ok(());
+    }
+
+    let port = url.port_or_known_default().or_err("missing port")?;
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .context(format!("failed resolving {}", host))?
+        .collect();
+    ensure!(!addrs.is_empty(), "no addresses for {}", host);
+    for addr in addrs {
+        ensure!(
+            is_public_ip(addr.ip()),
+            "the icon host {} resolves to the non-public address {}",
+            host,
+            addr.ip()
+        );
+    }
+    Ok(())
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // shared address space (carrier-grade nat)
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            // ipv4-mapped and ipv4-compatible addresses, except for ::1 and ::
+            Some(ipv4) if !ip.is_loopback() && !ip.is_unspecified() => {
+                is_public_ip(IpAddr::V4(ipv4))
+            }
+            _ => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local (fc00::/7) and link-local (fe80::/10)
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+// Fetch the asset's icon, if it has one, and check it against the `icon_sha256` commitment
+fn verify_asset_icon(asset: &Asset, config: &ValidationConfig) -> Result<()> {
+    let (icon_url, icon_sha256) = match (&asset.fields.icon_url, &asset.fields.icon_sha256) {
+        (Some(icon_url), Some(icon_sha256)) => (icon_url, icon_sha256),
+        _ => return Ok(()),
+    };
+
+    debug!(
+        "verifying icon for {}: GET {}",
+        asset.asset_id.to_hex(),
+        icon_url
+    );
+
+    let url = Url::parse(icon_url)?;
+    let host = url.host_str().req()?;
+    if !cfg!(any(test, feature = "dev")) {
+        // the mock icons in testing and development modes are served from localhost
+        ensure_public_icon_host(&url)?;
+    }
+    // redirects aren't followed, they could lead to a host that wasn't checked
+    let resp = http_client_builder(host, config)?
+        .redirect(redirect::Policy::none())
+        .timeout(ICON_FETCH_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()
+        .context(format!("failed fetching {}", icon_url))?;
+    ensure!(
+        resp.status().is_success(),
+        "unexpected status {} fetching {}",
+        resp.status(),
+        icon_url
+    );
+    ensure!(
+        resp.content_length()
+            .map_or(true, |len| len <= MAX_ICON_SIZE),
+        "icon exceeds the maximum size of {} bytes",
+        MAX_ICON_SIZE
+    );
+
+    // the content length may be missing or wrong, read one byte past the limit to detect it
+    let mut contents = vec![];
+    resp.take(MAX_ICON_SIZE + 1)
+        .read_to_end(&mut contents)
+        .context("failed reading icon")?;
+    ensure!(
+        contents.len() as u64 <= MAX_ICON_SIZE,
+        "icon exceeds the maximum size of {} bytes",
+        MAX_ICON_SIZE
+    );
+
+    ensure!(
+        sha256::Hash::hash(&contents).into_inner() == *icon_sha256,
+        "icon contents mismatch `icon_sha256`"
+    );
+    Ok(())
+}
+
+// Apply the operator's contract schema, if one is configured
+fn verify_contract_schema(contract: &Value, config: &ValidationConfig) -> Result<()> {
+    if let Some(schema_path) = &config.contract_schema {
+        schema::load_cached(schema_path)?.validate(contract)?;
+    }
+    Ok(())
+}
+
+// The contract signature is optional, except for self-declared assets without an entity
+fn verify_asset_contract_sig(asset: &Asset) -> Result<()> {
+    match &asset.contract_signature {
+        Some(signature) => verify_contract_sig(asset, signature),
+        None => {
+            ensure!(
+                !asset.entity().is_none(),
+                "contracts without an `entity` require a `contract_signature`"
+            );
+            Ok(())
+        }
+    }
+}
+
+fn verify_contract_sig(asset: &Asset, signature: &str) -> Result<()> {
+    let signature = base64::decode(signature).context("invalid base64 signature")?;
+    verify_bitcoin_msg(
+        &EC,
+        &asset.fields.issuer_pubkey,
+        &signature,
+        &contract_json_canonical(&asset.contract)?,
+    )
+}
+
+// The message signed by the issuer to authorize deleting the asset. Wallets should produce the
+// form that includes the contract hash, which names the contract being removed to the signer. The
+// asset id already commits to the contract hash, so both forms authorize the same deletion. The
+// legacy form is `remove <asset-id> from registry`.
+pub fn format_deletion_sig_msg(asset: &Asset, include_contract_hash: bool) -> Result<String> {
+    Ok(if include_contract_hash {
+        format!(
+            "remove {} with contract {} from registry",
+            asset.asset_id,
+            asset.contract_hash()?.to_hex()
+        )
+    } else {
+        format!("remove {} from registry", asset.asset_id)
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::errors::join_err;
+    use crate::fixtures::{sign_message, test_key, Fixture};
     use bitcoin_hashes::hex::ToHex;
     use std::path::PathBuf;
 
@@ -311,6 +1183,438 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test2_field_length_limits() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        asset.fields.validate(&ValidationConfig::default())?;
+
+        let config = ValidationConfig {
+            max_name_length: Some(8),
+            max_ticker_length: Some(3),
+            ..Default::default()
+        };
+        asset.fields.validate(&config)?;
+
+        let config = ValidationConfig {
+            max_name_length: Some(4),
+            ..Default::default()
+        };
+        assert!(asset.fields.validate(&config).is_err());
+
+        let config = ValidationConfig {
+            max_ticker_length: Some(2),
+            ..Default::default()
+        };
+        assert!(asset.fields.validate(&config).is_err());
+
+        // limits above the built-in maximum don't loosen the base rules
+        let mut fields = asset.fields.clone();
+        fields.ticker = Some("X".repeat(30));
+        let config = ValidationConfig {
+            max_ticker_length: Some(100),
+            ..Default::default()
+        };
+        assert!(fields.validate(&config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test2_contract_description() -> Result<()> {
+        let contract = json!({
+            "entity": { "domain": "test.dev" },
+            "issuer_pubkey": "037c7db0528e8b7b58e698ac104764f6852d74b5a7335bffcdad0ce799dd7742ec",
+            "name": "PPP coin",
+            "ticker": "PPP",
+            "version": 0,
+        });
+        let fields = AssetFields::from_contract(&contract)?;
+        fields.validate(&ValidationConfig::default())?;
+        assert_eq!(fields.description, None);
+        assert!(serde_json::to_value(&fields)?.get("description").is_none());
+        assert_eq!(
+            contract_json_hash(&contract)?,
+            ContractHash::from_hex(
+                "ac5a08996e50a12b38e2ad9e5e3ff2899db889b08422361d9fbed65d7b9c209b"
+            )?
+        );
+
+        let mut contract = contract;
+        contract["description"] = json!("A test asset, used for the registry tests");
+        let fields = AssetFields::from_contract(&contract)?;
+        fields.validate(&ValidationConfig::default())?;
+        assert_eq!(
+            serde_json::to_value(&fields)?["description"],
+            contract["description"]
+        );
+        // the description is committed to by the contract hash
+        assert_eq!(
+            contract_json_hash(&contract)?,
+            ContractHash::from_hex(
+                "b4b0b5b8ccfd3e3f846ed020fb51c2ccc55096fe617e153a2590ee1c9d0899e1"
+            )?
+        );
+
+        contract["description"] = json!("x".repeat(1001));
+        let fields = AssetFields::from_contract(&contract)?;
+        assert!(fields.validate(&ValidationConfig::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test2_icon() -> Result<()> {
+        crate::entity::tests::spawn_mock_verifier_server();
+        std::thread::sleep(std::time::Duration::from_millis(250));
+
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        asset.fields.icon_url = Some("http://127.0.0.1:58712/icon.png".into());
+        asset.fields.icon_sha256 =
+            Some(sha256::Hash::hash(crate::entity::tests::MOCK_ICON).into_inner());
+        asset.fields.validate(&ValidationConfig::default())?;
+        verify_asset_icon(&asset, &ValidationConfig::default())?;
+
+        // committed to as hex in the contract
+        let fields = serde_json::to_value(&asset.fields)?;
+        assert_eq!(
+            fields["icon_sha256"],
+            sha256::Hash::hash(crate::entity::tests::MOCK_ICON).to_hex()
+        );
+        assert_eq!(serde_json::from_value::<AssetFields>(fields)?, asset.fields);
+
+        let mut mismatch = asset.clone();
+        mismatch.fields.icon_sha256 = Some([0; 32]);
+        let err = verify_asset_icon(&mismatch, &ValidationConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("mismatch"), "{}", err);
+
+        let mut oversized = asset.clone();
+        oversized.fields.icon_url = Some("http://127.0.0.1:58712/big-icon.png".into());
+        let err = verify_asset_icon(&oversized, &ValidationConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("maximum size"), "{}", err);
+
+        let mut redirected = asset.clone();
+        redirected.fields.icon_url = Some("http://127.0.0.1:58712/redirect-icon.png".into());
+        let err = verify_asset_icon(&redirected, &ValidationConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("unexpected status 303"), "{}", err);
+
+        let mut missing_hash = asset.clone();
+        missing_hash.fields.icon_sha256 = None;
+        assert!(missing_hash
+            .fields
+            .validate(&ValidationConfig::default())
+            .is_err());
+
+        assert!(verify_icon_url("https://example.com/icon.png").is_ok());
+        assert!(verify_icon_url("ftp://example.com/icon.png").is_err());
+        assert!(verify_icon_url("icon.png").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test2_icon_public_host() {
+        for url in &[
+            "https://127.0.0.1/icon.png",
+            "https://[::1]/icon.png",
+            "https://93.184.216.34/icon.png",
+            // resolves to a loopback address
+            "https://localhost/icon.png",
+        ] {
+            assert!(
+                ensure_public_icon_host(&Url::parse(url).unwrap()).is_err(),
+                "{}",
+                url
+            );
+        }
+        ensure_public_icon_host(&Url::parse("http://example.onion/icon.png").unwrap()).unwrap();
+
+        for ip in &[
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::",
+            "::ffff:127.0.0.1",
+            "fd00::1",
+            "fe80::1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in &[
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test2_verification_step_codes() -> Result<()> {
+        for step in VerificationStep::ALL.iter() {
+            assert_eq!(serde_json::to_value(step)?, step.code());
+        }
+
+        let err: Error = format_err!("no matching TXT record found");
+        assert_eq!(VerificationStep::of_error(&err), None);
+        let err: Error = Err::<(), _>(err)
+            .context("failed verifying dns domain proof")
+            .context(VerificationStep::LinkedEntity.context())
+            .map_err(Error::from)
+            .unwrap_err();
+        assert_eq!(
+            VerificationStep::of_error(&err),
+            Some(VerificationStep::LinkedEntity)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test2_ticker_skeleton() {
+        assert_eq!(ticker_skeleton("USDT"), ticker_skeleton("usdt"));
+        assert_eq!(ticker_skeleton("USDI"), ticker_skeleton("usdi"));
+        assert_eq!(ticker_skeleton("USDI"), ticker_skeleton("USDL"));
+        assert_eq!(ticker_skeleton("B0B"), ticker_skeleton("BOB"));
+        assert_eq!(ticker_skeleton("1NCH"), ticker_skeleton("lNCH"));
+        assert_eq!(ticker_skeleton("INCH"), ticker_skeleton("lnch"));
+        assert_eq!(ticker_skeleton("burn"), ticker_skeleton("bum"));
+        assert_eq!(ticker_skeleton("BURN"), ticker_skeleton("BUM"));
+        assert_eq!(ticker_skeleton("VVBTC"), ticker_skeleton("WBTC"));
+        assert_eq!(ticker_skeleton("cI"), ticker_skeleton("d"));
+        assert_ne!(ticker_skeleton("USDT"), ticker_skeleton("USDC"));
+
+        assert!(is_safe_ticker("L-BTC.v2"));
+        assert!(!is_safe_ticker("USD\u{0422}")); // with a cyrillic T
+        assert!(!is_safe_ticker("BTC_"));
+    }
+
+    #[test]
+    fn test2_missing_contract_fields() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        AssetFields::from_contract(&asset.contract)?;
+
+        let mut contract = asset.contract.clone();
+        contract.as_object_mut().req()?.remove("name");
+        let err = AssetFields::from_contract(&contract).unwrap_err();
+        assert_eq!(err.to_string(), "contract missing required field `name`");
+
+        contract["name"] = Value::Null;
+        assert!(AssetFields::from_contract(&contract).is_err());
+
+        assert!(AssetFields::from_contract(&json!("foo")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test3_double_sha256_contract_hash() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        let config = ValidationConfig::default();
+
+        let hash = contract_json_hash(&asset.contract)?;
+        Asset::validate_contract(&asset.contract, &hash, None, &config)?;
+
+        let sha256d_hash = contract_json_hash_sha256d(&asset.contract)?;
+        let err = Asset::validate_contract(&asset.contract, &sha256d_hash, None, &config)
+            .expect_err("double SHA256 hash should be rejected");
+        assert!(err.to_string().contains("double SHA256"));
+
+        let err = Asset::validate_contract(
+            &asset.contract,
+            &ContractHash::from_inner([0; 32]),
+            None,
+            &config,
+        )
+        .expect_err("invalid hash should be rejected");
+        assert!(err
+            .to_string()
+            .contains(&serde_json::to_string(&asset.contract)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test3_contract_hash_key_order() -> Result<()> {
+        // contracts with the keys in the order the issuer provided them, and their hashes
+        let vectors = [
+            (
+                r#"{"entity":{"domain":"test.dev"},"issuer_pubkey":"037c7db0528e8b7b58e698ac104764f6852d74b5a7335bffcdad0ce799dd7742ec","name":"PPP coin","ticker":"PPP","version":0}"#,
+                "ac5a08996e50a12b38e2ad9e5e3ff2899db889b08422361d9fbed65d7b9c209b",
+            ),
+            (
+                r#"{"version":0,"ticker":"USDt","precision":8,"name":"Tether USD","issuer_pubkey":"0337cceec0beea0232ebe14cba0197a9fbd45fcf2ec946749de920e71434c2b904","entity":{"domain":"tether.to"}}"#,
+                "3c7f0a53c2ff5b99590620d7f6604a7a3a7bfbaaa6aa61f7bfc7833ca03cde82",
+            ),
+            // non-ascii characters are hashed as utf-8, not \u escaped
+            (
+                r#"{"ticker":"EURx","name":"Société Générale € token","precision":2,"version":0,"issuer_pubkey":"02a2f7a7a8e7c0c6ba7ac0c44e4bc0f3e4e9d6ed0c2c3f27ce3e0e5b5b9c2f4d1e","entity":{"domain":"example.com"}}"#,
+                "1644f46ea6c2194a1df8c8bbfdc475814f31115281ed398eea219b1b1ad13293",
+            ),
+            // only quotes, backslashes and control characters are escaped. uppercase keys sort
+            // before lowercase ones.
+            (
+                r#"{"name":"Quote \" backslash \\ tab \t emoji \ud83d\ude80","precision":0,"entity":{"domain":"sub.example.org"},"issuer_pubkey":"03b8a4c8fa6d4a5c9b1e8b8b9c2f6b5a5c3e2d1f0e9d8c7b6a5f4e3d2c1b0a9f8e","version":0,"Extra":"x","ticker":"QTE"}"#,
+                "518c1c004fdfd54884e4ee63ea81cad0a534abb1fc231a22841d243da5130499",
+            ),
+            // keys are sorted by code point, so "\u{ff61}" comes before "\u{1f680}". javascript's
+            // default sort() compares utf-16 code units and would order them the other way around.
+            (
+                r#"{"version":0,"name":"Keys","ticker":"KEY","issuer_pubkey":"037c7db0528e8b7b58e698ac104764f6852d74b5a7335bffcdad0ce799dd7742ec","entity":{"domain":"test.dev"},"\uff61":"halfwidth","\ud83d\ude80":"rocket"}"#,
+                "9af0cf45d64f2b195a4df189c8fc0e521477c7d6e50bc3246e053e86fcca079f",
+            ),
+        ];
+
+        for (contract, expected_hash) in vectors.iter() {
+            let contract: Value = serde_json::from_str(contract)?;
+            assert_eq!(
+                contract_json_hash(&contract)?,
+                ContractHash::from_hex(expected_hash)?,
+                "contract hash mismatch for {}",
+                contract_json_canonical(&contract)?
+            );
+        }
+
+        // the canonical form has no whitespace and sorted keys at every level
+        let contract = json!({ "b": [{ "d": 1, "c": "é" }], "a": { "f": null, "e": true } });
+        assert_eq!(
+            contract_json_canonical(&contract)?,
+            r#"{"a":{"e":true,"f":null},"b":[{"c":"é","d":1}]}"#
+        );
+        let contract = json!({ "\u{1f680}": 1, "\u{ff61}": 2 });
+        assert_eq!(
+            contract_json_canonical(&contract)?,
+            "{\"\u{ff61}\":2,\"\u{1f680}\":1}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test5_contract_signature() -> Result<()> {
+        let mut asset = Fixture::named("Signed coin")?.asset;
+        let config = ValidationConfig::default();
+        let sign = |msg: &str| base64::encode(&sign_message(msg));
+
+        asset.contract_signature = Some(sign(&contract_json_canonical(&asset.contract)?));
+        asset.verify_offline(&config)?;
+
+        asset.contract_signature = Some(sign("something else"));
+        assert!(asset.verify_offline(&config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test5_entityless_asset() -> Result<()> {
+        let contract = json!({
+            "name": "Self-declared coin",
+            "version": 1
+        });
+        let mut asset = Fixture::new(contract, &test_key(), b"entityless")?.asset;
+        assert_eq!(asset.entity(), &AssetEntity::None);
+
+        let config = ValidationConfig {
+            allow_entityless_assets: true,
+            ..Default::default()
+        };
+
+        // rejected unless allowed by the operator, and without a contract signature
+        let err = asset
+            .verify_offline(&ValidationConfig::default())
+            .unwrap_err();
+        assert!(join_err(&err).contains("not accepted by this registry"));
+        let err = asset.verify_offline(&config).unwrap_err();
+        assert!(join_err(&err).contains("require a `contract_signature`"));
+
+        let signature = sign_message(&contract_json_canonical(&asset.contract)?);
+        asset.contract_signature = Some(base64::encode(&signature));
+        asset.verify_offline(&config)?;
+
+        // the stricter policy doesn't allow claiming a ticker
+        let mut fields = asset.fields.clone();
+        fields.ticker = Some("SELF".to_string());
+        assert!(fields.validate(&config).is_err());
+
+        // the entity is only optional in version 1 contracts, which can't have one
+        let mut contract = asset.contract.clone();
+        contract["version"] = json!(0);
+        let err = AssetFields::from_contract(&contract).unwrap_err();
+        assert_eq!(err.to_string(), "contract missing required field `entity`");
+
+        contract["version"] = json!(1);
+        contract["entity"] = json!({ "domain": "test.dev" });
+        assert!(AssetFields::from_contract(&contract)?
+            .validate(&config)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test5_deletion_signature() -> Result<()> {
+        let asset = Fixture::named("Deleted coin")?.asset;
+
+        let msg = format_deletion_sig_msg(&asset, true)?;
+        assert_eq!(
+            msg,
+            format!(
+                "remove {} with contract {} from registry",
+                asset.asset_id,
+                asset.contract_hash()?.to_hex()
+            )
+        );
+        asset.verify_deletion(&sign_message(&msg))?;
+
+        // the legacy form is still accepted
+        let legacy_msg = format!("remove {} from registry", asset.asset_id);
+        assert_eq!(format_deletion_sig_msg(&asset, false)?, legacy_msg);
+        asset.verify_deletion(&sign_message(&legacy_msg))?;
+
+        // signatures bound to another contract are rejected
+        let other_msg = format!(
+            "remove {} with contract {} from registry",
+            asset.asset_id,
+            "00".repeat(32)
+        );
+        assert!(asset.verify_deletion(&sign_message(&other_msg)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test3_contract_schema() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        let hash = contract_json_hash(&asset.contract)?;
+        let config = ValidationConfig {
+            contract_schema: Some(PathBuf::from("test/contract-schema.json")),
+            ..Default::default()
+        };
+
+        // the schema requires a registry-specific field, missing from the contract
+        let err = Asset::validate_contract(&asset.contract, &hash, None, &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "contract schema rule `#/required` failed at `#`: missing required field `kyc_issuer`"
+        );
+        let err = asset.verify_offline(&config).unwrap_err();
+        assert!(join_err(&err).starts_with("invalid contract fields: contract schema rule"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test4_reissuance_token_id() -> Result<()> {
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        verify_asset_commitment(&asset)?;
+
+        let entropy =
+            AssetId::generate_asset_entropy(asset.issuance_prevout, asset.contract_hash()?);
+        asset.asset_id = AssetId::reissuance_token_from_entropy(entropy, false);
+
+        let err = verify_asset_commitment(&asset).expect_err("token id should be rejected");
+        assert!(err.to_string().contains("reissuance token id"));
+
+        Ok(())
+    }
+
     /*
     #[test]
     fn test2_verify_asset_sig() -> Result<()> {
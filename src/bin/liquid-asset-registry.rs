@@ -7,15 +7,34 @@ extern crate base64;
 #[macro_use]
 extern crate failure;
 
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use reqwest::{blocking::Client, StatusCode};
-use serde_json::Value;
+use serde_json::{json, Value};
 use structopt::StructOpt;
 
-use bitcoin_hashes::hex::ToHex;
+use bitcoin::Txid;
+use bitcoin_hashes::hex::{FromHex, ToHex};
+use elements::{AssetId, OutPoint};
 
-use asset_registry::asset::{contract_json_hash, Asset, AssetRequest};
-use asset_registry::chain::ChainQuery;
-use asset_registry::errors::{join_err, Result, ResultExt};
+use asset_registry::asset::{
+    contract_json_hash, Asset, AssetRequest, ValidationConfig, VerificationStep,
+};
+use asset_registry::chain::{verify_asset_issuance_tx, ChainQuery};
+use asset_registry::entity::{
+    dns_proof_record_name, domain_proofs, expected_proof_body, http_proof_path, http_proof_scheme,
+    verify_asset_link, AssetEntity,
+};
+use asset_registry::errors::{join_err, OptionExt, Result, ResultExt};
+use asset_registry::registry::{QuarantineKind, Registry, RegistryConfig, DIR_PARTITION_LEN};
+use asset_registry::util::verify_domain_name;
 
 #[derive(StructOpt, Debug)]
 struct Cli {
@@ -42,6 +61,29 @@ enum Command {
         )]
         esplora_url: String,
 
+        #[structopt(
+            short,
+            long,
+            default_value = "1",
+            help = "Number of assets to verify concurrently"
+        )]
+        jobs: usize,
+
+        #[structopt(
+            long = "esplora-max-concurrency",
+            help = "Maximum number of concurrent requests to the esplora api (unbounded if unset)"
+        )]
+        esplora_max_concurrency: Option<usize>,
+
+        #[structopt(
+            long,
+            help = "Print the verified assets as JSON, with the details derived during verification, instead of pass/fail"
+        )]
+        emit: bool,
+
+        #[structopt(flatten)]
+        validation: ValidationConfig,
+
         jsons: Vec<String>,
     },
 
@@ -58,6 +100,98 @@ enum Command {
         asset_req: AssetRequest,
     },
 
+    #[structopt(
+        name = "audit",
+        about = "Re-verify all the assets served by a registry, reporting the ones that fail"
+    )]
+    Audit {
+        #[structopt(
+            short,
+            long = "registry-url",
+            default_value = "https://assets.blockstream.info"
+        )]
+        registry_url: String,
+
+        #[structopt(flatten)]
+        validation: ValidationConfig,
+    },
+
+    #[structopt(
+        name = "validate-dir",
+        about = "Validate all the asset files in a directory offline (e.g. as a CI check), reporting the ones that fail"
+    )]
+    ValidateDir {
+        #[structopt(help = "The directory to scan for *.json asset files")]
+        dir: PathBuf,
+
+        #[structopt(
+            long = "esplora-url",
+            help = "Also verify the issuance transactions on-chain, using this esplora api url"
+        )]
+        esplora_url: Option<String>,
+
+        #[structopt(
+            long = "chain-snapshot",
+            conflicts_with = "esplora-url",
+            help = "Also verify the issuance transactions on-chain, using a snapshot directory written by chain-snapshot"
+        )]
+        chain_snapshot: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        validation: ValidationConfig,
+    },
+
+    #[structopt(
+        name = "chain-snapshot",
+        about = "Pre-fetch the chain state needed for verifying the asset files in a directory, for validate-dir --chain-snapshot"
+    )]
+    ChainSnapshot {
+        #[structopt(help = "The directory to scan for *.json asset files")]
+        dir: PathBuf,
+
+        #[structopt(
+            long = "output-dir",
+            help = "The snapshot directory to write the esplora responses to"
+        )]
+        output_dir: PathBuf,
+
+        #[structopt(
+            short,
+            long = "esplora-url",
+            help = "url for querying chain state using the esplora api",
+            default_value = "https://blockstream.info/liquid/api/"
+        )]
+        esplora_url: String,
+
+        #[structopt(flatten)]
+        validation: ValidationConfig,
+    },
+
+    #[structopt(
+        name = "verify-domain",
+        about = "Verify the domain ownership proofs of all the assets linked to a domain"
+    )]
+    VerifyDomain {
+        #[structopt(long, help = "The domain name to verify")]
+        domain: String,
+
+        #[structopt(
+            short,
+            long = "registry-url",
+            default_value = "https://assets.blockstream.info"
+        )]
+        registry_url: String,
+
+        #[structopt(
+            long = "db-path",
+            help = "Read the assets from a local database directory instead of the registry"
+        )]
+        db_path: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        validation: ValidationConfig,
+    },
+
     #[structopt(
         name = "contract-json",
         about = "print contract json in canonical serialization (sorted)"
@@ -67,31 +201,219 @@ enum Command {
         #[structopt(short, long, help = "print contract hash (sha256)")]
         hash: bool,
     },
+
+    #[structopt(
+        name = "contract-inspect",
+        about = "print the canonical contract json, its hash and the asset id derived from it"
+    )]
+    ContractInspect {
+        json: String,
+
+        #[structopt(
+            long,
+            help = "The issuance prevout (<txid>:<vout>), for deriving the asset id",
+            parse(try_from_str = parse_outpoint)
+        )]
+        prevout: Option<OutPoint>,
+    },
+
+    #[structopt(
+        name = "proof-file",
+        about = "print the domain ownership proof to publish for an asset"
+    )]
+    ProofFile {
+        #[structopt(
+            long = "asset-id",
+            help = "The asset-id",
+            parse(try_from_str = AssetId::from_hex)
+        )]
+        asset_id: AssetId,
+
+        #[structopt(long, help = "The domain name linked to the asset")]
+        domain: String,
+
+        #[structopt(
+            short,
+            long,
+            help = "write the proof file to this path, ready to be hosted as-is"
+        )]
+        output: Option<String>,
+    },
+
+    #[structopt(
+        name = "domain-proofs",
+        about = "print the domain ownership proofs to publish for multiple assets of a domain at once"
+    )]
+    DomainProofs {
+        #[structopt(long, help = "The domain name linked to the assets")]
+        domain: String,
+
+        #[structopt(
+            long = "output-dir",
+            help = "write the proof files under <output-dir>/.well-known/, ready to be hosted as-is"
+        )]
+        output_dir: Option<PathBuf>,
+
+        #[structopt(
+            help = "The asset ids",
+            required = true,
+            parse(try_from_str = AssetId::from_hex)
+        )]
+        asset_ids: Vec<AssetId>,
+    },
+
+    #[structopt(
+        name = "quarantine",
+        about = "Hide an asset pending review, keeping its namespace reserved"
+    )]
+    Quarantine {
+        #[structopt(long = "db-path", help = "Path to database directory")]
+        db_path: PathBuf,
+
+        #[structopt(flatten)]
+        registry: RegistryConfig,
+
+        #[structopt(
+            long = "asset-id",
+            help = "The asset-id",
+            parse(try_from_str = AssetId::from_hex)
+        )]
+        asset_id: AssetId,
+
+        #[structopt(
+            long = "legal-hold",
+            help = "restrict the asset for legal reasons (served as 451 Unavailable For Legal Reasons)"
+        )]
+        legal_hold: bool,
+
+        #[structopt(long, help = "restore the asset from quarantine")]
+        release: bool,
+    },
+
+    #[structopt(
+        name = "test-hook",
+        about = "Run the hook command for a sample asset, printing its output and exit status"
+    )]
+    TestHook {
+        #[structopt(
+            long = "db-path",
+            help = "Path to database directory (the hook's working directory)"
+        )]
+        db_path: PathBuf,
+
+        #[structopt(long = "hook-cmd", help = "Hook command to test")]
+        hook_cmd: String,
+
+        #[structopt(
+            long = "asset-file",
+            help = "Path to the asset json file to pass to the hook (uses a bundled sample asset if unset)"
+        )]
+        asset_file: Option<PathBuf>,
+
+        #[structopt(
+            long = "update-type",
+            default_value = "add",
+            possible_values = &["add", "delete", "quarantine", "unquarantine"],
+            help = "The update type to pass to the hook"
+        )]
+        update_type: String,
+    },
 }
 
+// Verify the assets using up to `jobs` threads. The results are sent to the returned receiver as
+// they complete, tagged with the index of their asset. Verified assets are returned with the
+// verification outcome recorded on them, like registered assets.
+fn verify_assets(
+    assets: Vec<Asset>,
+    chain: Option<ChainQuery>,
+    validation: ValidationConfig,
+    jobs: usize,
+) -> mpsc::Receiver<(usize, (AssetId, Result<Asset>))> {
+    let shared = Arc::new((assets, chain, validation));
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+
+    for _ in 0..jobs.max(1).min(shared.0.len()) {
+        let (shared, next, tx) = (Arc::clone(&shared), Arc::clone(&next), tx.clone());
+        thread::spawn(move || {
+            let (assets, chain, validation) = &*shared;
+            loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let asset = match assets.get(index) {
+                    Some(asset) => asset,
+                    None => break,
+                };
+                debug!("verifying asset: {:?}", asset);
+                let result = asset
+                    .verify(chain.as_ref(), validation)
+                    .map(|verification| {
+                        let mut asset = asset.clone();
+                        asset.record_verification(verification, validation);
+                        asset
+                    });
+                tx.send((index, (asset.asset_id, result))).unwrap();
+            }
+        });
+    }
+    rx
+}
+
+// A sample asset used for testing hook commands
+const SAMPLE_ASSET: &str = include_str!("../../test/asset-b1405e.json");
+
 fn main() -> Result<()> {
     let args = Cli::from_args();
     stderrlog::new().verbosity(args.verbose + 2).init().unwrap();
     debug!("cli args: {:?}", args);
 
     match args.cmd {
-        Command::VerifyAsset { esplora_url, jsons } => {
-            let chain = Some(ChainQuery::new(esplora_url));
-            let mut failed = false;
+        Command::VerifyAsset {
+            esplora_url,
+            jobs,
+            esplora_max_concurrency,
+            emit,
+            validation,
+            jsons,
+        } => {
+            let mut chain = ChainQuery::new(esplora_url);
+            if let Some(max_concurrency) = esplora_max_concurrency {
+                chain = chain.with_max_concurrency(max_concurrency);
+            }
+            let assets = jsons
+                .iter()
+                .map(|json| Ok(serde_json::from_str(json).context("invalid asset json")?))
+                .collect::<Result<Vec<Asset>>>()?;
 
-            for json in jsons {
-                let asset: Asset = serde_json::from_str(&json).context("invalid asset json")?;
-                debug!("verifying asset: {:?}", asset);
+            // the results are reported in the original order as they become available
+            let total = assets.len();
+            let mut pending = HashMap::new();
+            let mut next = 0;
+            let mut failed = false;
 
-                match asset.verify(chain.as_ref()) {
-                    Ok(()) => println!("{},true", asset.id().to_hex()),
-                    Err(err) => {
-                        warn!("asset verification failed: {}", join_err(&err));
-                        println!("{},false", asset.id().to_hex());
-                        failed = true;
+            for (index, result) in verify_assets(assets, Some(chain), validation, jobs) {
+                pending.insert(index, result);
+                while let Some((asset_id, result)) = pending.remove(&next) {
+                    match result {
+                        Ok(asset) if emit => println!("{}", serde_json::to_string(&asset)?),
+                        Ok(_) => println!("{},true", asset_id.to_hex()),
+                        Err(err) => {
+                            warn!("asset verification failed: {}", join_err(&err));
+                            if !emit {
+                                println!("{},false", asset_id.to_hex());
+                            }
+                            failed = true;
+                        }
                     }
+                    next += 1;
                 }
             }
+            // the channel also closes when a worker panics, leaving the remaining results missing
+            ensure!(
+                next == total,
+                "verification stopped after {} of {} assets",
+                next,
+                total
+            );
 
             if failed {
                 std::process::exit(1);
@@ -117,6 +439,142 @@ fn main() -> Result<()> {
             info!("registered succesfully: {:#?}", asset);
         }
 
+        Command::Audit {
+            registry_url,
+            validation,
+        } => {
+            let export = fetch_registry_export(&registry_url)?;
+
+            let (mut total, mut failed) = (0, 0);
+            for line in export.lines() {
+                total += 1;
+
+                let asset: Asset = match serde_json::from_str(line) {
+                    Ok(asset) => asset,
+                    Err(err) => {
+                        println!("invalid asset json: {} ({})", line, err);
+                        failed += 1;
+                        continue;
+                    }
+                };
+
+                if let Err(err) = asset.verify_offline(&validation) {
+                    println!("{},{}", asset.id().to_hex(), join_err(&err));
+                    failed += 1;
+                }
+            }
+
+            info!("audited {} assets, {} failed verification", total, failed);
+
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Command::ValidateDir {
+            dir,
+            esplora_url,
+            chain_snapshot,
+            validation,
+        } => {
+            let chain = esplora_url
+                .map(ChainQuery::new)
+                .or_else(|| chain_snapshot.map(ChainQuery::from_snapshot));
+
+            let mut files = Vec::new();
+            find_json_files(&dir, &mut files)?;
+
+            let mut failed = 0;
+            for path in &files {
+                if let Err(err) = validate_asset_file(path, chain.as_ref(), &validation) {
+                    println!("{},{}", path.display(), join_err(&err));
+                    failed += 1;
+                }
+            }
+
+            info!("validated {} asset files, {} failed", files.len(), failed);
+
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Command::ChainSnapshot {
+            dir,
+            output_dir,
+            esplora_url,
+            validation,
+        } => {
+            std::fs::create_dir_all(&output_dir)?;
+            let chain = ChainQuery::new(esplora_url).with_recording(output_dir);
+
+            let mut files = Vec::new();
+            find_json_files(&dir, &mut files)?;
+
+            // the responses are recorded as a side effect of verifying the issuances
+            let snapshot_asset = |path: &Path| -> Result<()> {
+                let asset = Asset::load(path.to_path_buf()).context("invalid asset json")?;
+                verify_asset_issuance_tx(&chain, &asset, &validation)
+                    .context(VerificationStep::OnChainIssuance.context())?;
+                Ok(())
+            };
+
+            let mut failed = 0;
+            for path in &files {
+                if let Err(err) = snapshot_asset(path) {
+                    println!("{},{}", path.display(), join_err(&err));
+                    failed += 1;
+                }
+            }
+
+            info!("snapshotted {} asset files, {} failed", files.len(), failed);
+
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Command::VerifyDomain {
+            domain,
+            registry_url,
+            db_path,
+            validation,
+        } => {
+            verify_domain_name(&domain).context("invalid domain name")?;
+
+            let assets = match db_path {
+                Some(db_path) => load_local_assets(&db_path)?,
+                None => fetch_registry_assets(&registry_url)?,
+            };
+            let entity = AssetEntity::DomainName(domain.clone());
+            let assets: Vec<Asset> = assets
+                .into_iter()
+                .filter(|asset| asset.entity() == &entity)
+                .collect();
+
+            info!("verifying {} assets linked to {}", assets.len(), domain);
+
+            let mut failed = false;
+            for asset in assets {
+                match verify_asset_link(&asset, &validation) {
+                    Ok(_) => println!("{},true", asset.id().to_hex()),
+                    Err(err) => {
+                        warn!(
+                            "domain verification for {} failed: {}",
+                            asset.id().to_hex(),
+                            join_err(&err)
+                        );
+                        println!("{},false", asset.id().to_hex());
+                        failed = true;
+                    }
+                }
+            }
+
+            if failed {
+                std::process::exit(1);
+            }
+        }
+
         Command::ContractJson { json, hash } => {
             let contract: Value = serde_json::from_str(&json).context("invalid contract json")?;
 
@@ -129,7 +587,356 @@ fn main() -> Result<()> {
                 println!("{}", contract_str);
             }
         }
+
+        Command::ContractInspect { json, prevout } => {
+            let contract: Value = serde_json::from_str(&json).context("invalid contract json")?;
+            let contract_hash = contract_json_hash(&contract)?;
+
+            let asset_id = prevout.map(|prevout| {
+                AssetId::from_entropy(AssetId::generate_asset_entropy(prevout, contract_hash))
+            });
+
+            let output = json!({
+                // deserializing and re-serializing gets us canonical encoding, with json keys sorted lexicographically
+                "canonical_contract": serde_json::to_string(&contract)?,
+                "contract_hash": contract_hash.to_hex(),
+                "prevout": prevout.map(|prevout| format!("{}:{}", prevout.txid, prevout.vout)),
+                "asset_id": asset_id.map(|asset_id| asset_id.to_hex()),
+            });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+
+        Command::ProofFile {
+            asset_id,
+            domain,
+            output,
+        } => {
+            verify_domain_name(&domain).context("invalid domain name")?;
+
+            let body = expected_proof_body(&domain, &asset_id);
+
+            if let Some(output) = output {
+                std::fs::write(&output, &body).context("failed writing proof file")?;
+                info!("proof file written to {}", output);
+            }
+
+            println!(
+                "# Host the following contents at {}://{}{} (a trailing newline is allowed)",
+                http_proof_scheme(&domain),
+                domain,
+                http_proof_path(&asset_id)
+            );
+            println!("{}", body);
+            println!("# Or publish it as a DNS TXT record:");
+            println!("{} TXT \"{}\"", dns_proof_record_name(&domain), body);
+        }
+
+        Command::DomainProofs {
+            domain,
+            output_dir,
+            asset_ids,
+        } => {
+            let proofs = domain_proofs(&domain, &asset_ids)?;
+
+            if let Some(output_dir) = output_dir {
+                for proof in &proofs {
+                    let path = output_dir.join(proof.http_path.trim_start_matches('/'));
+                    std::fs::create_dir_all(path.parent().unwrap())?;
+                    std::fs::write(&path, &proof.body).context("failed writing proof file")?;
+                }
+                info!(
+                    "{} proof files written to {}",
+                    proofs.len(),
+                    output_dir.display()
+                );
+            }
+
+            println!(
+                "# Host the following contents at {}://{} (a trailing newline is allowed)",
+                http_proof_scheme(&domain),
+                domain
+            );
+            for proof in &proofs {
+                println!("{}: {}", proof.http_path, proof.body);
+            }
+            println!("# Or publish them as DNS TXT records (one record per asset):");
+            for proof in &proofs {
+                println!("{} TXT \"{}\"", proof.dns_record_name, proof.body);
+            }
+        }
+
+        Command::Quarantine {
+            db_path,
+            registry,
+            asset_id,
+            legal_hold,
+            release,
+        } => {
+            // chain access is not needed for moving assets in and out of quarantine
+            let registry = Registry::new(&db_path, ChainQuery::new(String::new()), None, registry);
+
+            if release {
+                registry.unquarantine(&asset_id)?;
+                info!("asset {} restored from quarantine", asset_id.to_hex());
+            } else {
+                let kind = if legal_hold {
+                    QuarantineKind::LegalHold
+                } else {
+                    QuarantineKind::Review
+                };
+                registry.quarantine(&asset_id, kind)?;
+                info!("asset {} quarantined ({:?})", asset_id.to_hex(), kind);
+            }
+        }
+
+        Command::TestHook {
+            db_path,
+            hook_cmd,
+            asset_file,
+            update_type,
+        } => {
+            let (asset_file, is_sample) = match asset_file {
+                Some(asset_file) => (asset_file, false),
+                None => (write_sample_asset()?, true),
+            };
+            let asset = Asset::load(asset_file.clone())?;
+            let asset_file = asset_file
+                .canonicalize()
+                .context("failed resolving asset file path")?;
+
+            // chain access is not needed for running the hook
+            let registry = Registry::new(
+                &db_path,
+                ChainQuery::new(String::new()),
+                Some(hook_cmd),
+                RegistryConfig::default(),
+            );
+            let output = registry.run_hook(asset.id(), &asset_file, &update_type, None);
+            if is_sample {
+                std::fs::remove_file(&asset_file).ok();
+            }
+            let output = output?.req()?;
+
+            println!("## exit status: {}", output.status);
+            println!("## stdout:\n{}", String::from_utf8_lossy(&output.stdout));
+            println!("## stderr:\n{}", String::from_utf8_lossy(&output.stderr));
+
+            if !output.status.success() {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Write the bundled sample asset to a new temporary file. The file is created exclusively under a
+// unique name, so that a file (or symlink) planted at a predictable path can't be used instead.
+fn write_sample_asset() -> Result<PathBuf> {
+    for attempt in 0..100 {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.subsec_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "liquid-asset-registry-sample-{}-{}-{}.json",
+            std::process::id(),
+            nanos,
+            attempt
+        ));
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => Err(err).context("failed creating sample asset file")?,
+        };
+        file.write_all(SAMPLE_ASSET.as_bytes())
+            .context("failed writing sample asset")?;
+        return Ok(path);
+    }
+    bail!("failed creating sample asset file")
+}
+
+// Fetch the NDJSON export with all the assets served by a registry
+fn fetch_registry_export(registry_url: &str) -> Result<String> {
+    let export_url = format!("{}/export.ndjson", registry_url.trim_end_matches('/'));
+    info!("fetching assets from {}", export_url);
+
+    Ok(Client::new()
+        .get(&export_url)
+        .send()
+        .context("failed fetching assets from registry")?
+        .error_for_status()
+        .context("failed fetching assets from registry")?
+        .text()?)
+}
+
+fn fetch_registry_assets(registry_url: &str) -> Result<Vec<Asset>> {
+    fetch_registry_export(registry_url)?
+        .lines()
+        .map(|line| Ok(serde_json::from_str(line).context("invalid asset json")?))
+        .collect()
+}
+
+fn load_local_assets(db_path: &Path) -> Result<Vec<Asset>> {
+    let registry = Registry::new(
+        db_path,
+        ChainQuery::new(String::new()),
+        None,
+        RegistryConfig::default(),
+    );
+    registry.iter()?.collect()
+}
+
+// Recursively collect the *.json files under `dir`, sorted. Directories starting with `_` (the
+// registry's namespace map, quarantine and queue directories) and hidden ones (like .git) are skipped.
+fn find_json_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .context(format!("failed reading {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with('_') && !name.starts_with('.') {
+                find_json_files(&path, files)?;
+            }
+        } else if path.extension().map_or(false, |ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Validate an asset file offline (the issuance commitment and fields), and its issuance
+// transaction when a chain source is available
+fn validate_asset_file(
+    path: &Path,
+    chain: Option<&ChainQuery>,
+    config: &ValidationConfig,
+) -> Result<()> {
+    let asset = Asset::load(path.to_path_buf()).context("invalid asset json")?;
+
+    let asset_id = asset.id().to_hex();
+    let file_stem = path.file_stem().map(|stem| stem.to_string_lossy());
+    ensure!(
+        file_stem.as_deref() == Some(asset_id.as_str()),
+        "file name doesn't match the asset id {}",
+        asset_id
+    );
+
+    // files under another asset's partition sub-directory are invisible to the registry
+    let partition = path
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .map(|name| name.to_string_lossy());
+    if let Some(partition) = partition {
+        ensure!(
+            partition.len() != DIR_PARTITION_LEN
+                || !partition.chars().all(|c| c.is_ascii_hexdigit())
+                || asset_id.starts_with(partition.as_ref()),
+            "misfiled under the partition directory {}, expected {}",
+            partition,
+            &asset_id[0..DIR_PARTITION_LEN]
+        );
     }
 
+    asset.verify_offline(config)?;
+
+    if let Some(chain) = chain {
+        verify_asset_issuance_tx(chain, &asset, config)
+            .context(VerificationStep::OnChainIssuance.context())?;
+    }
     Ok(())
 }
+
+fn parse_outpoint(s: &str) -> Result<OutPoint> {
+    let mut parts = s.splitn(2, ':');
+    let txid = Txid::from_hex(parts.next().req()?).context("invalid prevout txid")?;
+    let vout = parts
+        .next()
+        .or_err("missing prevout vout (expected <txid>:<vout>)")?
+        .parse()
+        .context("invalid prevout vout")?;
+    Ok(OutPoint::new(txid, vout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_sample_asset() -> Result<()> {
+        let path1 = write_sample_asset()?;
+        let path2 = write_sample_asset()?;
+        assert_ne!(path1, path2);
+        assert_eq!(std::fs::read_to_string(&path1)?, SAMPLE_ASSET);
+        assert_eq!(
+            Asset::load(path2.clone())?.id().to_hex(),
+            "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05"
+        );
+
+        std::fs::remove_file(path1)?;
+        std::fs::remove_file(path2)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_dir_chain_args() {
+        let args = |extra: &[&str]| {
+            let mut args = vec!["liquid-asset-registry", "validate-dir", "db"];
+            args.extend_from_slice(extra);
+            Cli::from_iter_safe(args)
+        };
+        assert!(args(&["--esplora-url", "http://localhost"]).is_ok());
+        assert!(args(&["--chain-snapshot", "snapshot"]).is_ok());
+        let err = args(&[
+            "--esplora-url",
+            "http://localhost",
+            "--chain-snapshot",
+            "snapshot",
+        ])
+        .unwrap_err();
+        assert_eq!(err.kind, structopt::clap::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_validate_dir() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "asset-registry-testdb-validate-dir-{}",
+            std::process::id()
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+        let asset_id = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let write = |path: &str| -> Result<PathBuf> {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(&path, SAMPLE_ASSET)?;
+            Ok(path)
+        };
+        let valid = write(&format!("b1/{}.json", asset_id))?;
+        let misnamed = write("b1/asset.json")?;
+        let misfiled = write(&format!("cd/{}.json", asset_id))?;
+        write(&format!("_map/{}.json", asset_id))?;
+        write(&format!(".git/{}.json", asset_id))?;
+
+        let mut files = Vec::new();
+        find_json_files(&dir, &mut files)?;
+        assert_eq!(
+            files,
+            vec![misnamed.clone(), valid.clone(), misfiled.clone()]
+        );
+
+        let config = ValidationConfig::default();
+        validate_asset_file(&valid, None, &config)?;
+        let err = validate_asset_file(&misnamed, None, &config).unwrap_err();
+        assert!(err.to_string().contains("doesn't match the asset id"));
+        let err = validate_asset_file(&misfiled, None, &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "misfiled under the partition directory cd, expected b1"
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,14 +1,32 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
 use bitcoin_hashes::hex::ToHex;
-use elements::{issuance::ContractHash, AssetId};
-use reqwest::{blocking::Client as ReqClient, StatusCode, Url};
+use elements::{issuance::ContractHash, AssetId, OutPoint};
+use reqwest::blocking::{Client as ReqClient, Response};
+use reqwest::{StatusCode, Url};
+use secp256k1::Secp256k1;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
-use crate::asset::{Asset, AssetRequest};
-use crate::errors::{Result, ResultExt};
+use crate::asset::{Asset, AssetRequest, ValidationConfig, VerificationStep};
+use crate::chain::ChainQuery;
+use crate::errors::{Error, OptionExt, Result, ResultExt};
+use crate::util::verify_response_sig;
+
+// Kept in sync with server::SIGNATURE_HEADER and server::VERIFICATION_STEP_HEADER, which aren't
+// available without the server feature
+const SIGNATURE_HEADER: &str = "X-Registry-Signature";
+const VERIFICATION_STEP_HEADER: &str = "X-Verification-Step";
+
+lazy_static! {
+    static ref EC: Secp256k1<secp256k1::VerifyOnly> = Secp256k1::verification_only();
+}
 
 pub struct Client {
     registry_url: Url,
     rclient: ReqClient,
+    registry_pubkey: Option<secp256k1::PublicKey>,
 }
 
 impl Client {
@@ -16,58 +34,182 @@ impl Client {
         Client {
             registry_url,
             rclient: ReqClient::new(),
+            registry_pubkey: None,
+        }
+    }
+
+    // Require assets to be signed by the authoritative registry key, allowing them to be
+    // fetched from untrusted mirrors
+    pub fn with_registry_pubkey(mut self, pubkey: secp256k1::PublicKey) -> Self {
+        self.registry_pubkey = Some(pubkey);
+        self
+    }
+
+    // The url of the given path segments under the registry url, keeping its path prefix (for
+    // registries served under a path, like `https://example.com/registry/`)
+    fn endpoint(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = self.registry_url.clone();
+        if !segments.is_empty() {
+            url.path_segments_mut()
+                .ok()
+                .or_err("invalid registry url")?
+                .pop_if_empty()
+                .extend(segments);
         }
+        Ok(url)
     }
 
     pub fn get(&self, asset_id: &AssetId) -> Result<Option<Asset>> {
         let resp = self
             .rclient
-            .get(self.registry_url.join(&asset_id.to_hex())?)
+            .get(self.endpoint(&[&asset_id.to_hex()])?)
             .send()
             .context("failed fetching asset from registry")?;
 
         if resp.status() == StatusCode::NOT_FOUND {
-            Ok(None)
-        } else {
-            Ok(Some(
-                resp.error_for_status()
-                    .context("failed fetching asset from registry")?
-                    .json()
-                    .context("failed parsing asset from registry")?,
-            ))
+            return Ok(None);
         }
+
+        let resp = resp
+            .error_for_status()
+            .context("failed fetching asset from registry")?;
+        let body = self
+            .verified_body(resp)
+            .context("failed fetching asset from registry")?;
+
+        let asset: Asset =
+            serde_json::from_slice(&body).context("failed parsing asset from registry")?;
+        // the registry signature only covers the body, so a mirror could reply with another
+        // (validly signed) asset
+        ensure!(
+            asset.asset_id == *asset_id,
+            "registry replied with asset {} instead of {}",
+            asset.asset_id,
+            asset_id
+        );
+        Ok(Some(asset))
     }
 
-    /*
+    // All the registered assets, fetched from the registry index page by page
     pub fn index(&self) -> Result<HashMap<AssetId, Asset>> {
-        Ok(self
-            .rclient
-            .get(self.registry_url.join("/"))
-            .send()
-            .context("failed fetching assets from registry")?
-            .error_for_status()
-            .context("failed fetching assets from registry")?
-            .json()
-            .context("failed deserializing asset map from registry")?)
+        self.fetch_index(false)
+    }
+
+    // The names and tickers of all the registered assets, a much smaller download than index()
+    pub fn index_minimal(&self) -> Result<HashMap<AssetId, AssetSummary>> {
+        self.fetch_index(true)
     }
-    */
 
+    fn fetch_index<T: DeserializeOwned>(&self, minimal: bool) -> Result<HashMap<AssetId, T>> {
+        let mut assets = HashMap::new();
+        let mut after: Option<AssetId> = None;
+        let mut seen_cursors = HashSet::new();
+        loop {
+            let mut url = self.endpoint(&[])?;
+            if minimal {
+                url.query_pairs_mut().append_pair("minimal", "1");
+            }
+            if let Some(after) = after {
+                url.query_pairs_mut().append_pair("after", &after.to_hex());
+            }
+
+            let resp = self
+                .rclient
+                .get(url)
+                .send()
+                .context("failed fetching assets from registry")?
+                .error_for_status()
+                .context("failed fetching assets from registry")?;
+            let body = self
+                .verified_body(resp)
+                .context("failed fetching assets from registry")?;
+            let page: IndexPage<T> = serde_json::from_slice(&body)
+                .context("failed deserializing asset map from registry")?;
+
+            assets.extend(page.assets);
+            after = page.next;
+            match after {
+                // a misbehaving registry could otherwise keep us paging forever
+                Some(next) => ensure!(
+                    seen_cursors.insert(next),
+                    "registry repeated the index page cursor {}",
+                    next
+                ),
+                None => break,
+            }
+        }
+        Ok(assets)
+    }
+
+    // Read the response body, verifying its signature when the registry key is required
+    fn verified_body(&self, resp: Response) -> Result<Vec<u8>> {
+        let signature = resp
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .map(|sig| hex::decode(sig.as_bytes()))
+            .transpose()
+            .context("invalid registry signature")?;
+        let body = resp.bytes()?;
+
+        if let Some(pubkey) = &self.registry_pubkey {
+            let signature = signature.or_err("missing registry signature")?;
+            verify_response_sig(&EC, pubkey, &body, &signature)?;
+        }
+        Ok(body.to_vec())
+    }
+
+    // Register the asset. Failures reported by the registry are returned as a RegistrationError,
+    // which can be recovered with `err.downcast_ref::<RegistrationError>()`.
     pub fn register(&self, asset: &AssetRequest) -> Result<Asset> {
-        Ok(self
+        let resp = self
             .rclient
-            .post(self.registry_url.join("/")?)
+            .post(self.endpoint(&[])?)
             .json(asset)
             .send()
-            .context("failed sending asset to registry")?
-            .error_for_status()
-            .context("failed sending asset to registry")?
-            .json()
-            .context("failed parsing asset from registry")?)
+            .context("failed sending asset to registry")?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let step = resp
+                .headers()
+                .get(VERIFICATION_STEP_HEADER)
+                .and_then(|step| step.to_str().ok())
+                .map(String::from);
+            let body = resp.text().context("failed sending asset to registry")?;
+            return Err(RegistrationError::from_response(status, step.as_deref(), &body).into());
+        }
+
+        Ok(resp.json().context("failed parsing asset from registry")?)
+    }
+
+    // Verify the asset locally before registering it, to catch the most common mistakes (like a
+    // wrong contract hash or an invalid ticker) without a round trip to the registry. The issuance
+    // is looked up using the esplora instance at `esplora_url`. Local verification failures are
+    // returned as a RegistrationError, just like the ones reported by the registry.
+    pub fn verify_and_register(&self, asset: &AssetRequest, esplora_url: &str) -> Result<Asset> {
+        let chain = ChainQuery::new(esplora_url.to_string());
+        // only the rules common to all registries are checked, operator-specific policies
+        // (like length limits) are left for the registry to enforce
+        let config = ValidationConfig {
+            allow_entityless_assets: true,
+            ..Default::default()
+        };
+
+        if let Err(err) = Asset::from_request(asset.clone(), &chain)
+            .and_then(|local_asset| local_asset.verify_offline(&config))
+        {
+            return Err(match RegistrationError::from_error(&err) {
+                Some(reg_err) => reg_err.into(),
+                None => err,
+            });
+        }
+
+        self.register(asset)
     }
 
     pub fn delete(&self, asset_id: &AssetId, signature: &[u8]) -> Result<()> {
         self.rclient
-            .delete(self.registry_url.join(&asset_id.to_hex())?)
+            .delete(self.endpoint(&[&asset_id.to_hex()])?)
             .json(&json!({ "signature": base64::encode(signature) }))
             .send()
             .context("failed sending deletion request to registry")?
@@ -76,17 +218,270 @@ impl Client {
         Ok(())
     }
 
-    pub fn validate_contract(&self, contract: &Value, contract_hash: &ContractHash) -> Result<()> {
+    // Validate the contract against its hash. If the issuance prevout is provided, returns the
+    // asset id the registry derived for the contract and prevout.
+    pub fn validate_contract(
+        &self,
+        contract: &Value,
+        contract_hash: &ContractHash,
+        prevout: Option<&OutPoint>,
+    ) -> Result<Option<AssetId>> {
         let resp = self
             .rclient
-            .post(self.registry_url.join("/contract/validate")?)
-            .json(&json!({ "contract": contract, "contract_hash": contract_hash }))
+            .post(self.endpoint(&["contract", "validate"])?)
+            .json(&json!({
+                "contract": contract,
+                "contract_hash": contract_hash,
+                "prevout": prevout
+            }))
             .send()
             .context("failed sending validation request to registry")?;
 
         if resp.status() != StatusCode::OK {
             bail!("validation failed: {}", resp.text()?);
         }
+
+        Ok(match prevout {
+            Some(_) => {
+                let derived: DerivedAsset = resp
+                    .json()
+                    .context("failed parsing validation response from registry")?;
+                Some(derived.asset_id)
+            }
+            None => None,
+        })
+    }
+}
+
+// An asset in the minimal index, see Client::index_minimal()
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AssetSummary {
+    pub name: String,
+    pub ticker: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexPage<T> {
+    assets: HashMap<AssetId, T>,
+    next: Option<AssetId>,
+}
+
+#[derive(Deserialize)]
+struct DerivedAsset {
+    asset_id: AssetId,
+}
+
+// A registration rejected by the registry. Verification failures identify the step that failed,
+// so that wallets can show issuers a precise (and localized) message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistrationError {
+    Verification {
+        step: VerificationStep,
+        reason: String,
+    },
+    Rejected {
+        status: StatusCode,
+        reason: String,
+    },
+}
+
+impl RegistrationError {
+    // The registry replies with the error chain joined by ": ", and the code of the verification
+    // step that failed in the X-Verification-Step header (if the failure happened during
+    // verification). The step's context message is stripped from the reason.
+    fn from_response(status: StatusCode, step: Option<&str>, body: &str) -> Self {
+        let body = body.trim();
+        match step.and_then(VerificationStep::from_code) {
+            Some(step) => RegistrationError::Verification {
+                step,
+                reason: body
+                    .strip_prefix(step.context())
+                    .map_or(body, |rest| rest.trim_start_matches(": "))
+                    .to_string(),
+            },
+            None => RegistrationError::Rejected {
+                status,
+                reason: body.to_string(),
+            },
+        }
+    }
+
+    // A local verification failure, with the error chain that follows the step's context as the
+    // reason
+    fn from_error(err: &Error) -> Option<Self> {
+        let step = VerificationStep::of_error(err)?;
+        let reason = err
+            .iter_chain()
+            .map(|cause| cause.to_string())
+            .skip_while(|message| message != step.context())
+            .skip(1)
+            .collect::<Vec<String>>()
+            .join(": ");
+        Some(RegistrationError::Verification { step, reason })
+    }
+}
+
+impl fmt::Display for RegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistrationError::Verification { step, reason } => {
+                write!(f, "{}: {}", step.context(), reason)
+            }
+            RegistrationError::Rejected { status, reason } => {
+                write!(f, "registration rejected ({}): {}", status, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistrationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin_hashes::hex::FromHex;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::{thread, time::Duration};
+
+    // A registry replying to every request with the given json body
+    fn spawn_mock_registry(body: String) -> Result<Url> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/", listener.local_addr()?).parse()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                // consume the request head before replying
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        Ok(url)
+    }
+
+    #[test]
+    fn test_endpoint() -> Result<()> {
+        let endpoint = |registry_url: &str, segments: &[&str]| -> Result<String> {
+            Ok(Client::new(registry_url.parse()?)
+                .endpoint(segments)?
+                .to_string())
+        };
+        assert_eq!(
+            endpoint("http://localhost/", &["abc"])?,
+            "http://localhost/abc"
+        );
+        assert_eq!(
+            endpoint("http://localhost/registry/", &["contract", "validate"])?,
+            "http://localhost/registry/contract/validate"
+        );
+        assert_eq!(
+            endpoint("http://localhost/registry", &["abc"])?,
+            "http://localhost/registry/abc"
+        );
+        assert_eq!(
+            endpoint("http://localhost/registry/", &[])?,
+            "http://localhost/registry/"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_mismatched_asset() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        let client = Client::new(spawn_mock_registry(serde_json::to_string(&asset)?)?);
+
+        assert_eq!(client.get(&asset.asset_id)?.req()?.asset_id, asset.asset_id);
+
+        let other_id =
+            AssetId::from_hex("cdcc515938c9b38d4312fcdb6001fc434596f1edb1fe09e51d319bd487dcaab8")?;
+        let err = client.get(&other_id).unwrap_err();
+        assert!(err.to_string().contains("instead of"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_registration_error() {
+        assert_eq!(
+            RegistrationError::from_response(
+                StatusCode::BAD_REQUEST,
+                Some("linked_entity"),
+                "failed verifying linked entity: failed verifying http domain proof: verification page contents mismatch"
+            ),
+            RegistrationError::Verification {
+                step: VerificationStep::LinkedEntity,
+                reason: "failed verifying http domain proof: verification page contents mismatch"
+                    .to_string(),
+            }
+        );
+        assert_eq!(
+            RegistrationError::from_response(
+                StatusCode::BAD_REQUEST,
+                None,
+                "failed parsing json request"
+            ),
+            RegistrationError::Rejected {
+                status: StatusCode::BAD_REQUEST,
+                reason: "failed parsing json request".to_string(),
+            }
+        );
+        // the message alone isn't parsed, without the header it's not a verification failure
+        assert_eq!(
+            RegistrationError::from_response(
+                StatusCode::BAD_REQUEST,
+                None,
+                "failed verifying linked entity: the asset name looks like a step context"
+            ),
+            RegistrationError::Rejected {
+                status: StatusCode::BAD_REQUEST,
+                reason: "failed verifying linked entity: the asset name looks like a step context"
+                    .to_string(),
+            }
+        );
+        assert_eq!(
+            RegistrationError::from_response(StatusCode::BAD_REQUEST, Some("unknown"), "oops"),
+            RegistrationError::Rejected {
+                status: StatusCode::BAD_REQUEST,
+                reason: "oops".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_and_register_locally() -> Result<()> {
+        crate::chain::tests::spawn_mock_esplora_server();
+        thread::sleep(Duration::from_millis(250));
+
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        let mut req = AssetRequest {
+            asset_id: asset.asset_id,
+            contract: asset.contract.clone(),
+            domain_verification_method: None,
+            contract_signature: None,
+        };
+        req.contract["name"] = "Other coin".into();
+
+        // nothing listens here, so the failure must be found locally
+        let client = Client::new("http://127.0.0.1:1".parse()?);
+        let err = client
+            .verify_and_register(&req, "http://localhost:58713")
+            .unwrap_err();
+        match err.downcast_ref::<RegistrationError>() {
+            Some(RegistrationError::Verification { step, .. }) => {
+                assert_eq!(*step, VerificationStep::IssuanceCommitment)
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
         Ok(())
     }
 }
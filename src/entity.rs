@@ -1,97 +1,945 @@
-use std::fmt;
+use std::{env, fmt, str::FromStr, time::Duration};
 
 use bitcoin_hashes::hex::ToHex;
+use elements::AssetId;
 use failure::ResultExt;
-use reqwest::blocking::get as reqwest_get;
+use reqwest::blocking::{Client as ReqClient, ClientBuilder as ReqClientBuilder};
+use reqwest::{header, Proxy, StatusCode, Url};
+use secp256k1::Secp256k1;
 
-use crate::asset::Asset;
-use crate::errors::Result;
-use crate::util::verify_domain_name;
+use crate::asset::{Asset, ValidationConfig};
+use crate::errors::{OptionExt, Result};
+use crate::tls::{get_inspecting_certificate, TlsCertificate, TlsPin};
+use crate::util::{verify_bitcoin_msg, verify_domain_name};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+lazy_static! {
+    static ref EC: Secp256k1<secp256k1::VerifyOnly> = Secp256k1::verification_only();
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub enum AssetEntity {
     #[serde(rename = "domain")]
     DomainName(String),
+    // Self-declared assets that aren't linked to any entity, for issuers without a domain.
+    // Only allowed in `version` 1 contracts, which may omit the `entity` field.
+    #[serde(rename = "none")]
+    None,
+}
+
+impl AssetEntity {
+    pub fn is_none(&self) -> bool {
+        *self == AssetEntity::None
+    }
+}
+
+impl Default for AssetEntity {
+    fn default() -> Self {
+        AssetEntity::None
+    }
 }
 
 impl fmt::Display for AssetEntity {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             AssetEntity::DomainName(domain) => write!(f, "domain:{}", domain),
+            AssetEntity::None => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DomainVerificationMethod {
+    // A proof page hosted under the domain's /.well-known/
+    Http,
+    // A TXT record under the domain's _liquid-asset-proof subdomain
+    Dns,
+}
+
+impl fmt::Display for DomainVerificationMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DomainVerificationMethod::Http => write!(f, "http"),
+            DomainVerificationMethod::Dns => write!(f, "dns"),
+        }
+    }
+}
+
+impl FromStr for DomainVerificationMethod {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "http" => DomainVerificationMethod::Http,
+            "dns" => DomainVerificationMethod::Dns,
+            _ => bail!("unknown domain verification method `{}`", s),
+        })
+    }
+}
+
+// The DNS-over-HTTPS resolver used for looking up the DNS proofs, queried with the JSON api that
+// Google, Cloudflare and most other public resolvers support
+#[derive(Debug, Clone, PartialEq)]
+pub enum DnsResolver {
+    Google,
+    Cloudflare,
+    Custom(Url),
+}
+
+impl DnsResolver {
+    fn url(&self) -> &str {
+        match self {
+            DnsResolver::Google => "https://dns.google/resolve",
+            DnsResolver::Cloudflare => "https://cloudflare-dns.com/dns-query",
+            DnsResolver::Custom(url) => url.as_str(),
         }
     }
 }
 
-pub fn verify_asset_link(asset: &Asset) -> Result<()> {
+impl Default for DnsResolver {
+    fn default() -> Self {
+        DnsResolver::Google
+    }
+}
+
+impl FromStr for DnsResolver {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "google" => DnsResolver::Google,
+            "cloudflare" => DnsResolver::Cloudflare,
+            _ => {
+                let url = Url::parse(s).context(format!(
+                    "unknown dns resolver `{}`, expected google, cloudflare or an https url",
+                    s
+                ))?;
+                ensure!(url.scheme() == "https", "dns resolver url must be https");
+                DnsResolver::Custom(url)
+            }
+        })
+    }
+}
+
+// Verify the link to the asset's entity, returning where the proofs were found and the details of
+// the HTTP proof (if the HTTP method was used)
+pub fn verify_asset_link(asset: &Asset, config: &ValidationConfig) -> Result<VerifiedLink> {
+    let mut link = VerifiedLink::default();
     match asset.entity() {
-        AssetEntity::DomainName(domain) => verify_domain_link(asset, domain),
+        AssetEntity::DomainName(domain) => {
+            for method in verification_methods(asset, config) {
+                let location = match method {
+                    DomainVerificationMethod::Http => {
+                        let validators = verify_domain_link_http(asset, domain, config)
+                            .context("failed verifying http domain proof")?;
+                        let page_url =
+                            http_proof_url(&validators.host, validators.version, asset.id());
+                        link.http_proof = Some(HttpProof {
+                            version: validators.version,
+                            // only recorded when served by another host than the entity domain
+                            host: Some(validators.host).filter(|host| host != domain),
+                            tls_certificate: validators.tls_certificate,
+                        });
+                        page_url
+                    }
+                    DomainVerificationMethod::Dns => verify_domain_link_dns(asset, domain, config)
+                        .context("failed verifying dns domain proof")?,
+                };
+                link.proofs.push(ProofLocation { method, location });
+            }
+        }
+        // there's nothing to link, the asset is served as unverified
+        AssetEntity::None => (),
     }
+    Ok(link)
 }
 
-fn verify_domain_link(asset: &Asset, domain: &str) -> Result<()> {
-    verify_domain_name(domain).context("invalid domain name")?;
+// The outcome of verifying the link to the asset's entity, recorded on the asset
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifiedLink {
+    pub proofs: Vec<ProofLocation>,
+    pub http_proof: Option<HttpProof>,
+}
+
+// Where a domain proof was found: the proof page url for HTTP, or the name of the TXT record for
+// DNS (the CNAME target when delegated)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProofLocation {
+    pub method: DomainVerificationMethod,
+    pub location: String,
+}
 
-    // TODO tor proxy for accessing onion
+// The details of a verified HTTP proof, recorded on the asset
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpProof {
+    pub version: HttpProofVersion,
+    pub host: Option<String>,
+    // only inspected when pinned or recorded, see fetch_proof_response()
+    pub tls_certificate: Option<TlsCertificate>,
+}
 
-    let asset_id = asset.id().to_hex();
+// The domain verification methods that must pass for the asset link to be considered valid
+pub fn verification_methods(
+    asset: &Asset,
+    config: &ValidationConfig,
+) -> Vec<DomainVerificationMethod> {
+    if asset.entity().is_none() {
+        vec![]
+    } else if config.require_both_proofs {
+        vec![
+            DomainVerificationMethod::Http,
+            DomainVerificationMethod::Dns,
+        ]
+    } else {
+        vec![asset
+            .domain_verification_method
+            .or(config.default_verification_method)
+            .unwrap_or(DomainVerificationMethod::Http)]
+    }
+}
 
-    let expected_body = format!(
+// The contents expected in the HTTP proof page and the DNS TXT record
+pub fn expected_proof_body(domain: &str, asset_id: &AssetId) -> String {
+    format_proof_body(domain, &asset_id.to_hex())
+}
+
+fn format_proof_body(domain: &str, asset_id: &str) -> String {
+    format!(
         "Authorize linking the domain name {} to the Liquid asset {}",
         domain, asset_id
-    );
+    )
+}
 
-    let page_url = if cfg!(any(test, feature = "dev")) {
-        // use a hard-coded verification page in testing and development modes
-        format!(
-            "http://127.0.0.1:58712/.well-known/liquid-asset-proof-{}",
-            asset_id
-        )
-    } else {
-        // require tls for non-onion hosts, assume http for onion ones
-        let protocol = if domain.ends_with(".onion") {
-            "http"
-        } else {
-            "https"
-        };
+// The proofs to publish for linking an asset to a domain, with either verification method
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DomainProof {
+    pub asset_id: AssetId,
+    // the contents of both the HTTP proof page and the DNS TXT record
+    pub body: String,
+    pub http_path: String,
+    pub dns_record_name: String,
+}
 
-        format!(
-            "{}://{}/.well-known/liquid-asset-proof-{}",
-            protocol, domain, asset_id
-        )
+// The proofs for linking each of the assets to the domain, for onboarding a domain with many
+// assets at once
+pub fn domain_proofs(domain: &str, asset_ids: &[AssetId]) -> Result<Vec<DomainProof>> {
+    verify_domain_name(domain).context("invalid domain name")?;
+
+    Ok(asset_ids
+        .iter()
+        .map(|asset_id| DomainProof {
+            asset_id: *asset_id,
+            body: expected_proof_body(domain, asset_id),
+            http_path: http_proof_path(asset_id),
+            dns_record_name: dns_proof_record_name(domain),
+        })
+        .collect())
+}
+
+// The path under which the HTTP proof page should be hosted
+pub fn http_proof_path(asset_id: &AssetId) -> String {
+    format_http_proof_path(HttpProofVersion::V1, &asset_id.to_hex())
+}
+
+fn format_http_proof_path(version: HttpProofVersion, asset_id: &str) -> String {
+    format!("{}liquid-asset-proof-{}", version.path_prefix(), asset_id)
+}
+
+// The versioned locations of the HTTP proof page, allowing the proof format to evolve while the
+// proofs of existing registrations keep working. Verification tries the newest version first,
+// falling back to older ones when the page doesn't exist or doesn't verify.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpProofVersion {
+    // /.well-known/liquid-asset-proof-<asset-id>
+    V1,
+    // /.well-known/v2/liquid-asset-proof-<asset-id>
+    V2,
+}
+
+impl HttpProofVersion {
+    pub const NEWEST_FIRST: [HttpProofVersion; 2] = [HttpProofVersion::V2, HttpProofVersion::V1];
+
+    fn path_prefix(self) -> &'static str {
+        match self {
+            HttpProofVersion::V1 => "/.well-known/",
+            HttpProofVersion::V2 => "/.well-known/v2/",
+        }
+    }
+}
+
+// Describe the supported verification methods and their exact proof formats, as templates with
+// `{domain}` and `{asset_id}` placeholders and as examples rendered for the given values.
+// Derived from the same functions used for verification, so that they can't drift apart.
+pub fn describe_verification_methods(
+    default_method: DomainVerificationMethod,
+    example_domain: &str,
+    example_asset_id: &AssetId,
+) -> serde_json::Value {
+    let describe = |domain: &str, asset_id: &str| {
+        json!({
+            "http": {
+                "url": format!(
+                    "https://{}{}",
+                    domain,
+                    format_http_proof_path(HttpProofVersion::V1, asset_id)
+                ),
+                "body": format_proof_body(domain, asset_id),
+            },
+            "dns": {
+                "record_type": "TXT",
+                "name": dns_proof_record_name(domain),
+                "value": format_proof_body(domain, asset_id),
+                "delegation": {
+                    "record_type": "CNAME",
+                    "name": dns_challenge_record_name(domain),
+                },
+            },
+        })
     };
 
+    json!({
+        "default": default_method,
+        "templates": describe("{domain}", "{asset_id}"),
+        "example": {
+            "domain": example_domain,
+            "asset_id": example_asset_id.to_hex(),
+            "proofs": describe(example_domain, &example_asset_id.to_hex()),
+        },
+        "notes": [
+            "The HTTP proof is fetched over plain http for .onion domains",
+            "The HTTP proof body may be surrounded by whitespace",
+            "The HTTP proof body may instead be the base64 signature of the body by the contract's issuer_pubkey, as a Bitcoin signed message",
+            "The HTTP proof may also be hosted under /.well-known/v2/, which is tried first",
+            "When no matching TXT record is found, the TXT records at the target of the delegation CNAME are checked instead",
+        ],
+    })
+}
+
+// The cache validators returned with the HTTP proof page, for re-verifying it with a conditional
+// request that doesn't re-download an unchanged page. Only valid for the page at the same host and
+// version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProofValidators {
+    pub host: String,
+    pub version: HttpProofVersion,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    // the certificate of the session the page was fetched over, when it was inspected
+    pub tls_certificate: Option<TlsCertificate>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HttpProofStatus {
+    // the page wasn't modified since it was last verified with the given validators
+    Unchanged,
+    // the page was fetched and verified, with the validators to use for the next re-verification
+    Verified(ProofValidators),
+}
+
+// Re-verify the HTTP proof of an asset verified before with the given validators. An unmodified
+// proof page is considered still valid, only a changed (or missing) page is fully re-evaluated.
+pub fn reverify_domain_link_http(
+    asset: &Asset,
+    validators: Option<&ProofValidators>,
+    config: &ValidationConfig,
+) -> Result<HttpProofStatus> {
+    match asset.entity() {
+        AssetEntity::DomainName(domain) => {
+            // re-verified at the recorded proof location, as long as it's one of the proof urls
+            // of the entity domain (so that an edited asset file can't point the fetch
+            // elsewhere). otherwise, the url is rebuilt from the recorded host and path version.
+            let recorded = recorded_proof_page(asset, domain);
+            let host = match &recorded {
+                Some((host, _)) => host.clone(),
+                None => asset
+                    .http_proof_host
+                    .clone()
+                    .unwrap_or_else(|| domain.clone()),
+            };
+            ensure!(
+                host == *domain || host == www_alternate(domain),
+                "recorded proof host {} doesn't match the domain {}",
+                host,
+                domain
+            );
+            match recorded
+                .map(|(_, version)| version)
+                .or(asset.http_proof_version)
+            {
+                Some(version) => {
+                    verify_domain_name(domain).context("invalid domain name")?;
+                    let validators = validators.filter(|validators| {
+                        validators.version == version && validators.host == host
+                    });
+                    let page_url = http_proof_url(&host, version, asset.id());
+                    fetch_proof_page(asset, domain, &page_url, &host, version, validators, config)?
+                        .or_err(format!("verification page not found at {}", page_url))
+                }
+                // assets verified before the proof location and version were recorded
+                None => fetch_domain_link_http(asset, domain, &[&host], validators, config),
+            }
+        }
+        AssetEntity::None => bail!("asset is not linked to a domain"),
+    }
+}
+
+// The host and path version of the recorded HTTP proof location, if it's the proof url of the
+// entity domain or its www alternate
+fn recorded_proof_page(asset: &Asset, domain: &str) -> Option<(String, HttpProofVersion)> {
+    let location = asset.proof_location(DomainVerificationMethod::Http)?;
+    let hosts = [domain.to_string(), www_alternate(domain)];
+    hosts.iter().find_map(|host| {
+        HttpProofVersion::NEWEST_FIRST
+            .iter()
+            .find(|version| http_proof_url(host, **version, asset.id()) == location)
+            .map(|version| (host.clone(), *version))
+    })
+}
+
+// Verify the HTTP proof, returning the validators of the proof page that verified it (which
+// identify its host and path version)
+fn verify_domain_link_http(
+    asset: &Asset,
+    domain: &str,
+    config: &ValidationConfig,
+) -> Result<ProofValidators> {
+    let www_alternate = www_alternate(domain);
+    let mut hosts = vec![domain];
+    if config.http_proof_www_fallback && !domain.ends_with(".onion") {
+        hosts.push(&www_alternate);
+    }
+
+    match fetch_domain_link_http(asset, domain, &hosts, None, config)? {
+        HttpProofStatus::Verified(validators) => Ok(validators),
+        HttpProofStatus::Unchanged => unreachable!("unconditional requests are never unchanged"),
+    }
+}
+
+// The host to fall back to when the entity domain has no proof page: the domain without its `www.`
+// prefix, or with one added if it has none
+fn www_alternate(domain: &str) -> String {
+    match domain.strip_prefix("www.") {
+        Some(bare_domain) => bare_domain.to_string(),
+        None => format!("www.{}", domain),
+    }
+}
+
+// Fetch the proof page for the entity domain from the first host that has one, trying each of the
+// proof path versions. The proof must name the entity domain regardless of the host serving it.
+fn fetch_domain_link_http(
+    asset: &Asset,
+    domain: &str,
+    hosts: &[&str],
+    validators: Option<&ProofValidators>,
+    config: &ValidationConfig,
+) -> Result<HttpProofStatus> {
+    verify_domain_name(domain).context("invalid domain name")?;
+
+    let mut newer_err = None;
+    for host in hosts {
+        for &version in HttpProofVersion::NEWEST_FIRST.iter() {
+            let validators = validators
+                .filter(|validators| validators.version == version && validators.host == *host);
+            let page_url = http_proof_url(host, version, asset.id());
+            match fetch_proof_page(asset, domain, &page_url, host, version, validators, config) {
+                Ok(Some(status)) => return Ok(status),
+                Ok(None) => (),
+                // hosts that don't know about the newer paths may answer them with an error status
+                // or a catch-all page, fall back to the older versions
+                Err(err) if version != HttpProofVersion::V1 => {
+                    debug!("{:?} proof page at {} failed: {}", version, page_url, err);
+                    newer_err.get_or_insert(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    if let Some(err) = newer_err {
+        return Err(err);
+    }
+
+    let page_urls: Vec<String> = hosts
+        .iter()
+        .flat_map(|host| {
+            HttpProofVersion::NEWEST_FIRST
+                .iter()
+                .map(move |version| http_proof_url(host, *version, asset.id()))
+        })
+        .collect();
+    bail!("verification page not found at {}", page_urls.join(" or "))
+}
+
+fn http_proof_url(host: &str, version: HttpProofVersion, asset_id: &AssetId) -> String {
+    let proof_path = format_http_proof_path(version, &asset_id.to_hex());
+    if cfg!(any(test, feature = "dev")) {
+        // use a hard-coded verification page in testing and development modes. the host is passed
+        // along, so that the mock server can tell the entity domain and its alternate apart.
+        format!("http://127.0.0.1:58712{}?host={}", proof_path, host)
+    } else {
+        format!("{}://{}{}", http_proof_scheme(host), host, proof_path)
+    }
+}
+
+// The scheme the HTTP proof is fetched over: tls is required for non-onion hosts, http is
+// assumed for onion ones
+pub fn http_proof_scheme(host: &str) -> &'static str {
+    if host.ends_with(".onion") {
+        "http"
+    } else {
+        "https"
+    }
+}
+
+// Fetch and verify the proof page at the given url (of the host and path version), or None if
+// there's no page there
+fn fetch_proof_page(
+    asset: &Asset,
+    domain: &str,
+    page_url: &str,
+    host: &str,
+    version: HttpProofVersion,
+    validators: Option<&ProofValidators>,
+    config: &ValidationConfig,
+) -> Result<Option<HttpProofStatus>> {
+    let asset_id = asset.id().to_hex();
+
+    let expected_body = expected_proof_body(domain, asset.id());
+
     debug!(
         "verifying domain name {} for {}: GET {}",
         domain, asset_id, page_url
     );
 
-    let body = reqwest_get(&page_url)
-        .context(format!("failed fetching {}", page_url))?
-        .error_for_status()?
-        .text()
-        .context("invalid page contents")?;
+    let mut conditional = vec![];
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            conditional.push((header::IF_NONE_MATCH, etag.as_str()));
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            conditional.push((header::IF_MODIFIED_SINCE, last_modified.as_str()));
+        }
+    }
+    let resp = fetch_proof_response(page_url, host, &conditional, config)
+        .context(format!("failed fetching {}", page_url))?;
 
+    if validators.is_some() && resp.status == StatusCode::NOT_MODIFIED {
+        debug!("domain link {} for {} is unchanged", domain, asset_id);
+        return Ok(Some(HttpProofStatus::Unchanged));
+    }
+    if resp.status == StatusCode::NOT_FOUND {
+        debug!(
+            "no {:?} proof page for {} at {}",
+            version, asset_id, page_url
+        );
+        return Ok(None);
+    }
     ensure!(
-        body.trim_end() == expected_body,
+        resp.status.is_success(),
+        "unexpected status {} fetching {}",
+        resp.status,
+        page_url
+    );
+
+    let validators = ProofValidators {
+        host: host.to_string(),
+        version,
+        etag: resp.etag,
+        last_modified: resp.last_modified,
+        tls_certificate: resp.tls_certificate,
+    };
+    let body = resp.body;
+
+    ensure!(
+        http_proof_matches(&body, &expected_body)
+            || http_proof_sig_matches(&body, &expected_body, &asset.fields.issuer_pubkey),
         "verification page contents mismatch"
     );
 
-    debug!("verified domain link {} for {}", domain, asset_id);
+    debug!(
+        "verified domain link {} for {} ({:?})",
+        domain, asset_id, version
+    );
+
+    Ok(Some(HttpProofStatus::Verified(validators)))
+}
 
+// The parts of the proof page response used for verification
+struct ProofResponse {
+    status: StatusCode,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    // only read for successful responses
+    body: String,
+    tls_certificate: Option<TlsCertificate>,
+}
+
+// The timeout for fetching a proof page, including reading its body
+const PROOF_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Fetch the proof page. When the host has certificate pins or certificates are recorded, the page
+// is fetched over a TLS session whose certificate is checked against the pins before the request is
+// sent, since the HTTP client doesn't expose it. Onion hosts are fetched over plain http and have
+// no certificate. Redirects are rejected on both paths, since the pins only cover the first hop.
+fn fetch_proof_response(
+    page_url: &str,
+    host: &str,
+    conditional: &[(header::HeaderName, &str)],
+    config: &ValidationConfig,
+) -> Result<ProofResponse> {
+    let pins: Vec<&TlsPin> = config
+        .tls_pins
+        .iter()
+        .filter(|pin| pin.domain == host)
+        .collect();
+
+    if !host.ends_with(".onion") && (!pins.is_empty() || config.record_tls_certificate) {
+        let headers: Vec<(&str, &str)> = conditional
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        let url = Url::parse(page_url)?;
+        let proxy = https_proxy()?;
+        let resp = get_inspecting_certificate(
+            &url,
+            &headers,
+            PROOF_FETCH_TIMEOUT,
+            proxy.as_ref(),
+            |cert| {
+                ensure!(
+                    pins.is_empty() || pins.iter().any(|pin| pin.sha256 == cert.sha256),
+                    "certificate {} (issued to {}) doesn't match the pinned fingerprints",
+                    cert.sha256,
+                    cert.subject
+                );
+                Ok(())
+            },
+        )?;
+        let status = StatusCode::from_u16(resp.status)?;
+        ensure_not_redirected(status, resp.header("location"))?;
+        Ok(ProofResponse {
+            status,
+            etag: resp.header("etag").map(String::from),
+            last_modified: resp.header("last-modified").map(String::from),
+            body: if status.is_success() {
+                String::from_utf8_lossy(&resp.body).into_owned()
+            } else {
+                String::new()
+            },
+            tls_certificate: Some(resp.certificate),
+        })
+    } else {
+        let client = http_client_builder(host, config)?
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(PROOF_FETCH_TIMEOUT)
+            .build()?;
+        let mut req = client.get(page_url);
+        for (name, value) in conditional {
+            req = req.header(name.clone(), *value);
+        }
+        let resp = req.send()?;
+        let status = resp.status();
+        let get_header = |name: header::HeaderName| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        ensure_not_redirected(status, get_header(header::LOCATION).as_deref())?;
+        let (etag, last_modified) = (get_header(header::ETAG), get_header(header::LAST_MODIFIED));
+        Ok(ProofResponse {
+            status,
+            etag,
+            last_modified,
+            body: if status.is_success() {
+                resp.text().context("invalid page contents")?
+            } else {
+                String::new()
+            },
+            tls_certificate: None,
+        })
+    }
+}
+
+// Redirects aren't followed for proof pages, the proof has to be served at the well-known url
+fn ensure_not_redirected(status: StatusCode, location: Option<&str>) -> Result<()> {
+    ensure!(
+        !status.is_redirection() || status == StatusCode::NOT_MODIFIED,
+        "proof page redirected to {} ({}), redirects are not followed",
+        location.unwrap_or("nowhere"),
+        status
+    );
     Ok(())
 }
 
+// The HTTPS proxy from the environment, as the HTTP client uses it for the other requests
+fn https_proxy() -> Result<Option<Url>> {
+    match env::var("HTTPS_PROXY").or_else(|_| env::var("https_proxy")) {
+        Ok(proxy) if !proxy.is_empty() => {
+            let proxy = if proxy.contains("://") {
+                proxy
+            } else {
+                format!("http://{}", proxy)
+            };
+            Ok(Some(Url::parse(&proxy).context("invalid HTTPS_PROXY")?))
+        }
+        _ => Ok(None),
+    }
+}
+
+// The HTTP client for fetching from the given host. Onion hosts are reached through the Tor proxy,
+// when one is configured.
+pub fn http_client(host: &str, config: &ValidationConfig) -> Result<ReqClient> {
+    Ok(http_client_builder(host, config)?.build()?)
+}
+
+// The builder for `http_client()`, for callers that need further restrictions
+pub fn http_client_builder(host: &str, config: &ValidationConfig) -> Result<ReqClientBuilder> {
+    match &config.tor_proxy {
+        Some(tor_proxy) if host.ends_with(".onion") => {
+            // resolve the onion address on the proxy side (socks5h), it has no DNS record
+            let proxy_url = if tor_proxy.contains("://") {
+                tor_proxy.clone()
+            } else {
+                format!("socks5h://{}", tor_proxy)
+            };
+            Ok(ReqClient::builder().proxy(Proxy::all(&proxy_url).context("invalid tor proxy")?))
+        }
+        _ => Ok(ReqClient::builder()),
+    }
+}
+
+// Compare the proof page contents against the expected body, tolerating surrounding whitespace
+// (including CRLF line endings) and a leading UTF-8 BOM. Any other extra content is rejected.
+fn http_proof_matches(body: &str, expected_body: &str) -> bool {
+    body.trim_start_matches('\u{feff}').trim() == expected_body
+}
+
+// The signed alternative to the plaintext proof: a base64 signature by the issuer_pubkey over the
+// expected body, as a Bitcoin signed message. Unlike the plaintext, it can't be produced by anyone
+// who merely controls the domain's web server.
+fn http_proof_sig_matches(body: &str, expected_body: &str, issuer_pubkey: &[u8]) -> bool {
+    base64::decode(body.trim_start_matches('\u{feff}').trim()).map_or(false, |signature| {
+        verify_bitcoin_msg(&EC, issuer_pubkey, &signature, expected_body).is_ok()
+    })
+}
+
+// Verify the DNS proof, returning the name of the TXT record it was found at
+fn verify_domain_link_dns(
+    asset: &Asset,
+    domain: &str,
+    config: &ValidationConfig,
+) -> Result<String> {
+    verify_domain_name(domain).context("invalid domain name")?;
+
+    let resolver = config.dns_resolver.clone().unwrap_or_default();
+    let asset_id = asset.id().to_hex();
+    let expected_record = expected_proof_body(domain, asset.id());
+    let record_name = dns_proof_record_name(domain);
+
+    debug!(
+        "verifying domain name {} for {}: TXT {}",
+        domain, asset_id, record_name
+    );
+
+    let has_proof = |records: Vec<String>| {
+        records
+            .iter()
+            .any(|record| record.trim() == expected_record)
+    };
+
+    let mut proof_record_name = record_name.clone();
+    if !has_proof(txt_lookup(&record_name, &resolver)?) {
+        // the proof may be hosted on a third-party zone, delegated to via a CNAME record
+        let challenge_name = dns_challenge_record_name(domain);
+        let delegated_name = cname_lookup(&challenge_name, &resolver)?.or_err(format!(
+            "no matching TXT record found at {} (and no CNAME delegation at {})",
+            record_name, challenge_name
+        ))?;
+
+        debug!(
+            "verifying domain name {} for {}: delegated via {} to TXT {}",
+            domain, asset_id, challenge_name, delegated_name
+        );
+
+        ensure!(
+            has_proof(txt_lookup(&delegated_name, &resolver)?),
+            "no matching TXT record found at {} or at {} (delegated via {})",
+            record_name,
+            delegated_name,
+            challenge_name
+        );
+        proof_record_name = delegated_name;
+    }
+
+    debug!("verified dns domain link {} for {}", domain, asset_id);
+
+    Ok(proof_record_name)
+}
+
+// The name of the TXT record holding the DNS proofs. A single domain may link multiple assets,
+// with one TXT record per asset.
+pub fn dns_proof_record_name(domain: &str) -> String {
+    format!("_liquid-asset-proof.{}", domain.trim_end_matches('.'))
+}
+
+// The name of the CNAME record for delegating the DNS proofs to another zone, like ACME's
+// dns-01 delegation. The TXT records are then looked up at the CNAME target instead.
+pub fn dns_challenge_record_name(domain: &str) -> String {
+    format!("_liquid-asset-challenge.{}", domain.trim_end_matches('.'))
+}
+
+const DNS_TYPE_CNAME: u16 = 5;
+const DNS_TYPE_TXT: u16 = 16;
+
+#[derive(Deserialize)]
+struct DnsResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DnsAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DnsAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+fn txt_lookup(name: &str, resolver: &DnsResolver) -> Result<Vec<String>> {
+    Ok(dns_lookup(name, "TXT", DNS_TYPE_TXT, resolver)?
+        .iter()
+        .map(|data| parse_txt_data(data))
+        .collect())
+}
+
+// Resolvers report the TXT record data either as-is (Google), or as its quoted character-strings
+// (Cloudflare), like `"part one" "part two"` for records split into multiple strings. The strings
+// are joined back into the record's text, decoding the zone file escapes: `\DDD` for a byte in
+// decimal, and `\X` for any other character as-is.
+fn parse_txt_data(data: &str) -> String {
+    let data = data.trim();
+    if !data.starts_with('"') {
+        return data.to_string();
+    }
+
+    let mut text = vec![];
+    let mut quoted = false;
+    let mut chars = data.chars().peekable();
+    let push_char =
+        |text: &mut Vec<u8>, c: char| text.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => match chars.next() {
+                Some(digit) if digit.is_ascii_digit() => {
+                    let mut digits = digit.to_string();
+                    while digits.len() < 3 && chars.peek().map_or(false, char::is_ascii_digit) {
+                        digits.extend(chars.next());
+                    }
+                    match digits.parse::<u8>() {
+                        Ok(byte) if digits.len() == 3 => text.push(byte),
+                        // malformed, kept as-is
+                        _ => text.extend_from_slice(digits.as_bytes()),
+                    }
+                }
+                Some(c) => push_char(&mut text, c),
+                None => (),
+            },
+            c if quoted => push_char(&mut text, c),
+            // the whitespace separating the strings
+            _ => (),
+        }
+    }
+    // the escaped bytes may be the parts of multi-byte characters
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+// Get the CNAME target of `name`, if it has one
+fn cname_lookup(name: &str, resolver: &DnsResolver) -> Result<Option<String>> {
+    Ok(dns_lookup(name, "CNAME", DNS_TYPE_CNAME, resolver)?
+        .into_iter()
+        .next()
+        .map(|target| target.trim_end_matches('.').to_string()))
+}
+
+// Lookup DNS records using the resolver's DNS-over-HTTPS JSON API, returning the data of the
+// answers matching the record type
+fn dns_lookup(
+    name: &str,
+    record_type: &str,
+    record_type_code: u16,
+    resolver: &DnsResolver,
+) -> Result<Vec<String>> {
+    let resolver_url = if cfg!(any(test, feature = "dev")) {
+        "http://127.0.0.1:58712/resolve"
+    } else {
+        resolver.url()
+    };
+
+    let resp: DnsResponse = ReqClient::new()
+        .get(resolver_url)
+        .query(&[("name", name), ("type", record_type)])
+        // required by Cloudflare's resolver, which also serves the binary DNS wire format
+        .header(header::ACCEPT, "application/dns-json")
+        .send()
+        .context("failed querying dns resolver")?
+        .error_for_status()
+        .context("failed querying dns resolver")?
+        .json()
+        .context("invalid dns resolver response")?;
+
+    // NOERROR (0) and NXDOMAIN (3) are both valid responses, anything else indicates a failure
+    ensure!(
+        resp.status == 0 || resp.status == 3,
+        "dns lookup for {} failed with status {}",
+        name,
+        resp.status
+    );
+
+    Ok(resp
+        .answer
+        .into_iter()
+        .filter(|answer| answer.record_type == record_type_code)
+        .map(|answer| answer.data)
+        .collect())
+}
+
 // needs to be run with --test-threads 1
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::asset::Verification;
+    use crate::errors::join_err;
+    use crate::fixtures::{sign_message, test_key};
     use crate::util::BoolOpt;
+    use bitcoin_hashes::hex::FromHex;
     use rocket as r;
+    use rocket_contrib::json::JsonValue;
+    use std::io::Read;
     use std::path::PathBuf;
     use std::sync::Once;
+    use std::time::Duration;
 
     static SPAWN_ONCE: Once = Once::new();
 
+    // only published on the www alternate of test.dev
+    const WWW_PROOF_ASSET_ID: &str =
+        "c1d3b0e5a9f6a0e2a5c4f3d2e1b0a9f8e7d6c5b4a3928170f6e5d4c3b2a19080";
+
+    // published with a signed proof (by the fixtures test key) instead of the plaintext one
+    const SIGNED_PROOF_ASSET_ID: &str =
+        "6a8ee2a5e2b4fc1ed4d8e4bcab6c9f2f1d1f2c4b8a0a2ec0cd3c3b5a2bd6e7f1";
+
+    // the asset ids used in tests, published as DNS proofs by the mock dns resolver
+    const TEST_ASSET_IDS: [&str; 4] = [
+        "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05",
+        "cdcc515938c9b38d4312fcdb6001fc434596f1edb1fe09e51d319bd487dcaab8",
+        "455a7a5cf7a179dd5325968eb0319c1d182177930f8a70bfe61822d772b3783e",
+        "38dac0ec084ebc86cae69bd50ad1c46f1b9b6791dc77762e63baeb0548b0df69",
+    ];
+
     // a server that identifies as "test.dev" and verifies any requested asset id
     pub fn spawn_mock_verifier_server() {
         SPAWN_ONCE.call_once(|| {
@@ -99,22 +947,142 @@ pub mod tests {
                 .port(58712)
                 .finalize()
                 .unwrap();
-            let rocket = r::custom(config).mount("/", routes![verify_handler]);
+            let rocket = r::custom(config).mount(
+                "/",
+                routes![
+                    verify_handler,
+                    verify_v2_handler,
+                    resolve_handler,
+                    icon_handler,
+                    big_icon_handler,
+                    redirect_icon_handler
+                ],
+            );
 
             std::thread::spawn(|| rocket.launch());
         })
     }
 
-    #[get("/.well-known/<page>")]
-    fn verify_handler(page: String) -> Option<String> {
-        page.starts_with("liquid-asset-proof-")
-            .as_option()
-            .map(|_| {
+    struct IfNoneMatch(Option<String>);
+
+    impl<'a, 'r> r::request::FromRequest<'a, 'r> for IfNoneMatch {
+        type Error = ();
+
+        fn from_request(req: &'a r::Request<'r>) -> r::request::Outcome<Self, ()> {
+            let etag = req.headers().get_one("If-None-Match").map(String::from);
+            r::Outcome::Success(IfNoneMatch(etag))
+        }
+    }
+
+    #[get("/.well-known/<page>?<host>")]
+    fn verify_handler(
+        page: String,
+        host: Option<String>,
+        if_none_match: IfNoneMatch,
+    ) -> Option<r::Response<'static>> {
+        let www_only = page.ends_with(WWW_PROOF_ASSET_ID);
+        (page.starts_with("liquid-asset-proof-")
+            && (!www_only || host.as_deref() == Some("www.test.dev")))
+        .as_option()
+        .map(|_| {
+            let etag = format!("\"{}\"", &page[19..]);
+            if if_none_match.0.as_ref() == Some(&etag) {
+                return r::Response::build()
+                    .status(r::http::Status::NotModified)
+                    .finalize();
+            }
+            let mut body = format!(
+                "Authorize linking the domain name test.dev to the Liquid asset {}",
+                &page[19..]
+            );
+            if &page[19..] == SIGNED_PROOF_ASSET_ID {
+                body = base64::encode(&sign_message(&body));
+            }
+            r::Response::build()
+                .header(r::http::Header::new("ETag", etag))
+                .sized_body(std::io::Cursor::new(body))
+                .finalize()
+        })
+    }
+
+    // the v2 proof path, only published for the last test asset id. the v2 paths of the second
+    // and third ones are forbidden and answered with a catch-all page, respectively.
+    #[get("/.well-known/v2/<page>")]
+    fn verify_v2_handler(page: String) -> Option<r::Response<'static>> {
+        let asset_id = page.strip_prefix("liquid-asset-proof-")?;
+        let (status, body) = if asset_id == TEST_ASSET_IDS[3] {
+            (
+                r::http::Status::Ok,
                 format!(
                     "Authorize linking the domain name test.dev to the Liquid asset {}",
-                    &page[19..]
-                )
-            })
+                    asset_id
+                ),
+            )
+        } else if asset_id == TEST_ASSET_IDS[1] {
+            (r::http::Status::Forbidden, "forbidden".to_string())
+        } else if asset_id == TEST_ASSET_IDS[2] {
+            (r::http::Status::Ok, "<html>welcome</html>".to_string())
+        } else {
+            return None;
+        };
+        Some(
+            r::Response::build()
+                .status(status)
+                .sized_body(std::io::Cursor::new(body))
+                .finalize(),
+        )
+    }
+
+    // the asset icon served at /icon.png
+    pub const MOCK_ICON: &[u8] = b"\x89PNG\r\n\x1a\nnot really an icon";
+
+    #[get("/icon.png")]
+    fn icon_handler() -> Vec<u8> {
+        MOCK_ICON.to_vec()
+    }
+
+    // an icon over the maximum size
+    #[get("/big-icon.png")]
+    fn big_icon_handler() -> Vec<u8> {
+        vec![0; 300 * 1024]
+    }
+
+    // a redirect to the icon, which must not be followed
+    #[get("/redirect-icon.png")]
+    fn redirect_icon_handler() -> r::response::Redirect {
+        r::response::Redirect::to("/icon.png")
+    }
+
+    // a dns-over-https resolver with TXT records for "test.dev", and for "delegated.dev" via
+    // a CNAME delegation to "delegated-dev.proofs.test"
+    #[get("/resolve?<name>")]
+    fn resolve_handler(name: String) -> JsonValue {
+        let proof_records = |domain: &str| -> Vec<serde_json::Value> {
+            TEST_ASSET_IDS
+                .iter()
+                .map(|asset_id| {
+                    json!({
+                        "name": name,
+                        "type": DNS_TYPE_TXT,
+                        "data": format!(
+                            "Authorize linking the domain name {} to the Liquid asset {}",
+                            domain, asset_id
+                        )
+                    })
+                })
+                .collect()
+        };
+        let answers: Vec<serde_json::Value> = match name.as_str() {
+            "_liquid-asset-proof.test.dev" => proof_records("test.dev"),
+            "delegated-dev.proofs.test" => proof_records("delegated.dev"),
+            "_liquid-asset-challenge.delegated.dev" => vec![json!({
+                "name": name,
+                "type": DNS_TYPE_CNAME,
+                "data": "delegated-dev.proofs.test."
+            })],
+            _ => vec![],
+        };
+        JsonValue::from(json!({ "Status": 0, "Answer": answers }))
     }
 
     #[test]
@@ -127,6 +1095,509 @@ pub mod tests {
     fn test1_verify_domain_link() {
         let asset = Asset::load(PathBuf::from("test/asset-b1405e.json")).unwrap();
         // expects https://test.dev/ to forward requests to a local web server
-        verify_domain_link(&asset, "test.dev").expect("failed verifying domain name");
+        let validators = verify_domain_link_http(&asset, "test.dev", &ValidationConfig::default())
+            .expect("failed verifying domain name");
+        assert_eq!(validators.version, HttpProofVersion::V1);
+        assert_eq!(validators.host, "test.dev");
+    }
+
+    #[test]
+    fn test1_verify_domain_link_v2() -> Result<()> {
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        asset.asset_id = AssetId::from_hex(TEST_ASSET_IDS[3])?;
+
+        // the newest proof path is preferred
+        assert_eq!(
+            verify_domain_link_http(&asset, "test.dev", &ValidationConfig::default())?.version,
+            HttpProofVersion::V2
+        );
+
+        // validators from another version aren't used for conditional requests
+        let v1_validators = ProofValidators {
+            host: "test.dev".to_string(),
+            version: HttpProofVersion::V1,
+            etag: Some(format!("\"{}\"", TEST_ASSET_IDS[3])),
+            last_modified: None,
+            tls_certificate: None,
+        };
+        match reverify_domain_link_http(&asset, Some(&v1_validators), &ValidationConfig::default())?
+        {
+            HttpProofStatus::Verified(validators) => {
+                assert_eq!(validators.version, HttpProofVersion::V2)
+            }
+            status => bail!("unexpected status {:?}", status),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test1_verify_domain_link_v2_fallback() -> Result<()> {
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+
+        // a forbidden v2 page, and a catch-all page served for the v2 path
+        for asset_id in &TEST_ASSET_IDS[1..3] {
+            asset.asset_id = AssetId::from_hex(asset_id)?;
+            assert_eq!(
+                verify_domain_link_http(&asset, "test.dev", &ValidationConfig::default())?.version,
+                HttpProofVersion::V1
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test1_verify_domain_link_www_fallback() -> Result<()> {
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        asset.asset_id = AssetId::from_hex(WWW_PROOF_ASSET_ID)?;
+
+        // the alternate host is only tried with the fallback enabled
+        let err = verify_asset_link(&asset, &ValidationConfig::default()).unwrap_err();
+        assert!(
+            join_err(&err).contains("verification page not found"),
+            "{}",
+            join_err(&err)
+        );
+
+        let config = ValidationConfig {
+            http_proof_www_fallback: true,
+            ..Default::default()
+        };
+        let link = verify_asset_link(&asset, &config)?;
+        asset.record_verification(
+            Verification {
+                issuance: None,
+                link,
+            },
+            &config,
+        );
+        assert_eq!(asset.http_proof_host.as_deref(), Some("www.test.dev"));
+        assert_eq!(asset.http_proof_version, Some(HttpProofVersion::V1));
+
+        // and re-verified at the host that served it
+        match reverify_domain_link_http(&asset, None, &config)? {
+            HttpProofStatus::Verified(validators) => assert_eq!(validators.host, "www.test.dev"),
+            status => bail!("unexpected status {:?}", status),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test1_verify_domain_link_signed() -> Result<()> {
+        let key = test_key();
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        asset.asset_id = AssetId::from_hex(SIGNED_PROOF_ASSET_ID)?;
+
+        // the signature must be by the asset's issuer
+        let err =
+            verify_domain_link_http(&asset, "test.dev", &ValidationConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("contents mismatch"));
+
+        asset.fields.issuer_pubkey = key.public_key(&Secp256k1::signing_only()).to_bytes();
+        assert_eq!(
+            verify_domain_link_http(&asset, "test.dev", &ValidationConfig::default())?.version,
+            HttpProofVersion::V1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test1_reverify_domain_link() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+
+        let validators =
+            match reverify_domain_link_http(&asset, None, &ValidationConfig::default())? {
+                HttpProofStatus::Verified(validators) => validators,
+                status => bail!("unexpected status {:?}", status),
+            };
+        assert_eq!(
+            validators.etag,
+            Some(format!("\"{}\"", asset.id().to_hex()))
+        );
+
+        // an unmodified page is not re-downloaded
+        assert_eq!(
+            reverify_domain_link_http(&asset, Some(&validators), &ValidationConfig::default())?,
+            HttpProofStatus::Unchanged
+        );
+
+        // a modified page is fully re-verified
+        let stale = ProofValidators {
+            host: "test.dev".to_string(),
+            version: HttpProofVersion::V1,
+            etag: Some("\"stale\"".to_string()),
+            last_modified: None,
+            tls_certificate: None,
+        };
+        assert_eq!(
+            reverify_domain_link_http(&asset, Some(&stale), &ValidationConfig::default())?,
+            HttpProofStatus::Verified(validators)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test1_tls_pins() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        let body = expected_proof_body("test.dev", asset.id());
+        let (port, requests) = crate::tls::tests::spawn_mock_tls_server(format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))?;
+        let page_url = format!("https://localhost:{}{}", port, http_proof_path(asset.id()));
+        let fetch = |config: &ValidationConfig| {
+            fetch_proof_page(
+                &asset,
+                "test.dev",
+                &page_url,
+                "localhost",
+                HttpProofVersion::V1,
+                None,
+                config,
+            )
+        };
+        let pinned_config = |sha256: &str| ValidationConfig {
+            tls_pins: vec![format!("localhost={}", sha256).parse().unwrap()],
+            ..Default::default()
+        };
+
+        // the certificate of the session that served the proof is recorded
+        match fetch(&pinned_config(crate::tls::tests::MOCK_CERT_SHA256))? {
+            Some(HttpProofStatus::Verified(validators)) => assert_eq!(
+                validators.tls_certificate.unwrap().sha256,
+                crate::tls::tests::MOCK_CERT_SHA256
+            ),
+            status => bail!("unexpected status {:?}", status),
+        }
+        requests.recv()?;
+
+        // a mismatched pin fails the verification without requesting the proof
+        let err = fetch(&pinned_config(&"00".repeat(32))).unwrap_err();
+        assert!(
+            join_err(&err).contains("doesn't match the pinned fingerprints"),
+            "{}",
+            join_err(&err)
+        );
+        assert!(requests.recv_timeout(Duration::from_millis(250)).is_err());
+
+        // pins for other hosts don't apply
+        let config = ValidationConfig {
+            tls_pins: vec![format!("test.dev={}", "00".repeat(32)).parse()?],
+            record_tls_certificate: true,
+            ..Default::default()
+        };
+        match fetch(&config)? {
+            Some(HttpProofStatus::Verified(validators)) => {
+                assert!(validators.tls_certificate.is_some())
+            }
+            status => bail!("unexpected status {:?}", status),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test1_proof_redirects() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        let (port, _requests) = crate::tls::tests::spawn_mock_tls_server(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /moved\r\nContent-Length: 0\r\n\r\n"
+                .to_string(),
+        )?;
+        let fetch = |page_url: &str, host: &str, config: &ValidationConfig| {
+            fetch_proof_page(
+                &asset,
+                "test.dev",
+                page_url,
+                host,
+                HttpProofVersion::V1,
+                None,
+                config,
+            )
+        };
+
+        // redirects are rejected by both the HTTP client and the certificate recording path
+        let err = fetch(
+            "http://127.0.0.1:58712/redirect-icon.png",
+            "127.0.0.1",
+            &ValidationConfig::default(),
+        )
+        .unwrap_err();
+        assert!(
+            join_err(&err).contains("redirected to /icon.png"),
+            "{}",
+            join_err(&err)
+        );
+
+        let config = ValidationConfig {
+            record_tls_certificate: true,
+            ..Default::default()
+        };
+        let page_url = format!("https://localhost:{}{}", port, http_proof_path(asset.id()));
+        let err = fetch(&page_url, "localhost", &config).unwrap_err();
+        assert!(
+            join_err(&err).contains("redirected to /moved"),
+            "{}",
+            join_err(&err)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test1_reverify_rebuilds_proof_url() -> Result<()> {
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        asset.http_proof_version = Some(HttpProofVersion::V1);
+        asset.proof_locations = vec![ProofLocation {
+            method: DomainVerificationMethod::Http,
+            location: http_proof_url("test.dev", HttpProofVersion::V1, asset.id()),
+        }];
+        match reverify_domain_link_http(&asset, None, &ValidationConfig::default())? {
+            HttpProofStatus::Verified(validators) => {
+                assert_eq!(validators.version, HttpProofVersion::V1)
+            }
+            status => bail!("unexpected status {:?}", status),
+        }
+
+        // the recorded location is fetched even without a recorded version
+        asset.http_proof_version = None;
+        asset.proof_locations[0].location =
+            http_proof_url("test.dev", HttpProofVersion::V2, asset.id());
+        let err =
+            reverify_domain_link_http(&asset, None, &ValidationConfig::default()).unwrap_err();
+        assert!(
+            err.to_string().contains("/.well-known/v2/"),
+            "{}",
+            err.to_string()
+        );
+
+        // a location that isn't a proof url of the domain is ignored, the url is rebuilt from the
+        // domain and version
+        asset.http_proof_version = Some(HttpProofVersion::V1);
+        asset.proof_locations[0].location = "http://127.0.0.1:58712/.well-known/missing.txt".into();
+        match reverify_domain_link_http(&asset, None, &ValidationConfig::default())? {
+            HttpProofStatus::Verified(validators) => {
+                assert_eq!(validators.version, HttpProofVersion::V1)
+            }
+            status => bail!("unexpected status {:?}", status),
+        }
+
+        // the recorded host must be the domain or its www alternate
+        asset.http_proof_host = Some("www.test.dev".into());
+        reverify_domain_link_http(&asset, None, &ValidationConfig::default())?;
+        asset.http_proof_host = Some("attacker.example".into());
+        let err =
+            reverify_domain_link_http(&asset, None, &ValidationConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("doesn't match the domain"));
+        Ok(())
+    }
+
+    #[test]
+    fn test1_onion_tor_proxy() -> Result<()> {
+        // a mock socks proxy, reporting the protocol version of each connecting client and then
+        // dropping the connection (failing the request)
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let config = ValidationConfig {
+            tor_proxy: Some(listener.local_addr()?.to_string()),
+            ..Default::default()
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut greeting = [0u8; 2];
+                stream.unwrap().read_exact(&mut greeting).unwrap();
+                sender.send(greeting[0]).unwrap();
+            }
+        });
+
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+
+        // onion domains are fetched through the proxy
+        assert!(verify_domain_link_http(&asset, "registrytest.onion", &config).is_err());
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5))?, 5);
+
+        // other domains aren't
+        verify_domain_link_http(&asset, "test.dev", &config)?;
+        assert!(receiver.try_recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_domain_proofs() -> Result<()> {
+        let asset_ids = [
+            AssetId::from_hex(TEST_ASSET_IDS[0])?,
+            AssetId::from_hex(TEST_ASSET_IDS[1])?,
+        ];
+        let proofs = domain_proofs("test.dev", &asset_ids)?;
+        assert_eq!(proofs.len(), 2);
+        assert_eq!(proofs[1].asset_id, asset_ids[1]);
+        assert_eq!(
+            proofs[1].body,
+            format!(
+                "Authorize linking the domain name test.dev to the Liquid asset {}",
+                TEST_ASSET_IDS[1]
+            )
+        );
+        assert_eq!(
+            proofs[1].http_path,
+            format!("/.well-known/liquid-asset-proof-{}", TEST_ASSET_IDS[1])
+        );
+        assert_eq!(proofs[1].dns_record_name, "_liquid-asset-proof.test.dev");
+
+        assert!(domain_proofs("foo bar", &asset_ids).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_www_alternate() {
+        assert_eq!(www_alternate("test.dev"), "www.test.dev");
+        assert_eq!(www_alternate("www.test.dev"), "test.dev");
+        assert_eq!(www_alternate("sub.test.dev"), "www.sub.test.dev");
+    }
+
+    #[test]
+    fn test1_http_proof_matches() {
+        let expected = "Authorize linking the domain name test.dev to the Liquid asset 00";
+
+        assert!(http_proof_matches(expected, expected));
+        assert!(http_proof_matches(&format!("{}\n", expected), expected));
+        assert!(http_proof_matches(&format!("{}\r\n", expected), expected));
+        assert!(http_proof_matches(&format!("  \r\n{}", expected), expected));
+        assert!(http_proof_matches(
+            &format!("\u{feff}{}\n", expected),
+            expected
+        ));
+
+        assert!(!http_proof_matches(&format!("{}0", expected), expected));
+        assert!(!http_proof_matches(&format!("{}\nfoo", expected), expected));
+        assert!(!http_proof_matches(&format!("x{}", expected), expected));
+    }
+
+    #[test]
+    fn test2_verify_domain_link_dns() {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json")).unwrap();
+        let config = ValidationConfig::default();
+        verify_domain_link_dns(&asset, "test.dev", &config).expect("failed verifying domain name");
+        assert!(verify_domain_link_dns(&asset, "other.dev", &config).is_err());
+    }
+
+    #[test]
+    fn test2_dns_resolver() -> Result<()> {
+        assert_eq!("google".parse::<DnsResolver>()?, DnsResolver::Google);
+        assert_eq!(
+            "cloudflare".parse::<DnsResolver>()?,
+            DnsResolver::Cloudflare
+        );
+        assert_eq!(
+            "https://dns.quad9.net:5053/dns-query"
+                .parse::<DnsResolver>()?
+                .url(),
+            "https://dns.quad9.net:5053/dns-query"
+        );
+        assert!("http://dns.example.com/resolve"
+            .parse::<DnsResolver>()
+            .is_err());
+        assert!("quad9".parse::<DnsResolver>().is_err());
+
+        // the TXT data shapes reported by Google and Cloudflare
+        let record = "Authorize linking the domain name test.dev to the Liquid asset b1405e";
+        assert_eq!(parse_txt_data(record), record);
+        assert_eq!(parse_txt_data(&format!("\"{}\"", record)), record);
+        assert_eq!(
+            parse_txt_data(
+                r#""Authorize linking the domain name test.dev " "to the Liquid asset b1405e""#
+            ),
+            record
+        );
+        assert_eq!(parse_txt_data(r#""say \"hi\"""#), r#"say "hi""#);
+        // decimal escapes, including the bytes of multi-byte characters
+        assert_eq!(parse_txt_data(r#""a\032b\059c""#), "a b;c");
+        assert_eq!(parse_txt_data(r#""caf\195\169""#), "caf\u{e9}");
+        assert_eq!(parse_txt_data(r#""\256\12x""#), "25612x");
+        Ok(())
+    }
+
+    #[test]
+    fn test2_verify_domain_link_dns_delegated() {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json")).unwrap();
+        verify_domain_link_dns(&asset, "delegated.dev", &ValidationConfig::default())
+            .expect("failed verifying domain name");
+    }
+
+    #[test]
+    fn test3_describe_verification_methods() {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json")).unwrap();
+        let desc =
+            describe_verification_methods(DomainVerificationMethod::Http, "test.dev", asset.id());
+
+        assert_eq!(desc["default"], "http");
+        assert_eq!(
+            desc["templates"]["http"]["url"],
+            "https://{domain}/.well-known/liquid-asset-proof-{asset_id}"
+        );
+        assert_eq!(
+            desc["templates"]["dns"]["name"],
+            "_liquid-asset-proof.{domain}"
+        );
+        assert_eq!(
+            desc["example"]["proofs"]["http"]["body"],
+            expected_proof_body("test.dev", asset.id())
+        );
+        assert_eq!(
+            desc["example"]["proofs"]["dns"]["delegation"]["name"],
+            "_liquid-asset-challenge.test.dev"
+        );
+    }
+
+    #[test]
+    fn test3_verification_methods() {
+        let mut asset = Asset::load(PathBuf::from("test/asset-b1405e.json")).unwrap();
+        let mut config = ValidationConfig::default();
+
+        assert_eq!(
+            verification_methods(&asset, &config),
+            vec![DomainVerificationMethod::Http]
+        );
+
+        config.default_verification_method = Some(DomainVerificationMethod::Dns);
+        assert_eq!(
+            verification_methods(&asset, &config),
+            vec![DomainVerificationMethod::Dns]
+        );
+
+        // the asset's own method takes precedence over the default
+        asset.domain_verification_method = Some(DomainVerificationMethod::Http);
+        assert_eq!(
+            verification_methods(&asset, &config),
+            vec![DomainVerificationMethod::Http]
+        );
+        config.default_verification_method = None;
+
+        asset.domain_verification_method = Some(DomainVerificationMethod::Dns);
+        assert_eq!(
+            verification_methods(&asset, &config),
+            vec![DomainVerificationMethod::Dns]
+        );
+
+        config.require_both_proofs = true;
+        assert_eq!(
+            verification_methods(&asset, &config),
+            vec![
+                DomainVerificationMethod::Http,
+                DomainVerificationMethod::Dns
+            ]
+        );
+
+        let link = verify_asset_link(&asset, &config).expect("failed verifying both proofs");
+        assert_eq!(
+            link.proofs,
+            vec![
+                ProofLocation {
+                    method: DomainVerificationMethod::Http,
+                    location: http_proof_url("test.dev", HttpProofVersion::V1, asset.id()),
+                },
+                ProofLocation {
+                    method: DomainVerificationMethod::Dns,
+                    location: "_liquid-asset-proof.test.dev".to_string(),
+                },
+            ]
+        );
     }
 }
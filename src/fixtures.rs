@@ -0,0 +1,220 @@
+// Generates synthetic issuance transactions and matching asset json, for writing tests without
+// hand-crafting the files under test/
+
+use std::{fs, path};
+
+use bitcoin::util::misc::signed_msg_hash;
+use bitcoin::{PrivateKey, Txid};
+use bitcoin_hashes::{hex::ToHex, Hash};
+use elements::{encode::deserialize, AssetId, OutPoint, Transaction};
+use secp256k1::Secp256k1;
+use serde_json::Value;
+
+use crate::asset::{contract_json_hash, Asset};
+use crate::chain::ChainQuery;
+use crate::errors::{OptionExt, Result};
+use crate::registry::{Registry, RegistryConfig, DIR_PARTITION_LEN};
+
+// The key fixtures are issued with, also used for signing in tests
+pub fn test_key() -> PrivateKey {
+    PrivateKey::from_wif("cRmFPw94iHgnmUMui5brPsbH5F7wNmvgVkAGJYqZaK33F5vzCAST").unwrap()
+}
+
+// Sign a message with the test key, as a compact signature over the bitcoin signed message hash
+// (the format of contract, deletion and proof signatures)
+pub fn sign_message(msg: &str) -> Vec<u8> {
+    let msg_secp = secp256k1::Message::from_slice(&signed_msg_hash(msg).into_inner()).unwrap();
+    Secp256k1::signing_only()
+        .sign(&msg_secp, &test_key().key)
+        .serialize_compact()
+        .to_vec()
+}
+
+pub struct Fixture {
+    pub asset: Asset,
+    pub tx: Transaction,
+}
+
+impl Fixture {
+    // Create an asset with the given name linked to test.dev, issued by the test key. The name is
+    // also used as the seed.
+    pub fn named(name: &str) -> Result<Self> {
+        let contract = json!({
+            "entity": { "domain": "test.dev" },
+            "name": name,
+            "version": 0
+        });
+        Self::new(contract, &test_key(), name.as_bytes())
+    }
+
+    // Create an asset issued with the given contract. The issuer_pubkey is set from the signing
+    // key, and the seed is used to derive a unique issuance prevout.
+    pub fn new(mut contract: Value, key: &PrivateKey, seed: &[u8]) -> Result<Self> {
+        let issuer_pubkey = key.public_key(&Secp256k1::signing_only()).to_string();
+        contract
+            .as_object_mut()
+            .or_err("contract must be an object")?
+            .insert("issuer_pubkey".into(), issuer_pubkey.into());
+
+        let contract_hash = contract_json_hash(&contract)?;
+        let prevout = OutPoint::new(Txid::hash(seed), 0);
+        let asset_id =
+            AssetId::from_entropy(AssetId::generate_asset_entropy(prevout, contract_hash));
+
+        let tx: Transaction = deserialize(&issuance_tx_bytes(
+            &prevout,
+            &contract_hash.into_inner(),
+            1000,
+        ))?;
+
+        // the contract fields are duplicated at the top level, like in stored assets
+        let mut asset_json = json!({
+            "asset_id": asset_id,
+            "contract": contract,
+            "issuance_txin": { "txid": tx.txid(), "vin": 0 },
+            "issuance_prevout": prevout,
+        });
+        for (field, value) in contract.as_object().req()? {
+            asset_json[field] = value.clone();
+        }
+
+        Ok(Fixture {
+            asset: serde_json::from_value(asset_json)?,
+            tx,
+        })
+    }
+
+    // Write the files in the layout expected by the mock esplora server
+    pub fn write(&self, dir: &path::Path) -> Result<()> {
+        let asset_id = self.asset.asset_id.to_hex();
+        let txid = self.tx.txid().to_hex();
+        fs::write(
+            dir.join(format!("asset-{}.json", &asset_id[..6])),
+            serde_json::to_string(&self.asset)?,
+        )?;
+        fs::write(
+            dir.join(format!("issuance-tx-{}.hex", &txid[..6])),
+            hex::encode(elements::encode::serialize(&self.tx)),
+        )?;
+        Ok(())
+    }
+}
+
+// A temporary database directory, removed when dropped (also when the test fails)
+pub struct TestDb {
+    pub directory: path::PathBuf,
+}
+
+impl TestDb {
+    // An empty directory, unique to the test name and the process
+    pub fn new(name: &str) -> Result<Self> {
+        let directory = std::env::temp_dir().join(format!(
+            "asset-registry-testdb-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::remove_dir_all(&directory).ok();
+        fs::create_dir_all(&directory)?;
+        Ok(TestDb { directory })
+    }
+
+    // A registry over the directory, without a chain backend or hooks
+    pub fn registry(&self, config: RegistryConfig) -> Registry {
+        Registry::new(
+            &self.directory,
+            ChainQuery::new(String::new()),
+            None,
+            config,
+        )
+    }
+
+    // Write the asset json to its file under the partition directory, like another process
+    // sharing the directory would, returning its path
+    pub fn write_asset(&self, asset: &Value) -> Result<path::PathBuf> {
+        let asset_id = asset["asset_id"].as_str().req()?;
+        let dir = self.directory.join(&asset_id[0..DIR_PARTITION_LEN]);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", asset_id));
+        fs::write(&path, asset.to_string())?;
+        Ok(path)
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.directory).ok();
+    }
+}
+
+// The id of test/asset-b1405e.json
+pub const TEST_ASSET_ID: &str = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+
+// The test/asset-b1405e.json asset, as json with the given asset id. Its commitment doesn't match
+// other ids, which is fine for registries that trust their files.
+pub fn test_asset_json(asset_id: &str) -> Result<Value> {
+    let mut asset: Value = serde_json::from_str(&fs::read_to_string("test/asset-b1405e.json")?)?;
+    asset["asset_id"] = asset_id.into();
+    Ok(asset)
+}
+
+// Serialize a transaction with a single issuance input and a single explicit output
+fn issuance_tx_bytes(prevout: &OutPoint, entropy: &[u8; 32], amount: u64) -> Vec<u8> {
+    let mut tx = vec![];
+    tx.extend(&2u32.to_le_bytes()); // version
+    tx.push(0); // no witness
+
+    tx.push(1); // input count
+    tx.extend(&prevout.txid.into_inner());
+    tx.extend(&(prevout.vout | 1 << 31).to_le_bytes()); // vout, with the issuance flag
+    tx.push(0); // empty script_sig
+    tx.extend(&0xffff_ffffu32.to_le_bytes()); // sequence
+    tx.extend(&[0u8; 32]); // asset blinding nonce, zero for new issuances
+    tx.extend(entropy); // asset entropy, the contract hash for new issuances
+    tx.push(1); // explicit issued amount
+    tx.extend(&amount.to_be_bytes());
+    tx.push(0); // no reissuance tokens
+
+    tx.push(1); // output count
+    tx.push(1); // explicit asset
+    tx.extend(&[0u8; 32]);
+    tx.push(1); // explicit value
+    tx.extend(&amount.to_be_bytes());
+    tx.push(0); // no nonce
+    tx.push(0); // empty script_pubkey
+
+    tx.extend(&0u32.to_le_bytes()); // lock time
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::ValidationConfig;
+
+    #[test]
+    fn test_fixture() -> Result<()> {
+        let fixture = Fixture::new(
+            json!({
+                "entity": { "domain": "test.dev" },
+                "name": "Fixture coin",
+                "ticker": "FIX",
+                "version": 0
+            }),
+            &test_key(),
+            b"fixture",
+        )?;
+
+        let txin = &fixture.tx.input[0];
+        assert!(txin.has_issuance());
+        assert_eq!(txin.previous_output, fixture.asset.issuance_prevout);
+        assert_eq!(
+            txin.asset_issuance.asset_entropy,
+            fixture.asset.contract_hash()?.into_inner()
+        );
+        assert_eq!(fixture.asset.fields.ticker, Some("FIX".to_string()));
+
+        fixture.asset.verify_offline(&ValidationConfig::default())?;
+
+        Ok(())
+    }
+}
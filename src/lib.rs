@@ -2,13 +2,18 @@
 
 extern crate base64;
 extern crate bitcoin;
+extern crate chrono;
 extern crate elements;
+extern crate libc;
+extern crate native_tls;
 extern crate secp256k1;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+#[cfg(feature = "server")]
+extern crate serde_urlencoded;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -19,6 +24,8 @@ extern crate regex;
 
 #[cfg(feature = "server")]
 extern crate hyper;
+#[cfg(feature = "server")]
+extern crate notify;
 #[cfg(feature = "cli")]
 extern crate structopt;
 
@@ -34,7 +41,17 @@ pub mod chain;
 pub mod client;
 pub mod entity;
 pub mod errors;
+#[cfg(test)]
+pub mod fixtures;
+pub mod metrics;
+pub mod psl;
+pub mod queue;
+#[cfg(feature = "server")]
+pub mod ratelimit;
 pub mod registry;
+pub mod schema;
 #[cfg(feature = "server")]
 pub mod server;
+pub mod tls;
 pub mod util;
+pub mod webhook;
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// cap on the number of distinct (domain, reason) pairs tracked, to bound memory usage. the reasons
+// are a fixed set of codes, but the domains are chosen by the issuers.
+const MAX_FAILURE_ENTRIES: usize = 10_000;
+
+// cap on the number of distinct endpoints tracked, see MAX_FAILURE_ENTRIES
+const MAX_ENDPOINT_ENTRIES: usize = 100;
+
+// In-memory counters for operational monitoring, served by the server at /metrics
+#[derive(Debug, Default)]
+pub struct Metrics {
+    verification_failures: Mutex<HashMap<(String, String), usize>>,
+    request_latencies: Mutex<HashMap<String, Latency>>,
+}
+
+#[derive(Debug, Default)]
+struct Latency {
+    count: usize,
+    total: Duration,
+    max: Duration,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct VerificationFailures {
+    pub domain: String,
+    pub reason: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct EndpointLatency {
+    pub endpoint: String,
+    pub count: usize,
+    pub mean_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Metrics {
+    pub fn record_verification_failure(&self, domain: &str, reason: &str) {
+        let mut failures = self.verification_failures.lock().unwrap();
+        let key = (domain.to_string(), reason.to_string());
+        if failures.len() >= MAX_FAILURE_ENTRIES && !failures.contains_key(&key) {
+            return;
+        }
+        *failures.entry(key).or_insert(0) += 1;
+    }
+
+    // The verification failures grouped by domain and reason, most frequent first
+    pub fn verification_failures(&self) -> Vec<VerificationFailures> {
+        let failures = self.verification_failures.lock().unwrap();
+        let mut failures: Vec<VerificationFailures> = failures
+            .iter()
+            .map(|((domain, reason), count)| VerificationFailures {
+                domain: domain.clone(),
+                reason: reason.clone(),
+                count: *count,
+            })
+            .collect();
+        failures.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| (&a.domain, &a.reason).cmp(&(&b.domain, &b.reason)))
+        });
+        failures
+    }
+
+    // Record the time taken to handle a request, by its endpoint (like `GET /:asset_id`)
+    pub fn record_request(&self, endpoint: &str, elapsed: Duration) {
+        let mut latencies = self.request_latencies.lock().unwrap();
+        if latencies.len() >= MAX_ENDPOINT_ENTRIES && !latencies.contains_key(endpoint) {
+            return;
+        }
+        let latency = latencies.entry(endpoint.to_string()).or_default();
+        latency.count += 1;
+        latency.total += elapsed;
+        latency.max = latency.max.max(elapsed);
+    }
+
+    // The request latencies of each endpoint, slowest (on average) first
+    pub fn request_latencies(&self) -> Vec<EndpointLatency> {
+        let latencies = self.request_latencies.lock().unwrap();
+        let mut latencies: Vec<EndpointLatency> = latencies
+            .iter()
+            .map(|(endpoint, latency)| EndpointLatency {
+                endpoint: endpoint.clone(),
+                count: latency.count,
+                // computed in u128 millis, as the count may not fit the u32 Duration divisor
+                mean_ms: latency
+                    .total
+                    .as_millis()
+                    .checked_div(latency.count as u128)
+                    .unwrap_or(0) as u64,
+                max_ms: latency.max.as_millis() as u64,
+            })
+            .collect();
+        latencies.sort_by(|a, b| {
+            b.mean_ms
+                .cmp(&a.mean_ms)
+                .then_with(|| a.endpoint.cmp(&b.endpoint))
+        });
+        latencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verification_failures() {
+        let metrics = Metrics::default();
+        metrics.record_verification_failure("foo.com", "linked_entity");
+        metrics.record_verification_failure("bar.com", "issuance_commitment");
+        metrics.record_verification_failure("foo.com", "linked_entity");
+
+        assert_eq!(
+            metrics.verification_failures(),
+            vec![
+                VerificationFailures {
+                    domain: "foo.com".to_string(),
+                    reason: "linked_entity".to_string(),
+                    count: 2,
+                },
+                VerificationFailures {
+                    domain: "bar.com".to_string(),
+                    reason: "issuance_commitment".to_string(),
+                    count: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_request_latencies() {
+        let metrics = Metrics::default();
+        metrics.record_request("GET /:asset_id", Duration::from_millis(10));
+        metrics.record_request("POST /", Duration::from_millis(900));
+        metrics.record_request("GET /:asset_id", Duration::from_millis(30));
+
+        assert_eq!(
+            metrics.request_latencies(),
+            vec![
+                EndpointLatency {
+                    endpoint: "POST /".to_string(),
+                    count: 1,
+                    mean_ms: 900,
+                    max_ms: 900,
+                },
+                EndpointLatency {
+                    endpoint: "GET /:asset_id".to_string(),
+                    count: 2,
+                    mean_ms: 20,
+                    max_ms: 30,
+                },
+            ]
+        );
+    }
+}
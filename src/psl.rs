@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::errors::{OptionExt, Result, ResultExt};
+
+// A matcher for the Public Suffix List (https://publicsuffix.org/list/public_suffix_list.dat),
+// used to verify that entity domains are registrable under a known public suffix.
+#[derive(Debug, Default)]
+pub struct SuffixList {
+    rules: HashSet<String>,
+    // stored without the leading "*."
+    wildcards: HashSet<String>,
+    // stored without the leading "!"
+    exceptions: HashSet<String>,
+}
+
+impl SuffixList {
+    pub fn parse(list: &str) -> Self {
+        let mut suffixes = SuffixList::default();
+        for line in list.lines() {
+            // rules end at the first whitespace, anything after it is a comment
+            let rule = match line.split_whitespace().next() {
+                Some(rule) if !rule.starts_with("//") => rule,
+                _ => continue,
+            };
+            let (set, rule) = if rule.starts_with('!') {
+                (&mut suffixes.exceptions, &rule[1..])
+            } else if rule.starts_with("*.") {
+                (&mut suffixes.wildcards, &rule[2..])
+            } else {
+                (&mut suffixes.rules, rule)
+            };
+            // the list contains IDNA rules in their unicode form, domains are matched in punycode
+            if let Ok(rule) = idna::domain_to_ascii(rule) {
+                set.insert(rule);
+            }
+        }
+        suffixes
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let list = fs::read_to_string(path).context(format!(
+            "failed reading public suffix list {}",
+            path.display()
+        ))?;
+        Ok(Self::parse(&list))
+    }
+
+    // Get the public suffix of `domain`, or None if its TLD isn't a known public suffix
+    pub fn public_suffix(&self, domain: &str) -> Option<String> {
+        let labels: Vec<&str> = domain.trim_end_matches('.').split('.').collect();
+
+        // look for the longest matching rule, with exceptions taking precedence over wildcards
+        for i in 0..labels.len() {
+            let candidate = labels[i..].join(".");
+            if self.exceptions.contains(&candidate) {
+                return Some(labels[i + 1..].join("."));
+            }
+            if self.rules.contains(&candidate)
+                || (i + 1 < labels.len() && self.wildcards.contains(&labels[i + 1..].join(".")))
+            {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    // Verify that `domain` is under a known public suffix and has at least one label beneath it
+    pub fn verify_domain(&self, domain: &str) -> Result<()> {
+        let suffix = self
+            .public_suffix(domain)
+            .or_err("unknown top-level domain")?;
+        let domain = domain.trim_end_matches('.');
+        ensure!(
+            domain.len() > suffix.len(),
+            "must be registrable under the public suffix {}",
+            suffix
+        );
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref LOADED: Mutex<Option<(PathBuf, SystemTime, Arc<SuffixList>)>> = Mutex::new(None);
+}
+
+// Get the suffix list stored at `path`. The parsed list is kept in memory and reloaded
+// whenever the file's modification time changes, so the list can be updated in place.
+pub fn load_cached(path: &Path) -> Result<Arc<SuffixList>> {
+    let mtime = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .context(format!(
+            "failed reading public suffix list {}",
+            path.display()
+        ))?;
+
+    let mut loaded = LOADED.lock().unwrap();
+    if let Some((loaded_path, loaded_mtime, list)) = &*loaded {
+        if loaded_path == path && *loaded_mtime == mtime {
+            return Ok(list.clone());
+        }
+    }
+
+    let list = Arc::new(SuffixList::load(path)?);
+    *loaded = Some((path.to_path_buf(), mtime, list.clone()));
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_suffix() -> Result<()> {
+        let list = SuffixList::load(Path::new("test/public_suffix_list.dat"))?;
+
+        assert_eq!(list.public_suffix("foo.com"), Some("com".to_string()));
+        assert_eq!(list.public_suffix("foo.co.uk."), Some("co.uk".to_string()));
+        assert_eq!(list.public_suffix("foo.bar.ck"), Some("bar.ck".to_string()));
+        assert_eq!(list.public_suffix("www.ck"), Some("ck".to_string()));
+        assert_eq!(
+            list.public_suffix("xn--fiqs8s"),
+            Some("xn--fiqs8s".to_string())
+        );
+        assert_eq!(list.public_suffix("foo.invalidtld"), None);
+
+        assert!(list.verify_domain("foo.com").is_ok());
+        assert!(list.verify_domain("foo.co.uk").is_ok());
+        assert!(list.verify_domain("www.ck").is_ok());
+        assert!(list.verify_domain("co.uk").is_err());
+        assert!(list.verify_domain("bar.ck").is_err());
+        assert!(list.verify_domain("foo.invalidtld").is_err());
+        assert!(list.verify_domain("foo.localhost").is_err());
+
+        Ok(())
+    }
+}
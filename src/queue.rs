@@ -0,0 +1,158 @@
+use std::{fs, path};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::asset::Asset;
+use crate::errors::{Result, ResultExt};
+use crate::webhook::EventType;
+
+// delay before the first retry, doubled on every failed attempt up to MAX_DELAY_SECS
+const BASE_DELAY_SECS: i64 = 30;
+const MAX_DELAY_SECS: i64 = 3600;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Hook,
+    Webhook,
+}
+
+// A side-effect of a registry update that failed and is pending retry
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingEvent {
+    pub target: Target,
+    pub event_type: EventType,
+    pub asset: Asset,
+    // absolute path of the asset json file, passed to the hook script
+    pub asset_path: Option<path::PathBuf>,
+    // base64-encoded deletion signature, passed to the hook script
+    pub signature: Option<String>,
+    pub attempts: u32,
+    pub next_attempt: DateTime<Utc>,
+}
+
+impl PendingEvent {
+    pub fn new(
+        target: Target,
+        event_type: EventType,
+        asset: &Asset,
+        asset_path: Option<&path::Path>,
+        signature: Option<&[u8]>,
+    ) -> Self {
+        let mut event = PendingEvent {
+            target,
+            event_type,
+            asset: asset.clone(),
+            asset_path: asset_path.map(path::Path::to_path_buf),
+            signature: signature.map(base64::encode),
+            attempts: 0,
+            next_attempt: Utc::now(),
+        };
+        event.schedule_retry();
+        event
+    }
+
+    // Record a failed attempt and schedule the next one with exponential backoff
+    pub fn schedule_retry(&mut self) {
+        let delay = BASE_DELAY_SECS
+            .checked_shl(self.attempts)
+            .filter(|delay| *delay > 0 && *delay <= MAX_DELAY_SECS)
+            .unwrap_or(MAX_DELAY_SECS);
+        self.attempts += 1;
+        self.next_attempt = Utc::now() + Duration::seconds(delay);
+    }
+}
+
+// A durable queue of pending events, stored as a directory of json files
+#[derive(Debug, Clone)]
+pub struct RetryQueue {
+    directory: path::PathBuf,
+}
+
+impl RetryQueue {
+    pub fn new(directory: path::PathBuf) -> Self {
+        RetryQueue { directory }
+    }
+
+    pub fn push(&self, event: &PendingEvent) -> Result<()> {
+        fs::create_dir_all(&self.directory)?;
+
+        let filename = format!(
+            "{}-{}-{}.json",
+            Utc::now().timestamp_nanos(),
+            event.asset.asset_id,
+            match event.target {
+                Target::Hook => "hook",
+                Target::Webhook => "webhook",
+            }
+        );
+        let path = self.directory.join(filename);
+
+        debug!("queueing {:?} for retry at {:?}", event.target, path);
+        write_event(&path, event)
+    }
+
+    // The events due for a retry, oldest first, along with the path they're stored at
+    pub fn due(&self) -> Result<Vec<(path::PathBuf, PendingEvent)>> {
+        if !self.directory.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = fs::read_dir(&self.directory)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        paths.sort();
+
+        let now = Utc::now();
+        let mut due = vec![];
+        for path in paths {
+            if path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+            let event: PendingEvent = serde_json::from_slice(&fs::read(&path)?)
+                .context(format!("invalid pending event {:?}", path))?;
+            if event.next_attempt <= now {
+                due.push((path, event));
+            }
+        }
+        Ok(due)
+    }
+
+    pub fn update(&self, path: &path::Path, event: &PendingEvent) -> Result<()> {
+        write_event(path, event)
+    }
+
+    pub fn remove(&self, path: &path::Path) -> Result<()> {
+        fs::remove_file(path).context("failed removing pending event")?;
+        Ok(())
+    }
+}
+
+fn write_event(path: &path::Path, event: &PendingEvent) -> Result<()> {
+    // write to a temporary file first so that partially written events are never picked up
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_vec(event)?).context("failed writing pending event")?;
+    fs::rename(&tmp_path, path).context("failed writing pending event")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_schedule_retry() {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json")).unwrap();
+        let mut event = PendingEvent::new(Target::Webhook, EventType::Add, &asset, None, None);
+        assert_eq!(event.attempts, 1);
+
+        for _ in 0..20 {
+            let before = Utc::now();
+            event.schedule_retry();
+            assert!(event.next_attempt > before);
+            assert!(event.next_attempt <= Utc::now() + Duration::seconds(MAX_DELAY_SECS));
+        }
+        assert_eq!(event.attempts, 21);
+    }
+}
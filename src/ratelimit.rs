@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// number of client buckets to keep before pruning the ones that fully refilled
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// A token-bucket rate limiter keyed by the client IP. Each client may make up to `burst`
+// requests at once, with tokens refilling at a steady rate of `per_minute`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    // tokens per second
+    rate: f64,
+    burst: f64,
+    exempt: HashSet<IpAddr>,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refilled(&self, now: Instant, rate: f64, burst: f64) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * rate).min(burst)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32, exempt: &[IpAddr]) -> Self {
+        RateLimiter {
+            rate: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+            exempt: exempt.iter().cloned().collect(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Take a token for the client, or return how long it has to wait for the next one
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        if self.exempt.contains(&ip) {
+            return Ok(());
+        }
+        let key = client_key(ip);
+        let (rate, burst) = (self.rate, self.burst);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&key) {
+            // clients with a full bucket are no different from ones never seen before
+            buckets.retain(|_, bucket| bucket.refilled(now, rate, burst) < burst);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.refilled(now, rate, burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+// IPv6 clients are limited per /64 prefix, which is typically assigned to a single host/network
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let exempt_ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let limiter = RateLimiter::new(60, 2, &[exempt_ip]);
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let now = Instant::now();
+
+        // the burst is allowed, then throttled until a token refills (one per second)
+        assert!(limiter.check_at(ip, now).is_ok());
+        assert!(limiter.check_at(ip, now).is_ok());
+        assert_eq!(limiter.check_at(ip, now), Err(Duration::from_secs(1)));
+        assert!(limiter
+            .check_at(ip, now + Duration::from_millis(1500))
+            .is_ok());
+        assert!(limiter
+            .check_at(ip, now + Duration::from_millis(1500))
+            .is_err());
+
+        // other clients have their own bucket
+        assert!(limiter.check_at("192.0.2.2".parse().unwrap(), now).is_ok());
+
+        // exempt clients are never throttled
+        for _ in 0..10 {
+            assert!(limiter.check_at(exempt_ip, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_ipv6_prefix() {
+        let limiter = RateLimiter::new(60, 1, &[]);
+        let now = Instant::now();
+
+        assert!(limiter
+            .check_at("2001:db8::1".parse().unwrap(), now)
+            .is_ok());
+        // same /64
+        assert!(limiter
+            .check_at("2001:db8::ffff:2".parse().unwrap(), now)
+            .is_err());
+        // another /64
+        assert!(limiter
+            .check_at("2001:db8:0:1::1".parse().unwrap(), now)
+            .is_ok());
+    }
+}
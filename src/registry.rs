@@ -1,155 +1,1577 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::process::{self, Command};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::{fs, path, process::Command};
+use std::{fmt, fs, io, path};
 
-use bitcoin_hashes::hex::ToHex;
+use bitcoin_hashes::hex::{FromHex, ToHex};
+use chrono::{DateTime, Duration, Utc};
 use elements::AssetId;
 
-use crate::asset::Asset;
+use crate::asset::{
+    is_safe_ticker, is_valid_ticker, ticker_skeleton, Asset, ValidationConfig, VerificationStep,
+};
 use crate::chain::ChainQuery;
-use crate::entity::AssetEntity;
-use crate::errors::{OptionExt, Result, ResultExt};
+use crate::entity::{
+    reverify_domain_link_http, verification_methods, AssetEntity, DomainVerificationMethod,
+    HttpProofStatus, ProofValidators,
+};
+use crate::errors::{join_err, Error, OptionExt, Result, ResultExt};
+use crate::metrics::Metrics;
+use crate::queue::{PendingEvent, RetryQueue, Target};
+use crate::util::{serde_from_hex, serde_to_hex, verify_domain_name};
+use crate::webhook::{Event, EventType, Webhook, WebhookSecret};
+
+#[cfg(feature = "cli")]
+use structopt::StructOpt;
 
 // length of asset id prefix to use for sub-directory partitioning
 // (in number of hex characters, not bytes)
-const DIR_PARTITION_LEN: usize = 2;
+pub const DIR_PARTITION_LEN: usize = 2;
+
+// lock file used to coordinate writes across multiple processes
+const LOCK_FILENAME: &str = ".lock";
+
+// directory with an entry per claimed namespace key (see `namespace_key`), containing the asset id
+pub const NAMESPACE_MAP_DIR: &str = "_map";
+
+// directory for the namespace entries of recently deleted assets
+const TOMBSTONE_DIR: &str = "_tombstones";
+
+// directory for hook/webhook runs pending retry
+const RETRY_QUEUE_DIR: &str = "_pending";
+
+#[cfg_attr(feature = "cli", derive(StructOpt))]
+#[derive(Debug, Clone, Default)]
+pub struct RegistryConfig {
+    #[cfg_attr(feature = "cli", structopt(flatten))]
+    pub validation: ValidationConfig,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "file-lock",
+            help = "Hold an on-disk lock during updates, for multiple processes sharing the same database directory"
+        )
+    )]
+    pub file_lock: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "partition",
+            env,
+            default_value = "prefix",
+            help = "Directory layout for asset files (`prefix` for sub-directories by asset id prefix, or `none`)"
+        )
+    )]
+    pub partition: Partition,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-tickerless-per-domain",
+            env,
+            help = "Maximum number of tickerless assets that can be registered per domain"
+        )
+    )]
+    pub max_tickerless_per_domain: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-collections-per-domain",
+            env,
+            help = "Maximum number of distinct collections per domain"
+        )
+    )]
+    pub max_collections_per_domain: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-assets-per-collection",
+            env,
+            help = "Maximum number of assets in a single collection of a domain"
+        )
+    )]
+    pub max_assets_per_collection: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "max-assets",
+            env,
+            help = "Maximum number of registered assets. Once reached, new registrations are rejected (deletions still work)"
+        )
+    )]
+    pub max_assets: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "global-ticker-namespace",
+            help = "Make tickers unique across all entities (first-come-first-served), instead of per entity. Should be set before registering any assets, since existing assets don't reserve their tickers globally"
+        )
+    )]
+    pub global_ticker_namespace: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "unique-names-per-domain",
+            help = "Reject assets with the exact same name as another asset of the same domain. Existing assets are not checked against each other, but do reserve their names"
+        )
+    )]
+    pub unique_names_per_domain: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "reject-confusable-tickers",
+            help = "Reject tickers that are confusable with a ticker already claimed in the same namespace (differing only in case or look-alike characters, like 0/O or 1/I/l), or that use characters outside of ASCII letters, digits, `.` and `-`"
+        )
+    )]
+    pub reject_confusable_tickers: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "ns-grace-period",
+            env,
+            help = "Number of seconds a deleted asset's entity/ticker namespace stays reserved for its issuer key"
+        )
+    )]
+    pub ns_grace_period: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "webhook-url",
+            env,
+            help = "URL to POST a JSON event to after assets are added or deleted"
+        )
+    )]
+    pub webhook_url: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "webhook-secret",
+            env,
+            help = "Shared secret for signing webhook payloads (sent in the X-Signature header)"
+        )
+    )]
+    pub webhook_secret: Option<WebhookSecret>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "retry-queue",
+            help = "Queue failed hook/webhook runs for retry in the background, instead of rejecting the update"
+        )
+    )]
+    pub retry_queue: bool,
+}
+
+// Quarantined assets are hidden, with their namespace kept reserved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuarantineKind {
+    // Pending review by the registry operator
+    Review,
+    // Restricted for legal reasons
+    LegalHold,
+}
+
+impl QuarantineKind {
+    fn dir_name(&self) -> &'static str {
+        match self {
+            QuarantineKind::Review => "_quarantine",
+            QuarantineKind::LegalHold => "_legal_hold",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Partition {
+    // Partition into sub-directories by the asset id prefix
+    Prefix,
+    // Store all assets directly in the base directory
+    None,
+}
+
+impl Default for Partition {
+    fn default() -> Self {
+        Partition::Prefix
+    }
+}
+
+impl FromStr for Partition {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "prefix" => Partition::Prefix,
+            "none" => Partition::None,
+            _ => bail!("unknown partition mode `{}`", s),
+        })
+    }
+}
 
 #[derive(Debug)]
 pub struct Registry {
     directory: path::PathBuf,
     chain: ChainQuery,
     hook_cmd: Option<String>,
+    webhook: Option<Webhook>,
+    retry_queue: Option<RetryQueue>,
+    config: RegistryConfig,
     write_lock: Arc<Mutex<()>>,
+    // built on first use and kept up to date on every update
+    index: Mutex<Option<Index>>,
+    metrics: Metrics,
+    // the HTTP proof cache validators from the last re-verification, kept in memory only
+    proof_validators: Mutex<HashMap<AssetId, ProofValidators>>,
 }
 
 impl Registry {
-    pub fn new(directory: &path::Path, chain: ChainQuery, hook_cmd: Option<String>) -> Self {
+    pub fn new(
+        directory: &path::Path,
+        chain: ChainQuery,
+        hook_cmd: Option<String>,
+        config: RegistryConfig,
+    ) -> Self {
+        let retry_queue = if config.retry_queue {
+            Some(RetryQueue::new(directory.join(RETRY_QUEUE_DIR)))
+        } else {
+            None
+        };
+        let webhook = config
+            .webhook_url
+            .clone()
+            .map(|url| Webhook::new(url, config.webhook_secret.clone(), retry_queue.clone()));
+
         Registry {
             directory: directory.to_path_buf(),
             chain,
             hook_cmd,
+            webhook,
+            retry_queue,
+            config,
             write_lock: Arc::new(Mutex::new(())),
+            index: Mutex::new(None),
+            metrics: Metrics::default(),
+            proof_validators: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn load(&self, asset_id: &AssetId) -> Result<Option<Asset>> {
+        let path = self.asset_path(asset_id);
+
+        Ok(if path.exists() {
+            Some(Asset::load(path)?)
+        } else {
+            None
+        })
+    }
+
+    // The asset file contents exactly as stored on disk
+    pub fn load_raw(&self, asset_id: &AssetId) -> Result<Option<Vec<u8>>> {
+        let path = self.asset_path(asset_id);
+
+        Ok(if path.exists() {
+            Some(fs::read(path)?)
+        } else {
+            None
+        })
+    }
+
+    // The asset that claimed the ticker in the domain's namespace (or in the global namespace, with
+    // --global-ticker-namespace), looked up through its `_map` entry
+    pub fn load_by_ticker(&self, ticker: &str, domain: &str) -> Result<Option<Asset>> {
+        ensure!(is_valid_ticker(ticker), "invalid `ticker`");
+        verify_domain_name(domain).context("invalid domain name")?;
+
+        let entity = AssetEntity::DomainName(domain.to_string());
+        let ns_path = self.directory.join(NAMESPACE_MAP_DIR).join(namespace_key(
+            &entity,
+            ticker,
+            self.config.global_ticker_namespace,
+        ));
+        let asset_id = match fs::read_to_string(&ns_path) {
+            Ok(contents) => {
+                AssetId::from_hex(contents.trim()).context("invalid namespace map entry")?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        // in the global namespace, the ticker might be claimed by another domain's asset
+        Ok(self
+            .load(&asset_id)?
+            .filter(|asset| asset.entity() == &entity))
+    }
+
+    // All registered asset ids, sorted by their hex encoding. Both the partitioned and flat
+    // layouts are scanned. Misfiled asset files can't be loaded, so they're skipped (the server
+    // warns about them once on startup, see misfiled_assets()).
+    pub fn asset_ids(&self) -> Result<Vec<AssetId>> {
+        let (mut ids, misfiled) = self.scan_asset_files()?;
+        for path in misfiled {
+            debug!("skipping misfiled asset file {}", path.display());
+        }
+        ids.sort_by_cached_key(|asset_id| asset_id.to_hex());
+        ids.dedup();
+        Ok(ids)
+    }
+
+    // Asset files placed under a partition sub-directory that doesn't match their asset id prefix
+    // (e.g. by a manual edit). These are invisible to `load()`, which derives the path from the id.
+    pub fn misfiled_assets(&self) -> Result<Vec<path::PathBuf>> {
+        let mut misfiled = self.scan_asset_files()?.1;
+        misfiled.sort();
+        Ok(misfiled)
+    }
+
+    // Scan the directory for asset files, returning the ids of the valid ones and the paths of the
+    // misfiled ones
+    fn scan_asset_files(&self) -> Result<(Vec<AssetId>, Vec<path::PathBuf>)> {
+        let (mut ids, mut misfiled) = (Vec::new(), Vec::new());
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                // skip the _map directory and anything else that isn't a partition sub-directory
+                if entry.file_name().len() != DIR_PARTITION_LEN {
+                    continue;
+                }
+                let partition = entry.file_name();
+                for subentry in fs::read_dir(entry.path())? {
+                    let path = subentry?.path();
+                    if let Some(asset_id) = parse_asset_filename(&path) {
+                        if partition.to_str() == Some(&asset_id.to_hex()[0..DIR_PARTITION_LEN]) {
+                            ids.push(asset_id);
+                        } else {
+                            misfiled.push(path);
+                        }
+                    }
+                }
+            } else {
+                ids.extend(parse_asset_filename(&entry.path()));
+            }
+        }
+        Ok((ids, misfiled))
+    }
+
+    // Lazily load all the registered assets, sorted by asset id. Each asset file is read and
+    // closed before the next one is opened, so full scans use a bounded number of file
+    // descriptors regardless of the registry size. Assets deleted mid-scan are skipped.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<Asset>> + '_> {
+        Ok(self
+            .asset_ids()?
+            .into_iter()
+            .filter_map(move |asset_id| self.load(&asset_id).transpose()))
+    }
+
+    // A page of the registered assets, sorted by their asset id hex like `asset_ids()`. Paging
+    // with the asset id cursor (rather than an offset) doesn't skip or repeat assets when others
+    // get added or deleted between the requests. The page is looked up in the index, so only its
+    // own asset files get loaded.
+    pub fn list(&self, page: &Page) -> Result<AssetPage> {
+        let after = page.after.map(|after| after.to_hex());
+        let (asset_ids, has_more) = self.with_index(|index| {
+            let mut asset_ids: Vec<AssetId> = index
+                .ids_after(after.as_deref())
+                .take(page.limit + 1)
+                .collect();
+            let has_more = asset_ids.len() > page.limit;
+            asset_ids.truncate(page.limit);
+            (asset_ids, has_more)
+        })?;
+
+        let mut assets = Vec::with_capacity(asset_ids.len());
+        for asset_id in &asset_ids {
+            // might've been deleted since
+            assets.extend(self.load(asset_id)?);
+        }
+
+        Ok(AssetPage {
+            assets,
+            next: asset_ids.last().copied().filter(|_| has_more),
+        })
+    }
+
+    // The assets linked to the domain, sorted by their asset id hex. Looked up in the index, so
+    // only the matching asset files get loaded.
+    pub fn list_by_domain(&self, domain: &str) -> Result<Vec<Asset>> {
+        verify_domain_name(domain).context("invalid domain name")?;
+
+        let entity = AssetEntity::DomainName(domain.to_string());
+        let mut asset_ids: Vec<AssetId> = self.with_index(|index| {
+            index
+                .assets
+                .iter()
+                .filter(|(_, entry)| entry.entity == entity)
+                .map(|(asset_id, _)| *asset_id)
+                .collect()
+        })?;
+        asset_ids.sort_by_cached_key(|asset_id| asset_id.to_hex());
+
+        let mut assets = Vec::with_capacity(asset_ids.len());
+        for asset_id in asset_ids {
+            // might've been deleted since
+            assets.extend(self.load(&asset_id)?);
+        }
+        Ok(assets)
+    }
+
+    // The number of registered assets
+    pub fn count(&self) -> Result<usize> {
+        self.with_index(|index| index.assets.len())
+    }
+
+    // Access the in-memory index, building it if needed. When the database directory is shared
+    // with other processes (with --file-lock) it could get updated behind our back, so the index
+    // is refreshed against a directory listing first. Only the asset files added since get loaded,
+    // the indexed fields are committed to by the asset id and can't change in place.
+    fn with_index<T>(&self, f: impl FnOnce(&Index) -> T) -> Result<T> {
+        let mut index = self.index.lock().unwrap();
+        match index.as_mut() {
+            None => {
+                let mut built = Index::default();
+                for asset in self.iter()? {
+                    built.add(&asset?);
+                }
+                self.sync_quarantined(&mut built)?;
+                *index = Some(built);
+            }
+            Some(index) if self.config.file_lock => {
+                let current: HashSet<AssetId> = self.scan_asset_files()?.0.into_iter().collect();
+                let removed: Vec<AssetId> = index
+                    .assets
+                    .keys()
+                    .filter(|asset_id| !current.contains(asset_id))
+                    .copied()
+                    .collect();
+                for asset_id in removed {
+                    index.remove(&asset_id);
+                }
+                for asset_id in current {
+                    if !index.assets.contains_key(&asset_id) {
+                        // might've been deleted since the scan
+                        if let Some(asset) = self.load(&asset_id)? {
+                            index.add(&asset);
+                        }
+                    }
+                }
+                self.sync_quarantined(index)?;
+            }
+            Some(_) => (),
+        }
+        Ok(f(index.as_ref().unwrap()))
+    }
+
+    // All the collections, with the entity they belong to, sorted by name
+    pub fn collections(&self) -> Result<Vec<CollectionSummary>> {
+        self.with_index(|index| {
+            let mut counts: HashMap<(&String, &AssetEntity), usize> = HashMap::new();
+            for entry in index.assets.values() {
+                if let Some(collection) = &entry.collection {
+                    *counts.entry((collection, &entry.entity)).or_default() += 1;
+                }
+            }
+
+            let mut collections: Vec<CollectionSummary> = counts
+                .into_iter()
+                .map(|((name, entity), asset_count)| CollectionSummary {
+                    name: name.clone(),
+                    entity: entity.clone(),
+                    asset_count,
+                })
+                .collect();
+            collections.sort_by(|a, b| {
+                (&a.name, a.entity.to_string()).cmp(&(&b.name, b.entity.to_string()))
+            });
+            collections
+        })
+    }
+
+    // The most recently registered assets, newest first. Assets registered before creation times
+    // were recorded have none, and are never included.
+    pub fn recent(&self, limit: usize) -> Result<Vec<Asset>> {
+        let mut recent: Vec<(DateTime<Utc>, AssetId)> = self.with_index(|index| {
+            index
+                .assets
+                .iter()
+                .filter_map(|(asset_id, entry)| Some((entry.created_at?, *asset_id)))
+                .collect()
+        })?;
+        recent.sort_by(|(a_time, a_id), (b_time, b_id)| {
+            b_time
+                .cmp(a_time)
+                .then_with(|| a_id.to_hex().cmp(&b_id.to_hex()))
+        });
+
+        let mut assets = Vec::with_capacity(limit);
+        for (_, asset_id) in recent {
+            if assets.len() == limit {
+                break;
+            }
+            // might've been deleted since
+            assets.extend(self.load(&asset_id)?);
+        }
+        Ok(assets)
+    }
+
+    fn update_index(&self, f: impl FnOnce(&mut Index)) {
+        if let Some(index) = self.index.lock().unwrap().as_mut() {
+            f(index)
+        }
+    }
+
+    // Bring the index and the namespace map up to date with asset files that were added or
+    // removed out-of-band (e.g. by merging pull requests into a git-backed database), without
+    // restarting. `known` holds the asset ids seen by the previous sync and gets updated to the
+    // current ones. Added assets are trusted as-is, like the ones present on startup. Asset files
+    // modified in place aren't reloaded, since the set of asset ids doesn't change.
+    pub fn sync_changes(&self, known: &mut HashSet<AssetId>) -> Result<SyncSummary> {
+        let _lock = self.write_lock.lock().unwrap();
+        let _file_lock = self.file_lock()?;
+
+        let current: HashSet<AssetId> = self.asset_ids()?.into_iter().collect();
+        let mut summary = SyncSummary::default();
+
+        for asset_id in current.difference(known) {
+            // might've been removed again since the scan
+            if let Some(asset) = self.load(asset_id)? {
+                self.file_handle(&asset).claim_ns()?;
+                self.update_index(|index| index.add(&asset));
+                summary.added += 1;
+            }
+        }
+        for asset_id in known.difference(&current) {
+            // quarantined assets keep their namespace reserved
+            if !self.is_quarantined(asset_id) {
+                self.release_ns(asset_id)?;
+            }
+            self.update_index(|index| index.remove(asset_id));
+            self.proof_validators.lock().unwrap().remove(asset_id);
+            summary.removed += 1;
+        }
+
+        if let Some(index) = self.index.lock().unwrap().as_mut() {
+            self.sync_quarantined(index)?;
+        }
+
+        *known = current;
+        Ok(summary)
+    }
+
+    // Remove the namespace map entry of a removed asset, if it still points to it. The asset file
+    // is already gone, so its namespace key is taken from the index. Assets missing from it are
+    // looked up by scanning the contents of all the entries instead.
+    fn release_ns(&self, asset_id: &AssetId) -> Result<()> {
+        let map_dir = self.directory.join(NAMESPACE_MAP_DIR);
+        if !map_dir.exists() {
+            return Ok(());
+        }
+        let ns_filename = self.index.lock().unwrap().as_ref().and_then(|index| {
+            let entry = index.assets.get(asset_id)?;
+            Some(make_unique_ns_filename(
+                &entry.entity,
+                entry.ticker.as_ref(),
+                self.config.global_ticker_namespace,
+            ))
+        });
+        let paths = match ns_filename {
+            // tickerless assets don't claim a namespace
+            Some(None) => return Ok(()),
+            Some(Some(filename)) => vec![map_dir.join(filename)],
+            None => fs::read_dir(&map_dir)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<Result<Vec<_>>>()?,
+        };
+        let asset_id = asset_id.to_hex();
+        for path in paths {
+            if path.exists() && fs::read_to_string(&path)?.trim() == asset_id {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, asset: &mut Asset) -> Result<()> {
+        // checked upfront to avoid verifying assets that can't be registered anyway, and again
+        // with the write lock held
+        self.ensure_capacity(1)?;
+        self.verify(asset)?;
+        asset.created_at = Some(Utc::now());
+
+        let _lock = self.write_lock.lock().unwrap();
+        let _file_lock = self.file_lock()?;
+        let asset_fh = self.file_handle(asset);
+
+        self.write_checked(&asset_fh)?;
+
+        let abs_path = asset_fh.abs_path()?;
+        if let Err(err) = self
+            .exec_hook(&asset.asset_id, &abs_path, "add", None)
+            .context("hook script failed")
+        {
+            warn!("hook failed: {:?}", err);
+            if let Some(queue) = &self.retry_queue {
+                // keep the asset and retry the hook later
+                queue.push(&PendingEvent::new(
+                    Target::Hook,
+                    EventType::Add,
+                    asset,
+                    Some(&abs_path),
+                    None,
+                ))?;
+            } else {
+                // cleanup created files if the hook fails (might've already been cleaned by the hook script)
+                asset_fh.delete()?;
+                self.update_index(|index| index.remove(&asset.asset_id));
+                bail!(err)
+            }
+        }
+
+        self.notify_webhook(EventType::Add, asset);
+
+        Ok(())
+    }
+
+    // Register multiple assets atomically: either all of them are written, or none are. All the
+    // assets are verified upfront, then written under a single acquisition of the write lock.
+    // Returns the per-asset outcomes, in the same order as `assets`.
+    pub fn write_batch(&self, assets: &mut [Asset]) -> Result<Vec<BatchOutcome>> {
+        self.ensure_capacity(assets.len())?;
+        let mut seen = HashSet::new();
+        let mut outcomes: Vec<BatchOutcome> = assets
+            .iter_mut()
+            .map(|asset| {
+                if !seen.insert(asset.asset_id) {
+                    return BatchOutcome::failed("duplicate asset in batch");
+                }
+                match self.verify(asset) {
+                    Ok(()) => BatchOutcome::Registered,
+                    Err(err) => BatchOutcome::failed(join_err(&err)),
+                }
+            })
+            .collect();
+
+        if outcomes.iter().any(BatchOutcome::is_failed) {
+            for outcome in outcomes.iter_mut().filter(|outcome| !outcome.is_failed()) {
+                *outcome = BatchOutcome::Aborted;
+            }
+            return Ok(outcomes);
+        }
+
+        let created_at = Utc::now();
+        for asset in assets.iter_mut() {
+            asset.created_at = Some(created_at);
+        }
+
+        let _lock = self.write_lock.lock().unwrap();
+        let _file_lock = self.file_lock()?;
+        let handles: Vec<AssetFileHandle> =
+            assets.iter().map(|asset| self.file_handle(asset)).collect();
+
+        // earlier assets in the batch are already written when checking the later ones, so
+        // conflicts within the batch are caught too
+        for (i, asset_fh) in handles.iter().enumerate() {
+            if let Err(err) = self.write_checked(asset_fh) {
+                self.rollback_batch(&handles[..i])?;
+                return Ok(BatchOutcome::batch_failure(handles.len(), i, &err));
+            }
+        }
+
+        for (i, asset_fh) in handles.iter().enumerate() {
+            let abs_path = asset_fh.abs_path()?;
+            if let Err(err) = self
+                .exec_hook(&asset_fh.asset.asset_id, &abs_path, "add", None)
+                .context("hook script failed")
+            {
+                warn!("hook failed: {:?}", err);
+                if let Some(queue) = &self.retry_queue {
+                    queue.push(&PendingEvent::new(
+                        Target::Hook,
+                        EventType::Add,
+                        asset_fh.asset,
+                        Some(&abs_path),
+                        None,
+                    ))?;
+                } else {
+                    // revert the hooks that already ran for the earlier assets, while their files
+                    // are still in place
+                    for earlier_fh in &handles[..i] {
+                        let asset_id = &earlier_fh.asset.asset_id;
+                        if let Err(err) = earlier_fh
+                            .abs_path()
+                            .and_then(|path| self.exec_hook(asset_id, &path, "delete", None))
+                        {
+                            warn!("reverting the hook for {} failed: {:?}", asset_id, err);
+                        }
+                    }
+                    self.rollback_batch(&handles)?;
+                    return Ok(BatchOutcome::batch_failure(handles.len(), i, &err.into()));
+                }
+            }
+        }
+
+        for asset_fh in &handles {
+            self.notify_webhook(EventType::Add, asset_fh.asset);
+        }
+
+        Ok(outcomes)
+    }
+
+    // Check that the asset can be written and write it. Must be called with the write lock held.
+    fn write_checked(&self, asset_fh: &AssetFileHandle) -> Result<()> {
+        let asset = asset_fh.asset;
+        asset_fh.ensure_available()?;
+        ensure!(
+            !self.is_quarantined(&asset.asset_id),
+            "asset is quarantined pending review"
+        );
+        self.ensure_entity_limits(asset)?;
+        self.ensure_unique_name(asset)?;
+        self.ensure_distinct_ticker(asset)?;
+        self.ensure_capacity(1)?;
+        asset_fh.write()?;
+        self.update_index(|index| index.add(asset));
+        Ok(())
+    }
+
+    fn rollback_batch(&self, written: &[AssetFileHandle]) -> Result<()> {
+        for asset_fh in written {
+            asset_fh.delete()?;
+            self.update_index(|index| index.remove(&asset_fh.asset.asset_id));
+        }
+        Ok(())
+    }
+
+    // Run the full verification and conflict checks without writing anything. The asset is
+    // populated with the same fields it would be stored with.
+    pub fn preview(&self, asset: &mut Asset) -> Result<()> {
+        self.verify(asset)?;
+
+        let asset_fh = self.file_handle(asset);
+        asset_fh.ensure_available()?;
+        ensure!(
+            !self.is_quarantined(&asset.asset_id),
+            "asset is quarantined pending review"
+        );
+        self.ensure_entity_limits(asset)?;
+        self.ensure_unique_name(asset)?;
+        self.ensure_distinct_ticker(asset)?;
+        Ok(())
+    }
+
+    // Enforce the configured per-entity limits, against spamming assets that don't reserve a
+    // ticker namespace slot or that occupy UI space with many collections. Self-declared assets
+    // share the `none` entity across all issuers, so they aren't subject to the per-domain limits
+    // (one issuer would otherwise use up the quota for everyone).
+    fn ensure_entity_limits(&self, asset: &Asset) -> Result<()> {
+        let config = &self.config;
+        let entity = asset.entity();
+        if entity.is_none()
+            || (config.max_tickerless_per_domain.is_none()
+                && config.max_collections_per_domain.is_none()
+                && config.max_assets_per_collection.is_none())
+        {
+            return Ok(());
+        }
+
+        let collection = asset.fields.collection.as_ref();
+
+        let (tickerless, collections, in_collection) = self.with_index(|index| {
+            let entries: Vec<&IndexEntry> = index.entity_assets(entity).collect();
+            let collections: HashSet<&String> = entries
+                .iter()
+                .filter_map(|entry| entry.collection.as_ref())
+                .collect();
+            (
+                entries
+                    .iter()
+                    .filter(|entry| entry.ticker.is_none())
+                    .count(),
+                collections.len(),
+                entries
+                    .iter()
+                    .filter(|entry| collection.is_some() && entry.collection.as_ref() == collection)
+                    .count(),
+            )
+        })?;
+
+        if let (Some(max), None) = (config.max_tickerless_per_domain, &asset.fields.ticker) {
+            ensure!(
+                tickerless < max,
+                "the maximum number of tickerless assets for {} was reached ({})",
+                entity,
+                max
+            );
+        }
+        if let Some(collection) = collection {
+            if let Some(max) = config.max_assets_per_collection {
+                ensure!(
+                    in_collection < max,
+                    "the maximum number of assets in collection {} for {} was reached ({})",
+                    collection,
+                    entity,
+                    max
+                );
+            }
+            if let Some(max) = config.max_collections_per_domain {
+                // joining an existing collection doesn't count against the limit
+                ensure!(
+                    in_collection > 0 || collections < max,
+                    "the maximum number of collections for {} was reached ({})",
+                    entity,
+                    max
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // With --max-assets, reject registering `count` more assets once the registry would exceed its
+    // size. The count is kept in the in-memory index, so this doesn't scan the database directory.
+    fn ensure_capacity(&self, count: usize) -> Result<()> {
+        if let Some(max_assets) = self.config.max_assets {
+            if self.count()? + count > max_assets {
+                return Err(RegistryFull { max_assets }.into());
+            }
+        }
+        Ok(())
+    }
+
+    // With --unique-names-per-domain, reject assets named exactly like another asset of the same
+    // domain, which would be ambiguous to tell apart (especially tickerless ones). Quarantined
+    // assets keep their name, as they may get restored. Must be called after checking that the
+    // asset isn't already registered.
+    fn ensure_unique_name(&self, asset: &Asset) -> Result<()> {
+        let entity = asset.entity();
+        if !self.config.unique_names_per_domain || entity.is_none() {
+            return Ok(());
+        }
+
+        let name = &asset.fields.name;
+        let taken = self.with_index(|index| {
+            index
+                .entity_assets(entity)
+                .chain(
+                    index
+                        .quarantined
+                        .values()
+                        .filter(|entry| &entry.entity == entity),
+                )
+                .any(|entry| &entry.name == name)
+        })?;
+        ensure!(
+            !taken,
+            "an asset named {:?} is already registered for {}",
+            name,
+            entity
+        );
+        Ok(())
+    }
+
+    // Reject tickers that impersonate another ticker of the same namespace (per entity, or global),
+    // as tickers are only required to be unique by exact match. Must be called with the write lock
+    // held, like ensure_unique_name.
+    fn ensure_distinct_ticker(&self, asset: &Asset) -> Result<()> {
+        let ticker = match &asset.fields.ticker {
+            Some(ticker) if self.config.reject_confusable_tickers => ticker,
+            _ => return Ok(()),
+        };
+        ensure!(
+            is_safe_ticker(ticker),
+            "ticker {:?} must only use ASCII letters, digits, `.` and `-`",
+            ticker
+        );
+
+        let skeleton = ticker_skeleton(ticker);
+        let entity = asset.entity();
+        let global = self.config.global_ticker_namespace;
+        let mut tickers: Vec<String> = self.with_index(|index| {
+            index
+                .assets
+                .values()
+                .filter(|entry| global || &entry.entity == entity)
+                .filter_map(|entry| entry.ticker.clone())
+                .collect()
+        })?;
+        // quarantined assets keep their namespace reserved, and may get restored
+        tickers.extend(self.with_index(|index| {
+            index
+                .quarantined
+                .values()
+                .filter(|entry| global || &entry.entity == entity)
+                .filter_map(|entry| entry.ticker.clone())
+                .collect::<Vec<String>>()
+        })?);
+        let confusable = tickers
+            .into_iter()
+            // exact matches are left to the namespace checks
+            .find(|other| other != ticker && ticker_skeleton(other) == skeleton);
+        if let Some(other) = confusable {
+            bail!(
+                "ticker {:?} is confusable with the already registered ticker {:?}",
+                ticker,
+                other
+            );
+        }
+        Ok(())
+    }
+
+    // Hide the asset. Unlike deletion, its namespace remains reserved.
+    pub fn quarantine(&self, asset_id: &AssetId, kind: QuarantineKind) -> Result<()> {
+        let _lock = self.write_lock.lock().unwrap();
+        let _file_lock = self.file_lock()?;
+
+        let path = self.asset_path(asset_id);
+        ensure!(path.exists(), "asset does not exists");
+        let asset = Asset::load(path.clone())?;
+
+        let quarantine_path = self.quarantine_path(asset_id, kind);
+        fs::create_dir_all(quarantine_path.parent().unwrap())?;
+
+        debug!("quarantining asset {:?} ({:?})", asset_id, kind);
+        fs::rename(&path, &quarantine_path).context("failed moving asset to quarantine")?;
+        self.update_index(|index| {
+            index.remove(asset_id);
+            index.quarantined.insert(*asset_id, IndexEntry::new(&asset));
+        });
+
+        let abs_path = quarantine_path.canonicalize()?;
+        self.notify_update(EventType::Quarantine, &asset, &abs_path, None)
+    }
+
+    // Restore a quarantined asset
+    pub fn unquarantine(&self, asset_id: &AssetId) -> Result<()> {
+        let _lock = self.write_lock.lock().unwrap();
+        let _file_lock = self.file_lock()?;
+
+        let kind = self
+            .quarantine_status(asset_id)
+            .or_err("asset is not quarantined")?;
+        let quarantine_path = self.quarantine_path(asset_id, kind);
+
+        let path = self.asset_path(asset_id);
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        debug!("restoring quarantined asset {:?}", asset_id);
+        fs::rename(&quarantine_path, &path).context("failed restoring asset from quarantine")?;
+        let asset = Asset::load(path.clone())?;
+        self.update_index(|index| {
+            index.quarantined.remove(asset_id);
+            index.add(&asset);
+        });
+
+        let abs_path = path.canonicalize()?;
+        self.notify_update(EventType::Unquarantine, &asset, &abs_path, None)
+    }
+
+    pub fn quarantine_status(&self, asset_id: &AssetId) -> Option<QuarantineKind> {
+        [QuarantineKind::LegalHold, QuarantineKind::Review]
+            .iter()
+            .copied()
+            .find(|kind| self.quarantine_path(asset_id, *kind).exists())
+    }
+
+    pub fn is_quarantined(&self, asset_id: &AssetId) -> bool {
+        self.quarantine_status(asset_id).is_some()
+    }
+
+    // Bring the quarantined assets of the index up to date with the quarantine directories, of
+    // either kind. Only the newly seen files are loaded. Unreadable ones are skipped with a
+    // warning, so that a broken file can't block every registration.
+    fn sync_quarantined(&self, index: &mut Index) -> Result<()> {
+        let mut current = HashMap::new();
+        for kind in &[QuarantineKind::Review, QuarantineKind::LegalHold] {
+            let dir = self.directory.join(kind.dir_name());
+            if !dir.exists() {
+                continue;
+            }
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if let Some(asset_id) = parse_asset_filename(&path) {
+                    current.insert(asset_id, path);
+                }
+            }
+        }
+
+        index
+            .quarantined
+            .retain(|asset_id, _| current.contains_key(asset_id));
+        for (asset_id, path) in current {
+            if index.quarantined.contains_key(&asset_id) {
+                continue;
+            }
+            match Asset::load(path.clone()) {
+                Ok(asset) => {
+                    index.quarantined.insert(asset_id, IndexEntry::new(&asset));
+                }
+                Err(err) => warn!(
+                    "skipping unreadable quarantined asset file {}: {}",
+                    path.display(),
+                    join_err(&err)
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn quarantine_path(&self, asset_id: &AssetId, kind: QuarantineKind) -> path::PathBuf {
+        self.directory
+            .join(kind.dir_name())
+            .join(format!("{}.json", asset_id.to_hex()))
+    }
+
+    // Verify the asset and record the verification details on it
+    fn verify(&self, asset: &mut Asset) -> Result<()> {
+        // record the configured default method on the asset, so that it keeps being verified
+        // with the same method if the default changes later
+        if asset.domain_verification_method.is_none() && !asset.entity().is_none() {
+            asset.domain_verification_method = self.config.validation.default_verification_method;
+        }
+        let verification = match asset.verify(Some(&self.chain), &self.config.validation) {
+            Ok(verification) => verification,
+            Err(err) => {
+                self.record_verification_failure(asset, VerificationStep::of_error(&err), &err);
+                return Err(err);
+            }
+        };
+
+        asset.record_verification(verification, &self.config.validation);
+        Ok(())
+    }
+
+    // The failure is logged in full, but only counted by the step that failed, to keep the set of
+    // metric labels fixed. Failures outside of the verification steps are counted as `other`.
+    fn record_verification_failure(
+        &self,
+        asset: &Asset,
+        step: Option<VerificationStep>,
+        err: &Error,
+    ) {
+        let domain = match asset.entity() {
+            AssetEntity::DomainName(domain) => domain.as_str(),
+            AssetEntity::None => "(none)",
+        };
+        warn!("verification failed for {}: {}", domain, join_err(err));
+        self.metrics
+            .record_verification_failure(domain, step.map_or("other", VerificationStep::code));
+    }
+
+    // Re-verify the HTTP domain proofs of all the assets, to detect proofs that were taken down.
+    // Proof pages are fetched with conditional requests using the validators from the previous
+    // run, so unchanged pages aren't re-downloaded. Failures are logged and counted in the metrics,
+    // without modifying the assets. Returns the number of assets that failed re-verification.
+    pub fn reverify_http_proofs(&self) -> Result<usize> {
+        let mut failed = 0;
+        for asset in self.iter()? {
+            let asset = asset?;
+            if !verification_methods(&asset, &self.config.validation)
+                .contains(&DomainVerificationMethod::Http)
+            {
+                continue;
+            }
+
+            let validators = self
+                .proof_validators
+                .lock()
+                .unwrap()
+                .get(&asset.asset_id)
+                .cloned();
+            match reverify_domain_link_http(&asset, validators.as_ref(), &self.config.validation) {
+                Ok(HttpProofStatus::Unchanged) => (),
+                Ok(HttpProofStatus::Verified(validators)) => {
+                    self.proof_validators
+                        .lock()
+                        .unwrap()
+                        .insert(asset.asset_id, validators);
+                }
+                Err(err) => {
+                    self.proof_validators
+                        .lock()
+                        .unwrap()
+                        .remove(&asset.asset_id);
+                    let step = Some(VerificationStep::LinkedEntity);
+                    self.record_verification_failure(&asset, step, &err);
+                    failed += 1;
+                }
+            }
+        }
+        Ok(failed)
+    }
+
+    pub fn delete(&self, asset: &Asset, signature: &[u8]) -> Result<()> {
+        asset.verify_deletion(signature)?;
+
+        let _lock = self.write_lock.lock().unwrap();
+        let _file_lock = self.file_lock()?;
+        let asset_fh = self.file_handle(asset);
+        ensure!(asset_fh.exists(), "asset does not exists");
+        let abs_path = asset_fh.abs_path()?;
+
+        debug!("deleting asset {:?}", asset.asset_id);
+        asset_fh.delete()?;
+        if self.config.ns_grace_period.is_some() {
+            asset_fh.write_tombstone()?;
+        }
+        self.update_index(|index| index.remove(&asset.asset_id));
+
+        self.notify_update(EventType::Delete, asset, &abs_path, Some(signature))
+    }
+
+    // Run the hook and notify the webhook about an update that was already applied. Hook failures
+    // are queued for retry when the retry queue is enabled, and returned otherwise.
+    fn notify_update(
+        &self,
+        event_type: EventType,
+        asset: &Asset,
+        abs_path: &path::Path,
+        signature: Option<&[u8]>,
+    ) -> Result<()> {
+        if let Err(err) = self
+            .exec_hook(&asset.asset_id, abs_path, event_type.as_str(), signature)
+            .context("hook script failed")
+        {
+            match &self.retry_queue {
+                Some(queue) => {
+                    warn!("hook failed: {:?}", err);
+                    queue.push(&PendingEvent::new(
+                        Target::Hook,
+                        event_type,
+                        asset,
+                        Some(abs_path),
+                        signature,
+                    ))?;
+                }
+                None => bail!(err),
+            }
+        }
+
+        self.notify_webhook(event_type, asset);
+
+        Ok(())
+    }
+
+    fn file_handle<'a>(&self, asset: &'a Asset) -> AssetFileHandle<'a> {
+        AssetFileHandle::new(
+            asset,
+            self.asset_path(&asset.asset_id),
+            &self.directory,
+            &self.config,
+        )
+    }
+
+    // Path to the asset json file. Both the partitioned and flat layouts are checked, so that
+    // switching between them works without migrating existing files. Defaults to the configured
+    // layout when the asset does not exist.
+    fn asset_path(&self, asset_id: &AssetId) -> path::PathBuf {
+        let name = format!("{}.json", asset_id.to_hex());
+        let partitioned = self.directory.join(&name[0..DIR_PARTITION_LEN]).join(&name);
+        let flat = self.directory.join(&name);
+
+        let (primary, fallback) = match self.config.partition {
+            Partition::Prefix => (partitioned, flat),
+            Partition::None => (flat, partitioned),
+        };
+
+        if !primary.exists() && fallback.exists() {
+            fallback
+        } else {
+            primary
+        }
+    }
+
+    // Acquire the cross-process file lock, if enabled. Released when dropped.
+    fn file_lock(&self) -> Result<Option<FileLock>> {
+        Ok(if self.config.file_lock {
+            Some(FileLock::acquire(&self.directory.join(LOCK_FILENAME))?)
+        } else {
+            None
+        })
+    }
+
+    fn exec_hook(
+        &self,
+        asset_id: &AssetId,
+        asset_path: &path::Path,
+        update_type: &str,
+        signature: Option<&[u8]>,
+    ) -> Result<()> {
+        if let Some(output) = self.run_hook(asset_id, asset_path, update_type, signature)? {
+            ensure!(output.status.success(), "hook script failed");
+        }
+        Ok(())
+    }
+
+    // Run the hook command, returning its output regardless of its exit status (or None if no
+    // hook is configured). Exposed separately from `exec_hook` for testing hook commands.
+    pub fn run_hook(
+        &self,
+        asset_id: &AssetId,
+        asset_path: &path::Path,
+        update_type: &str,
+        signature: Option<&[u8]>,
+    ) -> Result<Option<process::Output>> {
+        let cmd = match &self.hook_cmd {
+            Some(cmd) => cmd,
+            None => return Ok(None),
+        };
+        debug!("running hook {} for {:?}", cmd, asset_id);
+
+        let mut envs = HashMap::new();
+        if let Some(sig) = signature {
+            envs.insert("AUTHORIZING_SIG", base64::encode(sig));
+        }
+
+        let output = Command::new(cmd)
+            .current_dir(&self.directory)
+            .arg(asset_id.to_hex())
+            .arg(asset_path.to_str().req()?)
+            .arg(update_type)
+            .envs(envs)
+            .output()
+            .context(format!("failed running hook {}", cmd))?;
+        debug!(
+            "hook exited with {:?}\n## stdout: {}\n## stderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        Ok(Some(output))
+    }
+
+    pub fn has_retry_queue(&self) -> bool {
+        self.retry_queue.is_some()
+    }
+
+    // Retry the queued hook/webhook runs that are due. Successful ones are removed from the
+    // queue, failed ones are rescheduled with backoff.
+    pub fn retry_pending(&self) -> Result<()> {
+        let queue = match &self.retry_queue {
+            Some(queue) => queue,
+            None => return Ok(()),
+        };
+
+        // Claim the due events by scheduling their next attempt up front, so that they aren't
+        // picked up again by concurrent runs while being delivered. The locks are only held for
+        // this, not while waiting on the hooks and webhooks.
+        let due = {
+            let _lock = self.write_lock.lock().unwrap();
+            let _file_lock = self.file_lock()?;
+
+            let mut due = queue.due()?;
+            for (path, event) in &mut due {
+                event.schedule_retry();
+                queue.update(path, event)?;
+            }
+            due
+        };
+
+        for (path, event) in due {
+            debug!(
+                "retrying {:?} for {} (attempt {})",
+                event.target,
+                event.asset.asset_id.to_hex(),
+                event.attempts
+            );
+            match self.run_pending(&event) {
+                Ok(()) => queue.remove(&path)?,
+                // already rescheduled
+                Err(err) => warn!(
+                    "retrying {:?} for {} failed: {:?}",
+                    event.target,
+                    event.asset.asset_id.to_hex(),
+                    err
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn run_pending(&self, event: &PendingEvent) -> Result<()> {
+        match event.target {
+            Target::Hook => {
+                let signature = event.signature.as_ref().map(base64::decode).transpose()?;
+                self.exec_hook(
+                    &event.asset.asset_id,
+                    event.asset_path.as_ref().req()?,
+                    event.event_type.as_str(),
+                    signature.as_deref(),
+                )
+            }
+            Target::Webhook => self
+                .webhook
+                .as_ref()
+                .or_err("webhook is not configured")?
+                .send(&Event::new(event.event_type, &event.asset)),
         }
     }
 
-    pub fn load(&self, asset_id: &AssetId) -> Result<Option<Asset>> {
-        let name = format!("{}.json", asset_id.to_hex());
-        let subdir = self.directory.join(&name[0..DIR_PARTITION_LEN]);
-        let path = subdir.join(name);
+    fn notify_webhook(&self, event_type: EventType, asset: &Asset) {
+        if let Some(webhook) = &self.webhook {
+            webhook.notify(Event::new(event_type, asset));
+        }
+    }
 
-        Ok(if path.exists() {
-            Some(Asset::load(path)?)
-        } else {
-            None
-        })
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
     }
 
-    pub fn write(&self, asset: &Asset) -> Result<()> {
-        asset.verify(Some(&self.chain))?;
+    pub fn chain(&self) -> &ChainQuery {
+        &self.chain
+    }
 
-        let _lock = self.write_lock.lock().unwrap();
-        let asset_fh = AssetFileHandle::new(asset, &self.directory);
+    pub fn validation(&self) -> &ValidationConfig {
+        &self.config.validation
+    }
+}
 
-        ensure!(!asset_fh.exists(), "updates are not allowed");
-        ensure!(
-            !asset_fh.ns_exists(),
-            "another asset is already registered with this entity/ticker"
-        );
+// An exclusive advisory lock (flock) held on a file for as long as this is alive
+struct FileLock(fs::File);
 
-        asset_fh.write()?;
+impl FileLock {
+    #[cfg(unix)]
+    fn acquire(path: &path::Path) -> Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path)
+            .context("failed opening lock file")?;
 
-        if let Err(err) = self
-            .exec_hook(&asset.asset_id, &asset_fh.abs_path()?, "add", None)
-            .context("hook script failed")
-        {
-            warn!("hook failed: {:?}", err);
-            // cleanup created files if the hook fails (might've already been cleaned by the hook script)
-            asset_fh.delete()?;
-            bail!(err)
+        // blocks until the lock is available
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            bail!(
+                "failed acquiring lock on {:?}: {}",
+                path,
+                io::Error::last_os_error()
+            );
         }
 
-        Ok(())
+        Ok(FileLock(file))
     }
 
-    pub fn delete(&self, asset: &Asset, signature: &[u8]) -> Result<()> {
-        asset.verify_deletion(signature)?;
+    #[cfg(not(unix))]
+    fn acquire(_path: &path::Path) -> Result<Self> {
+        bail!("--file-lock is only supported on unix")
+    }
+}
 
-        let _lock = self.write_lock.lock().unwrap();
-        let asset_fh = AssetFileHandle::new(asset, &self.directory);
-        ensure!(asset_fh.exists(), "asset does not exists");
-        let abs_path = asset_fh.abs_path()?;
+#[cfg(unix)]
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // closing the file would release the lock too, but be explicit about it
+        unsafe { libc::flock(self.0.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
 
-        debug!("deleting asset {:?}", asset.asset_id);
-        asset_fh.delete()?;
+#[derive(Debug, Serialize)]
+pub struct CollectionSummary {
+    pub name: String,
+    pub entity: AssetEntity,
+    pub asset_count: usize,
+}
 
-        self.exec_hook(&asset.asset_id, &abs_path, "delete", Some(signature))
-            .context("hook script failed")?;
+// A registration rejected because the registry reached its --max-assets size. Served as
+// `507 Insufficient Storage`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryFull {
+    pub max_assets: usize,
+}
 
-        Ok(())
+impl fmt::Display for RegistryFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the registry is full ({} assets), new registrations are not accepted",
+            self.max_assets
+        )
     }
+}
 
-    fn exec_hook(
-        &self,
-        asset_id: &AssetId,
-        asset_path: &path::Path,
-        update_type: &str,
-        signature: Option<&[u8]>,
-    ) -> Result<()> {
-        if let Some(cmd) = &self.hook_cmd {
-            debug!("running hook {} for {:?}", cmd, asset_id);
+impl std::error::Error for RegistryFull {}
 
-            let mut envs = HashMap::new();
-            if let Some(sig) = signature {
-                envs.insert("AUTHORIZING_SIG", base64::encode(sig));
-            }
+// A page of `Registry::list()`: up to `limit` assets, starting after the `after` asset id
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub after: Option<AssetId>,
+    pub limit: usize,
+}
 
-            let output = Command::new(cmd)
-                .current_dir(&self.directory)
-                .arg(asset_id.to_hex())
-                .arg(asset_path.to_str().req()?)
-                .arg(update_type)
-                .envs(envs)
-                .output()?;
-            debug!(
-                "hook exited with {:?}\n## stdout: {}\n## stderr: {}",
-                output.status,
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr)
-            );
+#[derive(Debug)]
+pub struct AssetPage {
+    pub assets: Vec<Asset>,
+    // the cursor for the next page, when more assets remain
+    pub next: Option<AssetId>,
+}
 
-            ensure!(output.status.success(), "hook script failed");
+// The asset files picked up by `Registry::sync_changes()`
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncSummary {
+    pub added: usize,
+    pub removed: usize,
+}
+
+// In-memory summary of the registered assets, for enforcing the per-entity limits without loading
+// every asset file
+#[derive(Debug, Default)]
+struct Index {
+    assets: HashMap<AssetId, IndexEntry>,
+    // the asset ids keyed by their hex encoding, for paging in the order of `asset_ids()`
+    sorted_ids: BTreeMap<String, AssetId>,
+    // the assets in quarantine, which keep their namespace reserved and may get restored
+    quarantined: HashMap<AssetId, IndexEntry>,
+}
+
+#[derive(Debug)]
+struct IndexEntry {
+    entity: AssetEntity,
+    name: String,
+    ticker: Option<String>,
+    collection: Option<String>,
+    created_at: Option<DateTime<Utc>>,
+}
+
+impl IndexEntry {
+    fn new(asset: &Asset) -> Self {
+        IndexEntry {
+            entity: asset.fields.entity.clone(),
+            name: asset.fields.name.clone(),
+            ticker: asset.fields.ticker.clone(),
+            collection: asset.fields.collection.clone(),
+            created_at: asset.created_at,
         }
-        Ok(())
     }
+}
 
-    pub fn chain(&self) -> &ChainQuery {
-        &self.chain
+impl Index {
+    fn add(&mut self, asset: &Asset) {
+        self.assets.insert(asset.asset_id, IndexEntry::new(asset));
+        self.sorted_ids
+            .insert(asset.asset_id.to_hex(), asset.asset_id);
+    }
+
+    fn remove(&mut self, asset_id: &AssetId) {
+        self.assets.remove(asset_id);
+        self.sorted_ids.remove(&asset_id.to_hex());
+    }
+
+    // The asset ids following the `after` hex cursor (or all of them), sorted by their hex
+    fn ids_after<'a>(&'a self, after: Option<&str>) -> impl Iterator<Item = AssetId> + 'a {
+        let start = match after {
+            Some(after) => Bound::Excluded(after.to_string()),
+            None => Bound::Unbounded,
+        };
+        self.sorted_ids
+            .range((start, Bound::Unbounded))
+            .map(|(_, asset_id)| *asset_id)
+    }
+
+    fn entity_assets<'a>(
+        &'a self,
+        entity: &'a AssetEntity,
+    ) -> impl Iterator<Item = &'a IndexEntry> + 'a {
+        self.assets
+            .values()
+            .filter(move |entry| &entry.entity == entity)
+    }
+}
+
+// The outcome of registering a single asset as part of a batch
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum BatchOutcome {
+    Registered,
+    Failed { error: String },
+    // not registered (or rolled back) because another asset in the batch failed
+    Aborted,
+}
+
+impl BatchOutcome {
+    fn failed(error: impl Into<String>) -> Self {
+        BatchOutcome::Failed {
+            error: error.into(),
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        match self {
+            BatchOutcome::Failed { .. } => true,
+            _ => false,
+        }
+    }
+
+    // The outcomes of a batch that failed on the asset at index `failed`
+    fn batch_failure(len: usize, failed: usize, err: &Error) -> Vec<Self> {
+        (0..len)
+            .map(|i| {
+                if i == failed {
+                    BatchOutcome::failed(join_err(err))
+                } else {
+                    BatchOutcome::Aborted
+                }
+            })
+            .collect()
     }
 }
 
+// A namespace entry retained after the asset's deletion, reserving it for the original issuer
+#[derive(Serialize, Deserialize)]
+struct Tombstone {
+    asset_id: AssetId,
+    #[serde(deserialize_with = "serde_from_hex", serialize_with = "serde_to_hex")]
+    issuer_pubkey: Vec<u8>,
+    deleted_at: DateTime<Utc>,
+}
+
 struct AssetFileHandle<'a> {
     asset: &'a Asset,
     // directory and full path to main asset json file
     path: path::PathBuf,
     // path for unique namespace identifier file
     ns_path: Option<path::PathBuf>,
+    // path for the namespace tombstone file, left behind after deletion
+    tombstone_path: Option<path::PathBuf>,
+    global_ticker_namespace: bool,
+    // in seconds
+    ns_grace_period: Option<u64>,
 }
 
 impl<'a> AssetFileHandle<'a> {
-    fn new(asset: &'a Asset, base_dir: &path::Path) -> Self {
-        let name = format!("{}.json", asset.asset_id.to_hex());
-        let dir = base_dir.join(&name[0..DIR_PARTITION_LEN]);
-        let path = dir.join(name);
-
+    fn new(
+        asset: &'a Asset,
+        path: path::PathBuf,
+        base_dir: &path::Path,
+        config: &RegistryConfig,
+    ) -> Self {
         // XXX use sub-dirs inside map too, use the hash of the unique_key as filename?
-        let ns_dir = base_dir.join("_map");
-        let ns_path = make_unique_ns_filename(&asset.fields.entity, asset.fields.ticker.as_ref())
-            .map(|filename| ns_dir.join(filename));
+        let ns_filename = make_unique_ns_filename(
+            &asset.fields.entity,
+            asset.fields.ticker.as_ref(),
+            config.global_ticker_namespace,
+        );
+        let ns_path = ns_filename
+            .as_ref()
+            .map(|filename| base_dir.join(NAMESPACE_MAP_DIR).join(filename));
+        let tombstone_path =
+            ns_filename.map(|filename| base_dir.join(TOMBSTONE_DIR).join(filename));
 
         AssetFileHandle {
             asset,
             path,
             ns_path,
+            tombstone_path,
+            global_ticker_namespace: config.global_ticker_namespace,
+            ns_grace_period: config.ns_grace_period,
         }
     }
 
@@ -161,6 +1583,68 @@ impl<'a> AssetFileHandle<'a> {
         self.ns_path.as_ref().map_or(false, |path| path.exists())
     }
 
+    fn ensure_available(&self) -> Result<()> {
+        ensure!(!self.exists(), "updates are not allowed");
+        if self.global_ticker_namespace && self.ns_exists() {
+            // the namespace file holds the id of the asset that claimed the ticker
+            let claimed_by = fs::read_to_string(self.ns_path.as_ref().unwrap())?;
+            bail!(
+                "ticker {} is already registered by asset {}",
+                self.asset.fields.ticker.as_ref().unwrap(),
+                claimed_by.trim()
+            );
+        }
+        ensure!(
+            !self.ns_exists(),
+            "another asset is already registered with this entity/ticker"
+        );
+        if let Some((tombstone, reserved_until)) = self.active_tombstone()? {
+            ensure!(
+                tombstone.issuer_pubkey == self.asset.fields.issuer_pubkey,
+                "this entity/ticker was freed by the deletion of asset {} and is reserved for its issuer until {}",
+                tombstone.asset_id.to_hex(),
+                reserved_until.to_rfc3339()
+            );
+        }
+        Ok(())
+    }
+
+    // The tombstone left by a recently deleted asset with the same namespace, if still within
+    // the grace period. Returned along with the time the reservation expires.
+    fn active_tombstone(&self) -> Result<Option<(Tombstone, DateTime<Utc>)>> {
+        let (tombstone_path, grace_period) = match (&self.tombstone_path, self.ns_grace_period) {
+            (Some(path), Some(grace_period)) if path.exists() => (path, grace_period),
+            _ => return Ok(None),
+        };
+        let tombstone: Tombstone = serde_json::from_str(&fs::read_to_string(tombstone_path)?)
+            .context("invalid namespace tombstone")?;
+        let reserved_until = Duration::from_std(std::time::Duration::from_secs(grace_period))
+            .ok()
+            .and_then(|grace_period| tombstone.deleted_at.checked_add_signed(grace_period))
+            .or_err("namespace grace period out of range")?;
+
+        Ok(if Utc::now() < reserved_until {
+            Some((tombstone, reserved_until))
+        } else {
+            None
+        })
+    }
+
+    // Keep the namespace reserved for the asset's issuer after deleting it
+    fn write_tombstone(&self) -> Result<()> {
+        if let Some(tombstone_path) = &self.tombstone_path {
+            fs::create_dir_all(tombstone_path.parent().unwrap())?;
+            let tombstone = Tombstone {
+                asset_id: self.asset.asset_id,
+                issuer_pubkey: self.asset.fields.issuer_pubkey.clone(),
+                deleted_at: Utc::now(),
+            };
+            fs::write(tombstone_path, serde_json::to_string(&tombstone)?)
+                .context("failed writing namespace tombstone")?;
+        }
+        Ok(())
+    }
+
     fn abs_path(&self) -> Result<path::PathBuf> {
         Ok(self.path.canonicalize()?)
     }
@@ -189,17 +1673,690 @@ impl<'a> AssetFileHandle<'a> {
         Ok(())
     }
 
+    // Claim the namespace for an asset file added out-of-band. If another asset already claimed
+    // it, the existing entry is kept and the conflict is only logged, since the asset file is
+    // already in place.
+    fn claim_ns(&self) -> Result<()> {
+        let ns_path = match &self.ns_path {
+            Some(ns_path) => ns_path,
+            None => return Ok(()),
+        };
+        let asset_id = self.asset.asset_id.to_hex();
+        if ns_path.exists() {
+            let claimed_by = fs::read_to_string(ns_path)?;
+            if claimed_by.trim() != asset_id {
+                warn!(
+                    "asset {} conflicts with {}, which already claimed {}",
+                    asset_id,
+                    claimed_by.trim(),
+                    ns_path.display()
+                );
+            }
+            return Ok(());
+        }
+        fs::create_dir_all(ns_path.parent().unwrap())?;
+        fs::write(ns_path, asset_id).context("failed writing asset map to fs")?;
+        Ok(())
+    }
+
     fn delete(&self) -> Result<()> {
         if self.exists() {
             fs::remove_file(&self.path)?;
         }
-        if self.ns_exists() {
+        if self.ns_owned()? {
             fs::remove_file(self.ns_path.as_ref().unwrap())?;
         }
         Ok(())
     }
+
+    // Whether the namespace is claimed by this asset. It may be held by another asset with the same
+    // key, like a ticker claimed by another entity's asset before the namespace was made global.
+    fn ns_owned(&self) -> Result<bool> {
+        Ok(match &self.ns_path {
+            Some(ns_path) if ns_path.exists() => {
+                fs::read_to_string(ns_path)?.trim() == self.asset.asset_id.to_hex()
+            }
+            _ => false,
+        })
+    }
+}
+
+// Get the asset id from an asset json file path, ignoring any other files
+fn parse_asset_filename(path: &path::Path) -> Option<AssetId> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    AssetId::from_hex(path.file_stem()?.to_str()?).ok()
 }
 
-fn make_unique_ns_filename(entity: &AssetEntity, ticker: Option<&String>) -> Option<String> {
-    ticker.map(|ticker| format!("{}@{}", ticker, entity))
+// The namespace key claimed by an asset with the given ticker, used as its filename under the
+// `_map` directory. Tickers are namespaced per entity as `<ticker>@<entity>` by default, where the
+// entity is formatted as `domain:<domain-name>` (e.g. `FOO@domain:example.com`), or claimed
+// globally as just `<ticker>` with the global ticker namespace. Tickers and domains are used as
+// they appear in the contract, without any case normalization, so `FOO` and `foo` are distinct.
+pub fn namespace_key(entity: &AssetEntity, ticker: &str, global_ticker_namespace: bool) -> String {
+    if global_ticker_namespace {
+        ticker.to_string()
+    } else {
+        format!("{}@{}", ticker, entity)
+    }
+}
+
+// Assets without a ticker don't claim a namespace key
+fn make_unique_ns_filename(
+    entity: &AssetEntity,
+    ticker: Option<&String>,
+    global_ticker_namespace: bool,
+) -> Option<String> {
+    ticker.map(|ticker| namespace_key(entity, ticker, global_ticker_namespace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{test_asset_json, Fixture, TestDb, TEST_ASSET_ID};
+    use bitcoin_hashes::{sha256, Hash};
+
+    // counted through procfs, which is linux-specific
+    #[cfg(target_os = "linux")]
+    fn open_fds() -> usize {
+        fs::read_dir("/proc/self/fd").unwrap().count()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_iter_bounded_fds() -> Result<()> {
+        let db = TestDb::new("iter")?;
+
+        // a synthetic registry with copies of the same asset under different ids
+        let count = 500;
+        for i in 0..count {
+            let asset_id = sha256::Hash::hash(format!("asset {}", i).as_bytes()).to_hex();
+            db.write_asset(&test_asset_json(&asset_id)?)?;
+        }
+
+        let registry = db.registry(RegistryConfig::default());
+
+        let fds_before = open_fds();
+        let mut max_fds = fds_before;
+        let mut loaded = 0;
+        for asset in registry.iter()? {
+            asset?;
+            loaded += 1;
+            max_fds = max_fds.max(open_fds());
+        }
+
+        assert_eq!(loaded, count);
+        // reading /proc/self/fd itself takes a descriptor
+        assert!(max_fds <= fds_before + 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_pages() -> Result<()> {
+        let db = TestDb::new("list")?;
+        let mut asset_ids: Vec<String> = (0..5)
+            .map(|i| sha256::Hash::hash(format!("asset {}", i).as_bytes()).to_hex())
+            .collect();
+        let mut paths = vec![];
+        for asset_id in &asset_ids {
+            paths.push(db.write_asset(&test_asset_json(asset_id)?)?);
+        }
+        asset_ids.sort();
+
+        let registry = db.registry(RegistryConfig::default());
+        let mut listed = vec![];
+        let mut after = None;
+        loop {
+            let page = registry.list(&Page { after, limit: 2 })?;
+            assert!(page.assets.len() <= 2);
+            listed.extend(page.assets.iter().map(|asset| asset.asset_id.to_hex()));
+            after = page.next;
+            if after.is_none() {
+                break;
+            }
+        }
+        assert_eq!(listed, asset_ids);
+
+        // deleted assets are no longer listed
+        let path = paths
+            .iter()
+            .find(|path| path.ends_with(format!("{}.json", asset_ids[0])));
+        fs::remove_file(path.req()?)?;
+        registry.update_index(|index| index.remove(&AssetId::from_hex(&asset_ids[0]).unwrap()));
+        let page = registry.list(&Page {
+            after: None,
+            limit: 10,
+        })?;
+        assert_eq!(page.assets.len(), 4);
+        assert_eq!(page.next, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_namespace_key() {
+        let entity = AssetEntity::DomainName("example.com".to_string());
+        assert_eq!(
+            namespace_key(&entity, "FOO", false),
+            "FOO@domain:example.com"
+        );
+        assert_eq!(namespace_key(&entity, "FOO", true), "FOO");
+        assert_eq!(make_unique_ns_filename(&entity, None, false), None);
+    }
+
+    #[test]
+    fn test_sync_changes() -> Result<()> {
+        let db = TestDb::new("sync")?;
+        let registry = db.registry(RegistryConfig::default());
+        let mut known = HashSet::new();
+        assert_eq!(registry.sync_changes(&mut known)?, SyncSummary::default());
+        assert_eq!(registry.count()?, 0);
+
+        // an asset file merged into the directory by another process
+        let asset = Asset::load("test/asset-b1405e.json".into())?;
+        let asset_file = db.write_asset(&test_asset_json(TEST_ASSET_ID)?)?;
+
+        let summary = registry.sync_changes(&mut known)?;
+        assert_eq!(
+            summary,
+            SyncSummary {
+                added: 1,
+                removed: 0
+            }
+        );
+        assert_eq!(registry.count()?, 1);
+        assert!(registry.file_handle(&asset).ns_exists());
+        // nothing changed since
+        assert_eq!(registry.sync_changes(&mut known)?, SyncSummary::default());
+
+        // quarantined assets keep their namespace
+        registry.quarantine(&asset.asset_id, QuarantineKind::Review)?;
+        assert_eq!(registry.sync_changes(&mut known)?.removed, 1);
+        assert!(registry.file_handle(&asset).ns_exists());
+        registry.unquarantine(&asset.asset_id)?;
+        assert_eq!(registry.sync_changes(&mut known)?.added, 1);
+
+        fs::remove_file(&asset_file)?;
+        let summary = registry.sync_changes(&mut known)?;
+        assert_eq!(
+            summary,
+            SyncSummary {
+                added: 0,
+                removed: 1
+            }
+        );
+        assert_eq!(registry.count()?, 0);
+        assert!(!registry.file_handle(&asset).ns_exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_names_per_domain() -> Result<()> {
+        let db = TestDb::new("names")?;
+        let asset = Asset::load("test/asset-b1405e.json".into())?;
+        db.write_asset(&test_asset_json(TEST_ASSET_ID)?)?;
+
+        let mut config = RegistryConfig::default();
+        let registry = db.registry(config.clone());
+        // another asset with the same name and domain
+        let mut same_name = asset.clone();
+        same_name.asset_id = AssetId::from_hex(&"00".repeat(32))?;
+        registry.ensure_unique_name(&same_name)?;
+
+        config.unique_names_per_domain = true;
+        let registry = db.registry(config);
+        let err = registry.ensure_unique_name(&same_name).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "an asset named \"PPP coin\" is already registered for domain:test.dev"
+        );
+
+        // names are unique per domain
+        same_name.fields.entity = AssetEntity::DomainName("other.dev".to_string());
+        registry.ensure_unique_name(&same_name)?;
+
+        // quarantined assets keep their name
+        same_name.fields.entity = asset.fields.entity.clone();
+        registry.quarantine(&asset.asset_id, QuarantineKind::Review)?;
+        assert!(registry.ensure_unique_name(&same_name).is_err());
+        Ok(())
+    }
+
+    // A hook command logging its runs to hook.log (in the database directory, its working
+    // directory) as `<update-type> <asset-id>`, failing for the `fail_for` asset
+    #[cfg(unix)]
+    fn write_hook_script(directory: &path::Path, fail_for: Option<&AssetId>) -> Result<String> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = directory.join("hook.sh");
+        let fail_for = fail_for.map_or(String::new(), |asset_id| asset_id.to_hex());
+        fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\necho \"$3 $1\" >> hook.log\n[ \"$1\" != \"{}\" ]\n",
+                fail_for
+            ),
+        )?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        Ok(path.to_str().req()?.to_string())
+    }
+
+    fn hook_log(directory: &path::Path) -> Result<Vec<String>> {
+        let log = fs::read_to_string(directory.join("hook.log")).unwrap_or_default();
+        fs::remove_file(directory.join("hook.log")).ok();
+        Ok(log.lines().map(String::from).collect())
+    }
+
+    fn spawn_mocks() {
+        crate::entity::tests::spawn_mock_verifier_server();
+        crate::chain::tests::spawn_mock_esplora_server();
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_batch_hooks() -> Result<()> {
+        spawn_mocks();
+        let db = TestDb::new("batch-hooks")?;
+        let directory = &db.directory;
+
+        let mut assets = vec![];
+        for name in &["Batch 1", "Batch 2", "Batch 3", "Batch 4"] {
+            let fixture = Fixture::named(name)?;
+            fixture.write(&crate::chain::tests::mock_fixtures_dir())?;
+            assets.push(fixture.asset);
+        }
+        let ids: Vec<String> = assets.iter().map(|asset| asset.asset_id.to_hex()).collect();
+        let registry = |fail_for: Option<&AssetId>| -> Result<Registry> {
+            Ok(Registry::new(
+                directory,
+                ChainQuery::new("http://localhost:58713".to_string()),
+                Some(write_hook_script(directory, fail_for)?),
+                RegistryConfig::default(),
+            ))
+        };
+
+        // the hooks run for every asset in the batch
+        let outcomes = registry(None)?.write_batch(&mut assets[0..2])?;
+        assert_eq!(
+            outcomes,
+            vec![BatchOutcome::Registered, BatchOutcome::Registered]
+        );
+        assert_eq!(
+            hook_log(directory)?,
+            vec![format!("add {}", ids[0]), format!("add {}", ids[1])]
+        );
+
+        // a failing hook rolls back the batch, reverting the hooks that ran for the earlier assets
+        let failing = assets[3].asset_id;
+        let registry = registry(Some(&failing))?;
+        let outcomes = registry.write_batch(&mut assets[2..4])?;
+        assert_eq!(outcomes[0], BatchOutcome::Aborted);
+        assert!(outcomes[1].is_failed());
+        assert_eq!(
+            hook_log(directory)?,
+            vec![
+                format!("add {}", ids[2]),
+                format!("add {}", ids[3]),
+                format!("delete {}", ids[2]),
+            ]
+        );
+        assert!(registry.load(&assets[2].asset_id)?.is_none());
+        assert!(registry.load(&assets[3].asset_id)?.is_none());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_quarantine_events() -> Result<()> {
+        let db = TestDb::new("quarantine-events")?;
+        let directory = &db.directory;
+        let asset = Asset::load("test/asset-b1405e.json".into())?;
+        db.write_asset(&test_asset_json(TEST_ASSET_ID)?)?;
+
+        let (webhook_url, deliveries) = crate::webhook::tests::spawn_mock_receiver(vec![])?;
+        let config = RegistryConfig {
+            webhook_url: Some(webhook_url),
+            ..Default::default()
+        };
+        let registry = Registry::new(
+            directory,
+            ChainQuery::new(String::new()),
+            Some(write_hook_script(directory, None)?),
+            config,
+        );
+        let timeout = std::time::Duration::from_secs(5);
+
+        registry.quarantine(&asset.asset_id, QuarantineKind::LegalHold)?;
+        assert_eq!(
+            hook_log(directory)?,
+            vec![format!("quarantine {}", asset.asset_id.to_hex())]
+        );
+        assert_eq!(deliveries.recv_timeout(timeout)?.body["type"], "quarantine");
+
+        registry.unquarantine(&asset.asset_id)?;
+        assert_eq!(
+            hook_log(directory)?,
+            vec![format!("unquarantine {}", asset.asset_id.to_hex())]
+        );
+        assert_eq!(
+            deliveries.recv_timeout(timeout)?.body["type"],
+            "unquarantine"
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_lock_contention() -> Result<()> {
+        let db = TestDb::new("file-lock")?;
+        let lock_path = db.directory.join(LOCK_FILENAME);
+
+        let held = FileLock::acquire(&lock_path)?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let waiter_path = lock_path.clone();
+        let waiter = std::thread::spawn(move || {
+            let _lock = FileLock::acquire(&waiter_path).unwrap();
+            sender.send(()).unwrap();
+        });
+
+        // blocked for as long as the lock is held, acquired once it's released
+        let timeout = std::time::Duration::from_millis(250);
+        assert!(receiver.recv_timeout(timeout).is_err());
+        drop(held);
+        receiver.recv_timeout(timeout * 20)?;
+        waiter.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_ns_grace_period_overflow() -> Result<()> {
+        let db = TestDb::new("grace")?;
+        let asset = Asset::load("test/asset-b1405e.json".into())?;
+        let mut config = RegistryConfig {
+            ns_grace_period: Some(3600),
+            ..Default::default()
+        };
+        let registry = db.registry(config.clone());
+        registry.file_handle(&asset).write_tombstone()?;
+
+        // another issuer is kept out during the grace period
+        let mut other = asset.clone();
+        other.asset_id = AssetId::from_hex(&"00".repeat(32))?;
+        other.fields.issuer_pubkey = vec![2; 33];
+        let err = registry.file_handle(&other).ensure_available().unwrap_err();
+        assert!(err.to_string().contains("is reserved for its issuer"));
+
+        // a grace period too large to represent is rejected instead of overflowing
+        config.ns_grace_period = Some(u64::MAX);
+        let registry = db.registry(config);
+        let err = registry.file_handle(&other).ensure_available().unwrap_err();
+        assert_eq!(err.to_string(), "namespace grace period out of range");
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_releases_own_ns() -> Result<()> {
+        let db = TestDb::new("delete-ns")?;
+        let config = RegistryConfig {
+            global_ticker_namespace: true,
+            ..Default::default()
+        };
+        let registry = db.registry(config);
+        let asset = Asset::load("test/asset-b1405e.json".into())?;
+        let ns_path = db.directory.join(NAMESPACE_MAP_DIR).join("PPP");
+        fs::create_dir_all(ns_path.parent().unwrap())?;
+
+        // the ticker is held by another asset, which keeps it
+        fs::write(&ns_path, "00".repeat(32))?;
+        registry.file_handle(&asset).delete()?;
+        assert_eq!(fs::read_to_string(&ns_path)?, "00".repeat(32));
+
+        fs::write(&ns_path, asset.asset_id.to_hex())?;
+        registry.file_handle(&asset).delete()?;
+        assert!(!ns_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_confusable_tickers() -> Result<()> {
+        let db = TestDb::new("tickers")?;
+        let asset = Asset::load("test/asset-b1405e.json".into())?;
+        db.write_asset(&test_asset_json(TEST_ASSET_ID)?)?;
+
+        // another asset of the same domain, with a ticker confusable with PPP
+        let mut confusable = asset.clone();
+        confusable.asset_id = AssetId::from_hex(&"00".repeat(32))?;
+        confusable.fields.ticker = Some("ppp".to_string());
+
+        let mut config = RegistryConfig::default();
+        let registry = db.registry(config.clone());
+        registry.ensure_distinct_ticker(&confusable)?;
+
+        config.reject_confusable_tickers = true;
+        let registry = db.registry(config.clone());
+        let err = registry.ensure_distinct_ticker(&confusable).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ticker \"ppp\" is confusable with the already registered ticker \"PPP\""
+        );
+
+        confusable.fields.ticker = Some("PPQ".to_string());
+        registry.ensure_distinct_ticker(&confusable)?;
+
+        // tickers are only compared within their namespace
+        confusable.fields.ticker = Some("ppp".to_string());
+        confusable.fields.entity = AssetEntity::DomainName("other.dev".to_string());
+        registry.ensure_distinct_ticker(&confusable)?;
+
+        config.global_ticker_namespace = true;
+        let registry = db.registry(config.clone());
+        assert!(registry.ensure_distinct_ticker(&confusable).is_err());
+
+        // quarantined assets are still compared against, also when loaded on startup. unreadable
+        // quarantined files are skipped.
+        registry.quarantine(&asset.asset_id, QuarantineKind::Review)?;
+        assert!(registry.ensure_distinct_ticker(&confusable).is_err());
+        fs::write(
+            db.directory
+                .join(QuarantineKind::Review.dir_name())
+                .join(format!("{}.json", "11".repeat(32))),
+            "not json",
+        )?;
+        let registry = db.registry(config);
+        assert!(registry.ensure_distinct_ticker(&confusable).is_err());
+        confusable.fields.ticker = Some("QQQ".to_string());
+        registry.ensure_distinct_ticker(&confusable)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_entity_limits() -> Result<()> {
+        let db = TestDb::new("entity-limits")?;
+
+        // synthetic copies of the same test.dev asset under different ids, written directly to
+        // the directory like another process sharing it would
+        let write_asset =
+            |i: usize, ticker: Option<&str>, collection: Option<&str>| -> Result<path::PathBuf> {
+                let asset_id = sha256::Hash::hash(format!("limited {}", i).as_bytes()).to_hex();
+                let mut asset = test_asset_json(&asset_id)?;
+                asset["ticker"] = serde_json::json!(ticker);
+                asset["collection"] = serde_json::json!(collection);
+                db.write_asset(&asset)
+            };
+        let candidate = |ticker: Option<&str>, collection: Option<&str>| -> Result<Asset> {
+            let mut asset = Asset::load("test/asset-b1405e.json".into())?;
+            asset.fields.ticker = ticker.map(String::from);
+            asset.fields.collection = collection.map(String::from);
+            Ok(asset)
+        };
+
+        let config = RegistryConfig {
+            file_lock: true,
+            max_tickerless_per_domain: Some(2),
+            max_collections_per_domain: Some(2),
+            max_assets_per_collection: Some(2),
+            ..Default::default()
+        };
+        let registry = db.registry(config);
+
+        // tickerless assets, up to the cap
+        write_asset(0, None, None)?;
+        registry.ensure_entity_limits(&candidate(None, None)?)?;
+        let tickerless = write_asset(1, None, None)?;
+        let err = registry
+            .ensure_entity_limits(&candidate(None, None)?)
+            .unwrap_err();
+        assert!(err.to_string().contains("tickerless"), "{}", err);
+        registry.ensure_entity_limits(&candidate(Some("TCK"), None)?)?;
+
+        // collections, up to the cap. joining an existing one is still allowed.
+        write_asset(2, Some("AA"), Some("first"))?;
+        write_asset(3, Some("BB"), Some("second"))?;
+        let err = registry
+            .ensure_entity_limits(&candidate(Some("TCK"), Some("third"))?)
+            .unwrap_err();
+        assert!(err.to_string().contains("collections"), "{}", err);
+        registry.ensure_entity_limits(&candidate(Some("TCK"), Some("first"))?)?;
+
+        // assets in a collection, up to the cap
+        write_asset(4, Some("CC"), Some("first"))?;
+        let err = registry
+            .ensure_entity_limits(&candidate(Some("TCK"), Some("first"))?)
+            .unwrap_err();
+        assert!(err.to_string().contains("collection first"), "{}", err);
+        registry.ensure_entity_limits(&candidate(Some("TCK"), Some("second"))?)?;
+
+        // assets removed by another process free their slot
+        fs::remove_file(tickerless)?;
+        registry.ensure_entity_limits(&candidate(None, None)?)?;
+        assert_eq!(registry.count()?, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_entity_limits_self_declared() -> Result<()> {
+        let db = TestDb::new("entity-limits-none")?;
+
+        // a tickerless self-declared asset of one issuer
+        let mut asset = test_asset_json(&sha256::Hash::hash(b"self-declared").to_hex())?;
+        asset["ticker"] = serde_json::Value::Null;
+        asset.as_object_mut().req()?.remove("entity");
+        db.write_asset(&asset)?;
+
+        let config = RegistryConfig {
+            file_lock: true,
+            max_tickerless_per_domain: Some(1),
+            max_collections_per_domain: Some(1),
+            max_assets_per_collection: Some(1),
+            ..Default::default()
+        };
+        let registry = db.registry(config);
+
+        // another issuer's self-declared assets aren't counted against the same quota
+        let mut candidate = Asset::load("test/asset-b1405e.json".into())?;
+        candidate.fields.entity = AssetEntity::None;
+        candidate.fields.ticker = None;
+        candidate.fields.issuer_pubkey = crate::fixtures::test_key()
+            .public_key(&secp256k1::Secp256k1::signing_only())
+            .to_bytes();
+        registry.ensure_entity_limits(&candidate)?;
+        candidate.fields.collection = Some("first".to_string());
+        registry.ensure_entity_limits(&candidate)?;
+        assert_eq!(registry.count()?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_assets() -> Result<()> {
+        let db = TestDb::new("max-assets")?;
+        db.write_asset(&test_asset_json(TEST_ASSET_ID)?)?;
+
+        let config = RegistryConfig {
+            max_assets: Some(2),
+            ..Default::default()
+        };
+        let registry = db.registry(config);
+        registry.ensure_capacity(1)?;
+
+        let err = registry.ensure_capacity(2).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RegistryFull>(),
+            Some(&RegistryFull { max_assets: 2 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_misfiled_assets() -> Result<()> {
+        let db = TestDb::new("misfiled")?;
+        let directory = &db.directory;
+        let filename = format!("{}.json", TEST_ASSET_ID);
+        let filed = db.write_asset(&test_asset_json(TEST_ASSET_ID)?)?;
+        fs::create_dir_all(directory.join("ff"))?;
+        fs::copy(&filed, directory.join("ff").join(&filename))?;
+
+        let registry = db.registry(RegistryConfig::default());
+        assert_eq!(
+            registry.misfiled_assets()?,
+            vec![directory.join("ff").join(&filename)]
+        );
+        assert_eq!(registry.asset_ids()?.len(), 1);
+        assert_eq!(registry.iter()?.count(), 1);
+
+        // without the correctly filed copy, the misfiled one isn't listed at all
+        fs::remove_file(filed)?;
+        assert!(registry.asset_ids()?.is_empty());
+        assert_eq!(registry.misfiled_assets()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition() -> Result<()> {
+        let db = TestDb::new("partition")?;
+        let directory = &db.directory;
+        let asset_id = AssetId::from_hex(TEST_ASSET_ID)?;
+        let filename = format!("{}.json", asset_id.to_hex());
+        let other_id = AssetId::from_hex(&sha256::Hash::hash(b"other").to_hex())?;
+
+        let registry = |partition| {
+            let config = RegistryConfig {
+                partition,
+                ..Default::default()
+            };
+            db.registry(config)
+        };
+        let prefixed = registry(Partition::Prefix);
+        let flat = registry(Partition::None);
+
+        // new assets are placed according to the configured layout
+        assert_eq!(
+            prefixed.asset_path(&asset_id),
+            directory.join("b1").join(&filename)
+        );
+        assert_eq!(flat.asset_path(&asset_id), directory.join(&filename));
+
+        // existing files are found in either layout
+        fs::write(
+            directory.join(&filename),
+            fs::read_to_string("test/asset-b1405e.json")?,
+        )?;
+        assert_eq!(prefixed.asset_path(&asset_id), directory.join(&filename));
+        assert!(prefixed.load(&asset_id)?.is_some());
+        assert!(flat.load(&asset_id)?.is_some());
+
+        let other_path = db.write_asset(&test_asset_json(&other_id.to_hex())?)?;
+        assert_eq!(flat.asset_path(&other_id), other_path);
+
+        let mut expected = vec![asset_id, other_id];
+        expected.sort_by_cached_key(|asset_id| asset_id.to_hex());
+        assert_eq!(prefixed.asset_ids()?, expected);
+        assert_eq!(flat.asset_ids()?, expected);
+        assert_eq!(flat.iter()?.count(), 2);
+
+        assert_eq!("prefix".parse::<Partition>()?, Partition::Prefix);
+        assert_eq!("none".parse::<Partition>()?, Partition::None);
+        assert!("flat".parse::<Partition>().is_err());
+        Ok(())
+    }
 }
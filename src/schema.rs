@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::errors::{Error, OptionExt, Result, ResultExt};
+
+// The JSON Schema keywords used for validation. Schemas using any other keyword are rejected
+// when loaded, rather than having their rules silently ignored.
+const VALIDATION_KEYWORDS: [&str; 14] = [
+    "type",
+    "enum",
+    "const",
+    "required",
+    "properties",
+    "additionalProperties",
+    "items",
+    "minItems",
+    "maxItems",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minimum",
+    "maximum",
+];
+
+// Keywords that don't affect validation
+const ANNOTATION_KEYWORDS: [&str; 7] = [
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+];
+
+const TYPES: [&str; 7] = [
+    "object", "array", "string", "number", "integer", "boolean", "null",
+];
+
+// An operator-supplied JSON Schema (https://json-schema.org/) that contracts must match, in
+// addition to the built-in validation rules. Supports the subset of the keywords listed above.
+// Failures identify the rule as a JSON pointer into the schema (e.g. `#/properties/kyc/pattern`)
+// and the failing contract value as a pointer into the contract (e.g. `#/kyc`).
+#[derive(Debug)]
+pub struct ContractSchema {
+    schema: Value,
+    // the compiled `pattern`s, keyed by the schema path of their rule
+    patterns: HashMap<String, Regex>,
+}
+
+impl ContractSchema {
+    pub fn parse(schema: Value) -> Result<Self> {
+        let mut patterns = HashMap::new();
+        compile(&schema, "#", &mut patterns)?;
+        Ok(ContractSchema { schema, patterns })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let schema = fs::read_to_string(path)
+            .context(format!("failed reading contract schema {}", path.display()))?;
+        let schema = serde_json::from_str(&schema)
+            .context(format!("invalid contract schema {}", path.display()))?;
+        Ok(Self::parse(schema).context(format!("invalid contract schema {}", path.display()))?)
+    }
+
+    pub fn validate(&self, contract: &Value) -> Result<()> {
+        self.check(&self.schema, "#", contract, "#")
+    }
+
+    fn check(&self, schema: &Value, schema_path: &str, value: &Value, path: &str) -> Result<()> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok(()),
+            Value::Bool(false) => {
+                return Err(rule_failed(schema_path, path, "no value is allowed"))
+            }
+            Value::Object(schema) => schema,
+            _ => unreachable!("verified by compile()"),
+        };
+        for (keyword, rule) in schema {
+            let rule_path = format!("{}/{}", schema_path, escape(keyword));
+            self.check_keyword(schema, keyword, rule, &rule_path, value, path)?;
+        }
+        Ok(())
+    }
+
+    fn check_keyword(
+        &self,
+        schema: &Map<String, Value>,
+        keyword: &str,
+        rule: &Value,
+        rule_path: &str,
+        value: &Value,
+        path: &str,
+    ) -> Result<()> {
+        let fail = |reason: String| Err(rule_failed(rule_path, path, &reason));
+
+        match (keyword, value) {
+            ("type", _) => {
+                let types: Vec<&str> = match rule {
+                    Value::String(name) => vec![name.as_str()],
+                    _ => rule
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect(),
+                };
+                if !types.iter().any(|name| is_type(value, name)) {
+                    return fail(format!("expected {}", types.join(" or ")));
+                }
+            }
+            ("enum", _) => {
+                if !rule.as_array().unwrap().contains(value) {
+                    return fail(format!("must be one of {}", rule));
+                }
+            }
+            ("const", _) => {
+                if rule != value {
+                    return fail(format!("must be {}", rule));
+                }
+            }
+            ("required", Value::Object(object)) => {
+                for field in rule.as_array().unwrap().iter().filter_map(Value::as_str) {
+                    if !object.contains_key(field) {
+                        return fail(format!("missing required field `{}`", field));
+                    }
+                }
+            }
+            ("properties", Value::Object(object)) => {
+                for (field, schema) in rule.as_object().unwrap() {
+                    if let Some(value) = object.get(field) {
+                        let field = escape(field);
+                        let schema_path = format!("{}/{}", rule_path, field);
+                        self.check(schema, &schema_path, value, &format!("{}/{}", path, field))?;
+                    }
+                }
+            }
+            ("additionalProperties", Value::Object(object)) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for (field, value) in object {
+                    if properties.map_or(false, |props| props.contains_key(field)) {
+                        continue;
+                    }
+                    if *rule == Value::Bool(false) {
+                        return fail(format!("unexpected field `{}`", field));
+                    }
+                    self.check(
+                        rule,
+                        rule_path,
+                        value,
+                        &format!("{}/{}", path, escape(field)),
+                    )?;
+                }
+            }
+            ("items", Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.check(rule, rule_path, item, &format!("{}/{}", path, i))?;
+                }
+            }
+            ("minItems", Value::Array(items)) => {
+                if (items.len() as u64) < rule.as_u64().unwrap() {
+                    return fail(format!("must have at least {} items", rule));
+                }
+            }
+            ("maxItems", Value::Array(items)) => {
+                if items.len() as u64 > rule.as_u64().unwrap() {
+                    return fail(format!("must have at most {} items", rule));
+                }
+            }
+            ("minLength", Value::String(string)) => {
+                if (string.chars().count() as u64) < rule.as_u64().unwrap() {
+                    return fail(format!("must be at least {} characters long", rule));
+                }
+            }
+            ("maxLength", Value::String(string)) => {
+                if string.chars().count() as u64 > rule.as_u64().unwrap() {
+                    return fail(format!("must be at most {} characters long", rule));
+                }
+            }
+            ("pattern", Value::String(string)) => {
+                if !self.patterns[rule_path].is_match(string) {
+                    return fail(format!("must match the pattern {}", rule));
+                }
+            }
+            ("minimum", Value::Number(number)) => {
+                if number.as_f64().unwrap() < rule.as_f64().unwrap() {
+                    return fail(format!("must be at least {}", rule));
+                }
+            }
+            ("maximum", Value::Number(number)) => {
+                if number.as_f64().unwrap() > rule.as_f64().unwrap() {
+                    return fail(format!("must be at most {}", rule));
+                }
+            }
+            // annotations, and keywords that don't apply to the value's type
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+fn rule_failed(rule_path: &str, path: &str, reason: &str) -> Error {
+    format_err!(
+        "contract schema rule `{}` failed at `{}`: {}",
+        rule_path,
+        path,
+        reason
+    )
+}
+
+// Escape a field name for use in a JSON pointer (RFC 6901)
+fn escape(field: &str) -> String {
+    field.replace('~', "~0").replace('/', "~1")
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().map_or(false, |n| n.fract() == 0.0)
+        }
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+// Verify the schema uses the supported keywords correctly, and compile its patterns
+fn compile(schema: &Value, schema_path: &str, patterns: &mut HashMap<String, Regex>) -> Result<()> {
+    let schema = match schema {
+        Value::Bool(_) => return Ok(()),
+        Value::Object(schema) => schema,
+        _ => bail!("`{}` must be a schema object or boolean", schema_path),
+    };
+
+    for (keyword, rule) in schema {
+        let rule_path = format!("{}/{}", schema_path, escape(keyword));
+        if ANNOTATION_KEYWORDS.contains(&keyword.as_str()) {
+            continue;
+        }
+        ensure!(
+            VALIDATION_KEYWORDS.contains(&keyword.as_str()),
+            "unsupported keyword `{}`",
+            rule_path
+        );
+
+        match keyword.as_str() {
+            "type" => {
+                let types = match rule {
+                    Value::String(_) => vec![rule],
+                    Value::Array(types) => types.iter().collect(),
+                    _ => vec![],
+                };
+                ensure!(
+                    !types.is_empty()
+                        && types
+                            .iter()
+                            .all(|name| name.as_str().map_or(false, |name| TYPES.contains(&name))),
+                    "`{}` must be a type name or a list of type names",
+                    rule_path
+                );
+            }
+            "enum" => ensure!(rule.is_array(), "`{}` must be an array", rule_path),
+            "required" => ensure!(
+                rule.as_array()
+                    .map_or(false, |fields| fields.iter().all(Value::is_string)),
+                "`{}` must be an array of field names",
+                rule_path
+            ),
+            "properties" => {
+                let properties = rule
+                    .as_object()
+                    .or_err(format!("`{}` must be an object", rule_path))?;
+                for (field, schema) in properties {
+                    compile(
+                        schema,
+                        &format!("{}/{}", rule_path, escape(field)),
+                        patterns,
+                    )?;
+                }
+            }
+            "additionalProperties" | "items" => compile(rule, &rule_path, patterns)?,
+            "minItems" | "maxItems" | "minLength" | "maxLength" => ensure!(
+                rule.is_u64(),
+                "`{}` must be a non-negative integer",
+                rule_path
+            ),
+            "minimum" | "maximum" => ensure!(rule.is_number(), "`{}` must be a number", rule_path),
+            "pattern" => {
+                let pattern = rule
+                    .as_str()
+                    .or_err(format!("`{}` must be a string", rule_path))?;
+                let regex =
+                    Regex::new(pattern).context(format!("invalid regex at `{}`", rule_path))?;
+                patterns.insert(rule_path, regex);
+            }
+            // `const` accepts any value
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+lazy_static! {
+    static ref LOADED: Mutex<Option<(PathBuf, SystemTime, Arc<ContractSchema>)>> = Mutex::new(None);
+}
+
+// Get the schema stored at `path`. The parsed schema is kept in memory and reloaded
+// whenever the file's modification time changes, so it can be updated in place.
+pub fn load_cached(path: &Path) -> Result<Arc<ContractSchema>> {
+    let mtime = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .context(format!("failed reading contract schema {}", path.display()))?;
+
+    let mut loaded = LOADED.lock().unwrap();
+    if let Some((loaded_path, loaded_mtime, schema)) = &*loaded {
+        if loaded_path == path && *loaded_mtime == mtime {
+            return Ok(schema.clone());
+        }
+    }
+
+    let schema = Arc::new(ContractSchema::load(path)?);
+    *loaded = Some((path.to_path_buf(), mtime, schema.clone()));
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contract_schema() -> Result<()> {
+        let schema = ContractSchema::load(Path::new("test/contract-schema.json"))?;
+
+        let mut contract = json!({
+            "version": 0,
+            "name": "Foo Coin",
+            "kyc_issuer": "kyc.example.com",
+            "jurisdictions": ["CH", "SV"],
+        });
+        schema.validate(&contract)?;
+
+        let fails_with = |contract: &Value, expected: &str| {
+            let err = schema.validate(contract).unwrap_err().to_string();
+            assert_eq!(err, expected);
+        };
+
+        contract["kyc_issuer"] = json!("not a domain");
+        fails_with(
+            &contract,
+            "contract schema rule `#/properties/kyc_issuer/pattern` failed at `#/kyc_issuer`: must match the pattern \"^[a-z0-9.-]+$\"",
+        );
+
+        contract.as_object_mut().unwrap().remove("kyc_issuer");
+        fails_with(
+            &contract,
+            "contract schema rule `#/required` failed at `#`: missing required field `kyc_issuer`",
+        );
+
+        contract["kyc_issuer"] = json!("kyc.example.com");
+        contract["jurisdictions"] = json!(["CH", "Switzerland"]);
+        fails_with(
+            &contract,
+            "contract schema rule `#/properties/jurisdictions/items/maxLength` failed at `#/jurisdictions/1`: must be at most 2 characters long",
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_keyword() {
+        let err = ContractSchema::parse(json!({
+            "properties": { "kyc_issuer": { "$ref": "#/definitions/domain" } }
+        }))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unsupported keyword `#/properties/kyc_issuer/$ref`"
+        );
+    }
+}
@@ -1,22 +1,37 @@
-use std::net;
-use std::path::PathBuf;
-
-use bitcoin_hashes::hex::FromHex;
-use elements::{issuance::ContractHash, AssetId};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use std::{fmt, net, thread};
+
+use bitcoin::PrivateKey;
+use bitcoin_hashes::hex::{FromHex, ToHex};
+use bitcoin_hashes::{sha256, Hash};
+use elements::{issuance::ContractHash, AssetId, OutPoint};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
 use hyper::rt::{Future, Stream};
-use hyper::service::service_fn;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
 use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use secp256k1::Secp256k1;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::sync::Arc;
 
 #[cfg(feature = "cli")]
 use structopt::StructOpt;
 
-use crate::asset::Asset;
+use crate::asset::{Asset, AssetRequest, VerificationStep};
 use crate::chain::ChainQuery;
-use crate::errors::{join_err, Result, ResultExt};
-use crate::registry::Registry;
-use crate::util::serde_from_base64;
+use crate::entity::{describe_verification_methods, domain_proofs, DomainVerificationMethod};
+use crate::errors::{join_err, OptionExt, Result, ResultExt};
+use crate::ratelimit::RateLimiter;
+use crate::registry::{
+    AssetPage, BatchOutcome, Page, QuarantineKind, Registry, RegistryConfig, RegistryFull,
+    SyncSummary,
+};
+use crate::util::{reverse_asset_id, serde_from_base64, sign_response};
 
 #[derive(Debug)]
 #[cfg_attr(feature = "cli", derive(StructOpt))]
@@ -65,8 +80,353 @@ pub struct Config {
         )
     )]
     esplora_url: String,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "esplora-max-concurrency",
+            env,
+            help = "Maximum number of concurrent requests to the esplora api (unbounded if unset)"
+        )
+    )]
+    esplora_max_concurrency: Option<usize>,
+
+    #[cfg_attr(feature = "cli", structopt(flatten))]
+    registry: RegistryConfig,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "cors-allow-origin",
+            env,
+            help = "Enable CORS for the given origin (or `*` to allow any origin)"
+        )
+    )]
+    cors_allow_origin: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "cors-allow-credentials",
+            help = "Echo the configured CORS origin with Access-Control-Allow-Credentials (cannot be used with `*`)"
+        )
+    )]
+    cors_allow_credentials: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "cors-max-age",
+            env,
+            help = "Number of seconds browsers may cache CORS preflight responses for"
+        )
+    )]
+    cors_max_age: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "legal-hold-message",
+            env,
+            default_value = "This asset is unavailable for legal reasons",
+            help = "Response body for assets under legal hold"
+        )
+    )]
+    legal_hold_message: String,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "signing-key",
+            env,
+            help = "WIF-encoded private key for signing successful responses (sent in the X-Registry-Signature header)"
+        )
+    )]
+    signing_key: Option<SigningKey>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "read-only",
+            help = "Reject registrations and deletions, for mirrors/replicas"
+        )
+    )]
+    read_only: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "read-only-allow-validation",
+            help = "Keep the (non-modifying) contract validation and preview endpoints available in read-only mode"
+        )
+    )]
+    read_only_allow_validation: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "serve-confirmations",
+            help = "Include the issuance transaction's current confirmation count in asset responses"
+        )
+    )]
+    serve_confirmations: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "reverify-interval",
+            env,
+            help = "Re-verify the HTTP domain proofs of all assets every this many seconds, logging the ones that fail"
+        )
+    )]
+    reverify_interval: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "watch",
+            env,
+            help = "Watch the database directory for asset files added or removed by other processes (e.g. a git workflow), updating the index without a restart"
+        )
+    )]
+    watch: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "rate-limit",
+            env,
+            help = "Maximum sustained number of write requests (POST/DELETE) per minute from a single client IP"
+        )
+    )]
+    rate_limit: Option<u32>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "rate-limit-burst",
+            env,
+            help = "Number of write requests a client IP can make in a burst before being throttled (defaults to the per-minute rate)"
+        )
+    )]
+    rate_limit_burst: Option<u32>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "rate-limit-exempt",
+            env,
+            use_delimiter = true,
+            number_of_values = 1,
+            help = "Client IPs exempt from the rate limit (comma separated)"
+        )
+    )]
+    rate_limit_exempt: Vec<net::IpAddr>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "trusted-proxies",
+            env,
+            use_delimiter = true,
+            number_of_values = 1,
+            help = "Reverse proxy addresses or CIDR ranges (comma separated) trusted to report the client IP in the --client-ip-header"
+        )
+    )]
+    trusted_proxies: Vec<TrustedProxy>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "client-ip-header",
+            env,
+            default_value = "X-Forwarded-For",
+            help = "Header listing the client IP and the proxies it went through, honored for connections from --trusted-proxies"
+        )
+    )]
+    client_ip_header: String,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "response-header",
+            number_of_values = 1,
+            help = "Static header to add to all responses, as `Name: value` (can be specified multiple times)"
+        )
+    )]
+    response_headers: Vec<ResponseHeader>,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "asset-cache-control",
+            env,
+            default_value = "public, max-age=60",
+            help = "Cache-Control header for asset responses (empty to omit)"
+        )
+    )]
+    asset_cache_control: String,
+
+    #[cfg_attr(
+        feature = "cli",
+        structopt(
+            long = "slow-request-ms",
+            env,
+            help = "Log a warning for requests that take longer than this many milliseconds to handle"
+        )
+    )]
+    slow_request_ms: Option<u64>,
+}
+
+// A static response header, configured as `Name: value`
+#[derive(Debug, Clone)]
+pub struct ResponseHeader {
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+impl FromStr for ResponseHeader {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let sep = s.find(':').or_err(format!(
+            "invalid response header `{}`, expected `Name: value`",
+            s
+        ))?;
+        Ok(ResponseHeader {
+            name: HeaderName::from_bytes(s[..sep].trim().as_bytes())
+                .context(format!("invalid response header name in `{}`", s))?,
+            value: HeaderValue::from_str(s[sep + 1..].trim())
+                .context(format!("invalid response header value in `{}`", s))?,
+        })
+    }
+}
+
+// Private key used to sign responses. Kept out of the Debug output, which gets logged.
+pub struct SigningKey(secp256k1::SecretKey);
+
+impl SigningKey {
+    fn sign(&self, body: &[u8]) -> String {
+        hex::encode(sign_response(&EC, &self.0, body))
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let key = PrivateKey::from_wif(s).context("invalid signing key")?;
+        Ok(SigningKey(key.key))
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SigningKey(<redacted>)")
+    }
+}
+
+// A trusted reverse proxy address, or a range of them in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrustedProxy {
+    addr: net::IpAddr,
+    prefix_len: u8,
+}
+
+impl TrustedProxy {
+    fn contains(&self, ip: net::IpAddr) -> bool {
+        match (self.addr, unmap_ipv4(ip)) {
+            (net::IpAddr::V4(addr), net::IpAddr::V4(ip)) => prefix_match(
+                u32::from(addr).into(),
+                u32::from(ip).into(),
+                32,
+                self.prefix_len,
+            ),
+            (net::IpAddr::V6(addr), net::IpAddr::V6(ip)) => {
+                prefix_match(u128::from(addr), u128::from(ip), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(2, '/');
+        let addr: net::IpAddr = parts
+            .next()
+            .unwrap()
+            .parse()
+            .context(format!("invalid trusted proxy address `{}`", s))?;
+        let addr = unmap_ipv4(addr);
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match parts.next() {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .context(format!("invalid trusted proxy prefix length `{}`", s))?,
+            None => max_len,
+        };
+        ensure!(
+            prefix_len <= max_len,
+            "invalid trusted proxy prefix length `{}`",
+            s
+        );
+        Ok(TrustedProxy { addr, prefix_len })
+    }
+}
+
+// Compare the leading `prefix_len` bits of two `bits`-wide addresses
+fn prefix_match(a: u128, b: u128, bits: u32, prefix_len: u8) -> bool {
+    let shift = bits - u32::from(prefix_len);
+    // shifting by the full width (a zero-length prefix) gives None for both, matching everything
+    a.checked_shr(shift) == b.checked_shr(shift)
+}
+
+// Connections accepted on a dual-stack socket report IPv4 peers as IPv4-mapped IPv6 addresses
+fn unmap_ipv4(ip: net::IpAddr) -> net::IpAddr {
+    match ip {
+        net::IpAddr::V6(ip6) => match ip6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, ..] => ip6.to_ipv4().map_or(ip, net::IpAddr::V4),
+            _ => ip,
+        },
+        _ => ip,
+    }
 }
 
+lazy_static! {
+    static ref EC: Secp256k1<secp256k1::SignOnly> = Secp256k1::signing_only();
+}
+
+// how often to check the retry queue for due hook/webhook runs
+const RETRY_QUEUE_INTERVAL: Duration = Duration::from_secs(10);
+// filesystem events are coalesced over this delay, so that a git merge touching many files
+// triggers a single sync
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+// an asset id for rendering the example proofs in /verification-methods
+const EXAMPLE_ASSET_ID: &str = "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
+
+// maximum number of assets per page of the / index, also used when no ?limit is requested
+const INDEX_MAX_LIMIT: usize = 500;
+
+// maximum number of asset ids in a single /assets?ids= request
+const ASSETS_MAX_IDS: usize = 100;
+
+// number of assets in /feed.json, unless a lower ?limit is requested
+const FEED_DEFAULT_LIMIT: usize = 20;
+const FEED_MAX_LIMIT: usize = 100;
+
+// maximum number of assets in a single batch registration
+const MAX_BATCH_SIZE: usize = 100;
+
+// Detached signature over the response body, see util::verify_response_sig
+pub const SIGNATURE_HEADER: &str = "X-Registry-Signature";
+
+// The code of the verification step that failed, on error responses (see VerificationStep::code)
+pub const VERIFICATION_STEP_HEADER: &str = "X-Verification-Step";
+
 //type ResponseFuture = Box<Future<Item = Response<Body>, Error = hyper::Error> + Send>;
 
 pub fn start_server(config: Config) -> Result<()> {
@@ -74,61 +434,345 @@ pub fn start_server(config: Config) -> Result<()> {
 
     stderrlog::new().verbosity(config.verbose + 2).init().ok();
 
-    let chain = ChainQuery::new(config.esplora_url);
-    let registry = Arc::new(Registry::new(&config.db_path, chain, config.hook_cmd));
+    let cors = Arc::new(Cors::from_config(&config)?);
+
+    let rate_limiter = Arc::new(match config.rate_limit {
+        Some(rate) => {
+            ensure!(rate > 0, "--rate-limit must be positive");
+            let burst = config.rate_limit_burst.unwrap_or(rate);
+            Some(RateLimiter::new(rate, burst, &config.rate_limit_exempt))
+        }
+        None => None,
+    });
+
+    let mut chain = ChainQuery::new(config.esplora_url.clone());
+    if let Some(max_concurrency) = config.esplora_max_concurrency {
+        chain = chain.with_max_concurrency(max_concurrency);
+    }
+    let registry = Arc::new(Registry::new(
+        &config.db_path,
+        chain,
+        config.hook_cmd.clone(),
+        config.registry.clone(),
+    ));
+    match registry.misfiled_assets() {
+        Ok(misfiled) => {
+            for path in misfiled {
+                warn!(
+                    "misfiled asset file {}, it won't be served until moved to its partition directory",
+                    path.display()
+                );
+            }
+        }
+        Err(err) => warn!("failed scanning the database directory: {:?}", err),
+    }
+    if let Some(interval) = config.reverify_interval {
+        let registry = Arc::clone(&registry);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(interval));
+            match registry.reverify_http_proofs() {
+                Ok(failed) => info!("re-verified http proofs, {} failed", failed),
+                Err(err) => warn!("failed re-verifying http proofs: {:?}", err),
+            }
+        });
+    }
+
+    if config.watch {
+        let registry = Arc::clone(&registry);
+        let db_path = config.db_path.clone();
+        thread::spawn(move || {
+            if let Err(err) = watch_db_dir(&registry, &db_path) {
+                warn!("failed watching the database directory: {:?}", err);
+            }
+        });
+    }
+
+    let config = Arc::new(config);
+    let addr = config.addr;
 
-    let make_service = move || {
+    if registry.has_retry_queue() {
         let registry = Arc::clone(&registry);
+        thread::spawn(move || loop {
+            if let Err(err) = registry.retry_pending() {
+                warn!("failed processing the retry queue: {:?}", err);
+            }
+            thread::sleep(RETRY_QUEUE_INTERVAL);
+        });
+    }
+
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let registry = Arc::clone(&registry);
+        let config = Arc::clone(&config);
+        let cors = Arc::clone(&cors);
+        let rate_limiter = Arc::clone(&rate_limiter);
+        let remote_addr = conn.remote_addr();
 
-        service_fn(move |req: Request<Body>| {
+        Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
             let registry = Arc::clone(&registry);
+            let config = Arc::clone(&config);
+            let cors = Arc::clone(&cors);
+            let rate_limiter = Arc::clone(&rate_limiter);
             let method = req.method().clone();
             let uri = req.uri().clone();
+            let headers = req.headers().clone();
+            let started = Instant::now();
 
-            info!("processing {} {}", method, uri);
+            let pretty = wants_pretty(&uri);
+            let client_ip = client_ip(&headers, remote_addr.ip(), &config);
+
+            info!("processing {} {} from {}", method, uri, client_ip);
 
             Box::new(req.into_body().concat2().and_then(move |body| {
-                Ok(match handle_req(method, uri, body, &registry) {
+                let path = uri.path().to_string();
+                let mut response = match handle_req(
+                    method.clone(),
+                    uri,
+                    &headers,
+                    body,
+                    client_ip,
+                    &rate_limiter,
+                    &registry,
+                    &config,
+                ) {
                     Ok(resp) => {
                         info!("replying with {:?}", resp);
 
-                        Response::builder()
+                        let body = resp.body(pretty);
+
+                        let mut builder = Response::builder();
+                        builder
                             .status(resp.status())
                             .header(header::CONTENT_TYPE, resp.content_type())
-                            .body(resp.body())
-                            .unwrap()
+                            .header(header::CONTENT_LENGTH, body.len().to_string());
+                        if let Some(etag) = resp.etag(&body) {
+                            builder.header(header::ETAG, etag);
+                        }
+                        for (name, value) in resp.headers() {
+                            builder.header(name, value.as_str());
+                        }
+                        if let Some(key) = &config.signing_key {
+                            if resp.status() == StatusCode::OK {
+                                builder.header(SIGNATURE_HEADER, key.sign(&body));
+                            }
+                        }
+
+                        // HEAD responses carry the same headers as GET, without the body
+                        if method == Method::HEAD {
+                            builder.body(Body::empty()).unwrap()
+                        } else {
+                            builder.body(Body::from(body)).unwrap()
+                        }
                     }
 
                     Err(err) => {
-                        warn!("error processing request: {:?}", err);
+                        warn!("error processing request from {}: {:?}", client_ip, err);
 
                         #[cfg(not(feature = "dev"))]
                         let body = join_err(&err);
                         #[cfg(feature = "dev")]
                         let body = format!("{:#?}", err);
 
-                        Response::builder()
-                            .status(StatusCode::BAD_REQUEST)
-                            .body(Body::from(body))
-                            .unwrap()
+                        let status = if err.downcast_ref::<RegistryFull>().is_some() {
+                            StatusCode::INSUFFICIENT_STORAGE
+                        } else {
+                            StatusCode::BAD_REQUEST
+                        };
+                        let mut builder = Response::builder();
+                        builder.status(status);
+                        if let Some(step) = VerificationStep::of_error(&err) {
+                            builder.header(VERIFICATION_STEP_HEADER, step.code());
+                        }
+                        builder.body(Body::from(body)).unwrap()
+                    }
+                };
+
+                if let Some(cors) = &*cors {
+                    cors.apply(&method, headers.get(header::ORIGIN), response.headers_mut());
+                }
+                // the headers set by the response itself take precedence over the configured ones
+                for extra in &config.response_headers {
+                    if !response.headers().contains_key(&extra.name) {
+                        response
+                            .headers_mut()
+                            .insert(extra.name.clone(), extra.value.clone());
                     }
-                })
+                }
+
+                let elapsed = started.elapsed();
+                registry
+                    .metrics()
+                    .record_request(&endpoint_label(&method, &path), elapsed);
+                if let Some(slow_request_ms) = config.slow_request_ms {
+                    if elapsed > Duration::from_millis(slow_request_ms) {
+                        warn!(
+                            "slow request: {} {} from {} took {:?}",
+                            method, path, client_ip, elapsed
+                        );
+                    }
+                }
+
+                Ok(response)
             }))
-        })
-    };
+        }))
+    });
 
-    let server = Server::bind(&config.addr).serve(make_service);
+    let server = Server::bind(&addr).serve(make_service);
 
-    info!("Starting web server on {:?}", config.addr);
+    info!("Starting web server on {:?}", addr);
     hyper::rt::run(server.map_err(|e| warn!("server error: {:?}", e)));
 
     Ok(())
 }
 
+// Keep the registry in sync with asset files added or removed by other processes, such as a git
+// workflow merging pull requests into the database directory. Runs until the watcher fails.
+// Modifications of existing asset files aren't synced, see `Registry::sync_changes()`.
+fn watch_db_dir(registry: &Registry, db_path: &Path) -> Result<()> {
+    // the event paths are absolute and have symlinks resolved, compare them to the same form
+    let db_path = &db_path
+        .canonicalize()
+        .context("failed resolving the database directory")?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::watcher(tx, WATCH_DEBOUNCE).context("failed creating watcher")?;
+    watcher
+        .watch(db_path, RecursiveMode::Recursive)
+        .context("failed watching directory")?;
+
+    let mut known = registry.asset_ids()?.into_iter().collect();
+    info!("watching {} for asset file changes", db_path.display());
+
+    for event in rx {
+        let relevant = match &event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path) => is_asset_file(db_path, path),
+            DebouncedEvent::Rename(from, to) => {
+                is_asset_file(db_path, from) || is_asset_file(db_path, to)
+            }
+            // events were dropped, a full re-scan is needed
+            DebouncedEvent::Rescan => true,
+            DebouncedEvent::Error(err, path) => {
+                warn!("watch error for {:?}: {:?}", path, err);
+                false
+            }
+            _ => false,
+        };
+        if !relevant {
+            continue;
+        }
+        match registry.sync_changes(&mut known) {
+            Ok(summary) if summary != SyncSummary::default() => info!(
+                "synced out-of-band changes: {} assets added, {} removed",
+                summary.added, summary.removed
+            ),
+            Ok(_) => (),
+            Err(err) => warn!("failed syncing out-of-band changes: {:?}", err),
+        }
+    }
+    Ok(())
+}
+
+// Asset json files within the database directory, as opposed to files under the registry's own
+// `_`-prefixed directories or hidden ones (like `.git`). Only the components below the database
+// directory are checked, so that it can itself be under a hidden directory.
+fn is_asset_file(db_path: &Path, path: &Path) -> bool {
+    let relative = match path.strip_prefix(db_path) {
+        Ok(relative) => relative,
+        Err(_) => return false,
+    };
+    let excluded_dir = relative.components().any(|c| match c {
+        Component::Normal(name) => name
+            .to_str()
+            .map_or(false, |name| name.starts_with('_') || name.starts_with('.')),
+        _ => false,
+    });
+    path.extension().map_or(false, |ext| ext == "json") && !excluded_dir
+}
+
+#[derive(Debug)]
+struct Cors {
+    origin: HeaderValue,
+    allow_credentials: bool,
+    max_age: Option<u64>,
+}
+
+impl Cors {
+    fn from_config(config: &Config) -> Result<Option<Self>> {
+        let origin = match &config.cors_allow_origin {
+            Some(origin) => origin,
+            None => {
+                ensure!(
+                    !config.cors_allow_credentials,
+                    "--cors-allow-credentials requires --cors-allow-origin"
+                );
+                return Ok(None);
+            }
+        };
+
+        // Per the CORS spec, credentialed requests cannot use the wildcard origin
+        ensure!(
+            !(config.cors_allow_credentials && origin == "*"),
+            "--cors-allow-credentials requires a specific origin, not `*`"
+        );
+
+        Ok(Some(Cors {
+            origin: HeaderValue::from_str(origin).context("invalid CORS origin")?,
+            allow_credentials: config.cors_allow_credentials,
+            max_age: config.cors_max_age,
+        }))
+    }
+
+    fn apply(&self, method: &Method, req_origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        if self.allow_credentials {
+            // Only echo the origin back when it matches the configured one
+            match req_origin {
+                Some(origin) if *origin == self.origin => (),
+                _ => return,
+            }
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+            headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+        }
+
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, self.origin.clone());
+
+        if *method == Method::OPTIONS {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static("GET, HEAD, POST, DELETE, OPTIONS"),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static("Content-Type"),
+            );
+            if let Some(max_age) = self.max_age {
+                headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 enum Resp {
-    Json(StatusCode, Value),
+    Json(StatusCode, Value, Vec<(HeaderName, String)>),
     Plain(StatusCode, String),
+    Raw {
+        status: StatusCode,
+        content_type: &'static str,
+        headers: Vec<(HeaderName, String)>,
+        body: RawBody,
+    },
+}
+
+// Raw response bodies can be large, don't dump them into the logs
+struct RawBody(Vec<u8>);
+
+impl fmt::Debug for RawBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} bytes>", self.0.len())
+    }
 }
 
 impl Resp {
@@ -136,69 +780,769 @@ impl Resp {
     where
         T: serde::ser::Serialize,
     {
-        Resp::Json(code, serde_json::to_value(value).unwrap())
+        Resp::Json(code, serde_json::to_value(value).unwrap(), vec![])
     }
     fn plain(code: StatusCode, message: &str) -> Resp {
         Resp::Plain(code, message.into())
     }
-    fn body(&self) -> Body {
-        Body::from(match self {
-            Resp::Plain(_, message) => message.into(),
-            Resp::Json(_, value) => serde_json::to_string(value).unwrap(),
-        })
+    fn raw(
+        status: StatusCode,
+        content_type: &'static str,
+        headers: Vec<(HeaderName, String)>,
+        body: Vec<u8>,
+    ) -> Resp {
+        Resp::Raw {
+            status,
+            content_type,
+            headers,
+            body: RawBody(body),
+        }
+    }
+    fn body(&self, pretty: bool) -> Vec<u8> {
+        match self {
+            Resp::Plain(_, message) => message.clone().into_bytes(),
+            Resp::Json(_, value, _) if pretty => serde_json::to_vec_pretty(value).unwrap(),
+            Resp::Json(_, value, _) => serde_json::to_vec(value).unwrap(),
+            Resp::Raw { body, .. } => body.0.clone(),
+        }
+    }
+    fn etag(&self, body: &[u8]) -> Option<String> {
+        match self {
+            Resp::Json(StatusCode::OK, ..) => Some(make_etag(body)),
+            _ => None,
+        }
+    }
+    fn headers(&self) -> &[(HeaderName, String)] {
+        match self {
+            Resp::Json(_, _, headers) | Resp::Raw { headers, .. } => headers,
+            Resp::Plain(..) => &[],
+        }
+    }
+    fn with_header(self, name: HeaderName, value: String) -> Resp {
+        match self {
+            Resp::Json(status, body, mut headers) => {
+                headers.push((name, value));
+                Resp::Json(status, body, headers)
+            }
+            Resp::Raw {
+                status,
+                content_type,
+                mut headers,
+                body,
+            } => {
+                headers.push((name, value));
+                Resp::Raw {
+                    status,
+                    content_type,
+                    headers,
+                    body,
+                }
+            }
+            Resp::Plain(status, message) => Resp::raw(
+                status,
+                "text/plain",
+                vec![(name, value)],
+                message.into_bytes(),
+            ),
+        }
     }
     fn content_type(&self) -> &'static str {
         match self {
             Resp::Plain(..) => "text/plain",
             Resp::Json(..) => "application/json",
+            Resp::Raw { content_type, .. } => *content_type,
         }
     }
     fn status(&self) -> StatusCode {
         match self {
             Resp::Plain(status, _) => *status,
-            Resp::Json(status, _) => *status,
+            Resp::Json(status, ..) => *status,
+            Resp::Raw { status, .. } => *status,
+        }
+    }
+}
+
+// Parse the request query string, ignoring unknown parameters
+fn parse_query<T: DeserializeOwned>(uri: &hyper::Uri) -> Result<T> {
+    Ok(serde_urlencoded::from_str(uri.query().unwrap_or("")).context("invalid query string")?)
+}
+
+#[derive(Deserialize)]
+struct FormatQuery {
+    pretty: Option<String>,
+}
+
+// JSON responses are compact by default, `?pretty=1` (or just `?pretty`) makes them pretty-printed
+fn wants_pretty(uri: &hyper::Uri) -> bool {
+    parse_query::<FormatQuery>(uri)
+        .ok()
+        .and_then(|query| query.pretty)
+        .map_or(false, |pretty| pretty != "0" && pretty != "false")
+}
+
+fn make_etag(body: &[u8]) -> String {
+    format!("\"{}\"", sha256::Hash::hash(body).to_hex())
+}
+
+// The client IP. For connections from a trusted proxy, this is the rightmost untrusted address in
+// the client IP header: each proxy appends the address it received the request from, so entries
+// to the left of the first untrusted one may have been forged by the client.
+fn client_ip(headers: &HeaderMap, remote_ip: net::IpAddr, config: &Config) -> net::IpAddr {
+    let is_trusted = |ip: net::IpAddr| config.trusted_proxies.iter().any(|p| p.contains(ip));
+    let remote_ip = unmap_ipv4(remote_ip);
+    if !is_trusted(remote_ip) {
+        return remote_ip;
+    }
+
+    // multiple header lines are equivalent to a single comma-separated one
+    let forwarded = headers
+        .get_all(config.client_ip_header.as_str())
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut client_ip = remote_ip;
+    for entry in forwarded.rsplit(',') {
+        match entry.trim().parse() {
+            Ok(ip) => client_ip = unmap_ipv4(ip),
+            // can't tell who added it, stop at the last address that could be verified
+            Err(_) => break,
+        }
+        if !is_trusted(client_ip) {
+            break;
         }
     }
+    client_ip
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_req(
     method: Method,
     uri: hyper::Uri,
+    headers: &HeaderMap,
     body: hyper::Chunk,
+    client_ip: net::IpAddr,
+    rate_limiter: &Option<RateLimiter>,
     registry: &Registry,
+    config: &Config,
 ) -> Result<Resp> {
+    let is_write = method == Method::POST || method == Method::DELETE;
+    if let (true, Some(rate_limiter)) = (is_write, rate_limiter) {
+        if let Err(retry_after) = rate_limiter.check(client_ip) {
+            warn!("rate limiting {} {} from {}", method, uri, client_ip);
+            // round up, so that retrying after the advertised delay succeeds
+            let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            return Ok(Resp::raw(
+                StatusCode::TOO_MANY_REQUESTS,
+                "text/plain",
+                vec![(header::RETRY_AFTER, retry_after.to_string())],
+                b"Too many requests".to_vec(),
+            ));
+        }
+    }
+
+    if config.read_only && !allowed_read_only(&method, uri.path(), config) {
+        return Ok(Resp::raw(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "text/plain",
+            vec![(header::ALLOW, "GET, HEAD, OPTIONS".to_string())],
+            b"The registry is read-only".to_vec(),
+        ));
+    }
+
     match (method, uri.path()) {
+        (Method::GET, "/export.ndjson") | (Method::HEAD, "/export.ndjson") => {
+            handle_export(headers, registry)
+        }
+        (Method::GET, "/export.json") | (Method::HEAD, "/export.json") => {
+            handle_export_blockstream(registry)
+        }
+        (Method::GET, "/") => handle_index(&uri, registry),
+        (Method::GET, "/assets") | (Method::HEAD, "/assets") => {
+            handle_assets(&uri, registry, config)
+        }
+        (Method::GET, "/feed.json") => handle_feed(&uri, registry),
+        (Method::GET, "/stats") => handle_stats(registry),
+        (Method::GET, "/metrics") => handle_metrics(registry),
+        (Method::GET, "/verification-methods") => handle_verification_methods(registry),
+        (Method::GET, "/proofs") => handle_domain_proofs(&uri),
+        (Method::GET, "/collections") => Ok(Resp::json(StatusCode::OK, registry.collections()?)),
         (Method::POST, "/") => handle_update(body, registry),
-        (Method::GET, path) => handle_get(&path[1..], registry),
+        (Method::POST, "/preview") => handle_preview(body, registry),
+        (Method::POST, "/register-batch") => handle_register_batch(body, registry),
+        (Method::GET, path) if path.starts_with("/ticker/") => {
+            handle_get_by_ticker(&path[8..], &uri, registry)
+        }
+        (Method::GET, path) | (Method::HEAD, path) if path.len() > 5 && path.ends_with("/raw") => {
+            handle_get_raw(&path[1..path.len() - 4], registry, config)
+        }
+        (Method::GET, path) | (Method::HEAD, path) => handle_get(&path[1..], registry, config),
         (Method::DELETE, path) => handle_delete(&path[1..], body, registry),
-        (Method::POST, "/contract/validate") => handle_contract_validate(body),
+        (Method::POST, "/contract/validate") => handle_contract_validate(body, registry),
+        // CORS preflight, the relevant headers are added by Cors::apply(). without CORS
+        // configured there's nothing to answer preflights with.
+        (Method::OPTIONS, _) if config.cors_allow_origin.is_some() => {
+            Ok(Resp::plain(StatusCode::NO_CONTENT, ""))
+        }
 
         _ => Ok(Resp::plain(StatusCode::NOT_FOUND, "Not Found")),
     }
 }
 
-fn handle_get(asset_id: &str, registry: &Registry) -> Result<Resp> {
+// The endpoint a request is routed to, for tracking metrics. Asset ids and tickers in the path are
+// replaced with placeholders, so that requests for different assets are grouped together.
+fn endpoint_label(method: &Method, path: &str) -> String {
+    let endpoint = match path {
+        "/"
+        | "/export.ndjson"
+        | "/export.json"
+        | "/assets"
+        | "/feed.json"
+        | "/stats"
+        | "/metrics"
+        | "/verification-methods"
+        | "/proofs"
+        | "/collections"
+        | "/preview"
+        | "/register-batch"
+        | "/contract/validate" => path,
+        _ if path.starts_with("/ticker/") => "/ticker/:ticker",
+        _ if path.len() > 5 && path.ends_with("/raw") => "/:asset_id/raw",
+        _ => "/:asset_id",
+    };
+    format!("{} {}", method, endpoint)
+}
+
+fn handle_get(asset_id: &str, registry: &Registry, config: &Config) -> Result<Resp> {
     let asset_id = AssetId::from_hex(asset_id)?;
 
     Ok(match registry.load(&asset_id)? {
-        Some(asset) => Resp::json(StatusCode::OK, asset),
+        Some(asset) => asset_resp(asset, registry, config)?,
+        None => match registry.quarantine_status(&asset_id) {
+            Some(QuarantineKind::LegalHold) => legal_hold_resp(config),
+            Some(QuarantineKind::Review) => Resp::plain(StatusCode::NOT_FOUND, "Not Found"),
+            // the asset id might've been provided in the wrong byte order, try the reverse
+            None => {
+                let reversed_id = reverse_asset_id(&asset_id);
+                match registry.load(&reversed_id)? {
+                    Some(asset) => {
+                        warn!(
+                            "asset id {} not found, serving its reversed byte order form {}",
+                            asset_id.to_hex(),
+                            reversed_id.to_hex()
+                        );
+                        asset_resp(asset, registry, config)?
+                    }
+                    None => match registry.quarantine_status(&reversed_id) {
+                        Some(QuarantineKind::LegalHold) => legal_hold_resp(config),
+                        _ => Resp::plain(StatusCode::NOT_FOUND, "Not Found"),
+                    },
+                }
+            }
+        },
+    })
+}
+
+#[derive(Deserialize)]
+struct TickerQuery {
+    domain: String,
+}
+
+// Resolve a ticker to the asset that claimed it within the domain. Not cacheable like the asset
+// responses, since the ticker may be claimed by another asset after a deletion.
+fn handle_get_by_ticker(ticker: &str, uri: &hyper::Uri, registry: &Registry) -> Result<Resp> {
+    let query = parse_query::<TickerQuery>(uri)?;
+
+    Ok(match registry.load_by_ticker(ticker, &query.domain)? {
+        Some(asset) => Resp::json(StatusCode::OK, asset_json(&asset)?),
         None => Resp::plain(StatusCode::NOT_FOUND, "Not Found"),
     })
 }
 
+// Serve the asset file verbatim, for mirrors that need to reproduce the store byte-for-byte
+fn handle_get_raw(asset_id: &str, registry: &Registry, config: &Config) -> Result<Resp> {
+    let asset_id = AssetId::from_hex(asset_id)?;
+
+    Ok(match registry.load_raw(&asset_id)? {
+        Some(contents) => {
+            let mut headers = vec![(header::ETAG, make_etag(&contents))];
+            // the body can't carry the `unverified` flag of self-declared assets, it's sent as a
+            // header instead
+            let asset: Asset = serde_json::from_slice(&contents)?;
+            if asset.entity().is_none() {
+                headers.push((
+                    header::HeaderName::from_static("x-asset-unverified"),
+                    "true".to_string(),
+                ));
+            }
+            cacheable(
+                Resp::raw(StatusCode::OK, "application/json", headers, contents),
+                config,
+            )
+        }
+        None => match registry.quarantine_status(&asset_id) {
+            Some(QuarantineKind::LegalHold) => legal_hold_resp(config),
+            _ => Resp::plain(StatusCode::NOT_FOUND, "Not Found"),
+        },
+    })
+}
+
+// The asset json as served, with an `unverified` flag for self-declared assets. Computed on the fly
+// and never persisted. Used by every response that includes assets, so the flag can't be missed by
+// clients reading them from any of the endpoints.
+fn asset_json(asset: &Asset) -> Result<Value> {
+    let mut asset_json = serde_json::to_value(asset)?;
+    if asset.entity().is_none() {
+        asset_json["unverified"] = json!(true);
+    }
+    Ok(asset_json)
+}
+
+// The asset json, with the issuance confirmations added when enabled. These are computed on the
+// fly and never persisted. Failing to query the confirmations is not fatal, the field is omitted
+// instead.
+fn asset_resp(asset: Asset, registry: &Registry, config: &Config) -> Result<Resp> {
+    let mut asset_json = asset_json(&asset)?;
+    if !config.serve_confirmations {
+        return Ok(cacheable(Resp::json(StatusCode::OK, asset_json), config));
+    }
+
+    match registry
+        .chain()
+        .get_confirmations(&asset.issuance_txin.txid)
+    {
+        Ok(confirmations) => {
+            asset_json["confirmations"] = json!(confirmations.unwrap_or(0));
+        }
+        Err(err) => warn!(
+            "failed fetching confirmations for {}: {:?}",
+            asset.asset_id.to_hex(),
+            err
+        ),
+    }
+    Ok(Resp::json(StatusCode::OK, asset_json))
+}
+
+// The response for assets under legal hold. Never stored by caches, so that the asset isn't served
+// from them once it's released.
+fn legal_hold_resp(config: &Config) -> Resp {
+    Resp::plain(
+        StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+        &config.legal_hold_message,
+    )
+    .with_header(header::CACHE_CONTROL, "no-store".to_string())
+}
+
+// Asset records don't change, but they can be deleted or put on legal hold, so their responses are
+// only cached briefly by default (and not at all when they include the current confirmation count,
+// see asset_resp)
+fn cacheable(resp: Resp, config: &Config) -> Resp {
+    if config.asset_cache_control.is_empty() {
+        resp
+    } else {
+        resp.with_header(header::CACHE_CONTROL, config.asset_cache_control.clone())
+    }
+}
+
+fn allowed_read_only(method: &Method, path: &str, config: &Config) -> bool {
+    match (method, path) {
+        (&Method::GET, _) | (&Method::HEAD, _) | (&Method::OPTIONS, _) => true,
+        (&Method::POST, "/preview") | (&Method::POST, "/contract/validate") => {
+            config.read_only_allow_validation
+        }
+        _ => false,
+    }
+}
+
+#[derive(Deserialize)]
+struct IndexQuery {
+    after: Option<AssetId>,
+    limit: Option<usize>,
+    minimal: Option<String>,
+    domain: Option<String>,
+}
+
+// A page of the registered assets as a json map keyed by the asset id, with the cursor of the next
+// page in `next` (when more remain). With `?domain=<domain>`, all the assets linked to the domain
+// are returned instead, unpaged (so the paging parameters are rejected with it). With `?minimal=1`,
+// only the asset names and tickers are included, to keep the payload small.
+fn handle_index(uri: &hyper::Uri, registry: &Registry) -> Result<Resp> {
+    let query = parse_query::<IndexQuery>(uri)?;
+    ensure!(
+        query.domain.is_none() || (query.limit.is_none() && query.after.is_none()),
+        "`limit` and `after` can't be used with `domain`, which isn't paged"
+    );
+    let minimal = query
+        .minimal
+        .map_or(false, |minimal| minimal != "0" && minimal != "false");
+    let AssetPage { assets, next } = match &query.domain {
+        Some(domain) => AssetPage {
+            assets: registry.list_by_domain(domain)?,
+            next: None,
+        },
+        None => registry.list(&Page {
+            after: query.after,
+            limit: query
+                .limit
+                .unwrap_or(INDEX_MAX_LIMIT)
+                .min(INDEX_MAX_LIMIT)
+                .max(1),
+        })?,
+    };
+
+    // the map keys are sorted, so the assets are kept in paging order
+    let assets: serde_json::Map<String, Value> = assets
+        .into_iter()
+        .map(|asset| -> Result<(String, Value)> {
+            let asset_json = if minimal {
+                let mut summary =
+                    json!({ "name": asset.fields.name, "ticker": asset.fields.ticker });
+                if asset.entity().is_none() {
+                    summary["unverified"] = json!(true);
+                }
+                summary
+            } else {
+                asset_json(&asset)?
+            };
+            Ok((asset.asset_id.to_hex(), asset_json))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut resp = json!({ "assets": assets });
+    if let Some(next) = next {
+        resp["next"] = json!(next.to_hex());
+    }
+    Ok(Resp::json(StatusCode::OK, resp))
+}
+
+#[derive(Deserialize)]
+struct AssetsQuery {
+    ids: String,
+}
+
+// Several assets by their comma-separated ids, as a json map keyed by the asset id with null for
+// the missing ones. Cacheable like single assets, unless some are missing (they might get
+// registered later).
+fn handle_assets(uri: &hyper::Uri, registry: &Registry, config: &Config) -> Result<Resp> {
+    let asset_ids = parse_asset_ids(&parse_query::<AssetsQuery>(uri)?.ids)?;
+
+    let mut assets = serde_json::Map::new();
+    for asset_id in asset_ids {
+        let asset_json = match registry.load(&asset_id)? {
+            Some(asset) => asset_json(&asset)?,
+            None => Value::Null,
+        };
+        assets.insert(asset_id.to_hex(), asset_json);
+    }
+
+    let resp = Resp::json(StatusCode::OK, &assets);
+    Ok(if assets.values().all(|asset| !asset.is_null()) {
+        cacheable(resp, config)
+    } else {
+        resp
+    })
+}
+
+// Parse a comma-separated list of asset ids, of up to ASSETS_MAX_IDS
+fn parse_asset_ids(ids: &str) -> Result<Vec<AssetId>> {
+    let asset_ids = ids
+        .split(',')
+        .map(str::trim)
+        .filter(|asset_id| !asset_id.is_empty())
+        .map(AssetId::from_hex)
+        .collect::<std::result::Result<Vec<AssetId>, _>>()
+        .context("invalid asset id")?;
+    ensure!(!asset_ids.is_empty(), "no asset ids provided");
+    ensure!(
+        asset_ids.len() <= ASSETS_MAX_IDS,
+        "too many asset ids, up to {} can be requested at once",
+        ASSETS_MAX_IDS
+    );
+    Ok(asset_ids)
+}
+
+#[derive(Deserialize)]
+struct ProofsQuery {
+    domain: String,
+    ids: String,
+}
+
+// The HTTP and DNS proofs to publish for linking each of the assets to the domain
+fn handle_domain_proofs(uri: &hyper::Uri) -> Result<Resp> {
+    let query = parse_query::<ProofsQuery>(uri)?;
+    let asset_ids = parse_asset_ids(&query.ids)?;
+    Ok(Resp::json(
+        StatusCode::OK,
+        domain_proofs(&query.domain, &asset_ids)?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct FeedQuery {
+    limit: Option<usize>,
+}
+
+// The most recently registered assets as a JSON Feed (https://jsonfeed.org/version/1.1), newest
+// first, for polling new registrations without downloading the full export. The assets are
+// included under the `_liquid_asset` extension key.
+fn handle_feed(uri: &hyper::Uri, registry: &Registry) -> Result<Resp> {
+    let limit = parse_query::<FeedQuery>(uri)?
+        .limit
+        .unwrap_or(FEED_DEFAULT_LIMIT)
+        .min(FEED_MAX_LIMIT);
+
+    let items: Vec<Value> = registry
+        .recent(limit)?
+        .into_iter()
+        .map(|asset| -> Result<Value> {
+            let title = match &asset.fields.ticker {
+                Some(ticker) => format!("{} ({})", asset.fields.name, ticker),
+                None => asset.fields.name.clone(),
+            };
+            Ok(json!({
+                "id": asset.asset_id.to_hex(),
+                "title": title,
+                "content_text": format!("{} registered by {}", title, asset.fields.entity),
+                "date_published": asset.created_at.map(|time| time.to_rfc3339()),
+                "_liquid_asset": asset_json(&asset)?,
+            }))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Resp::json(
+        StatusCode::OK,
+        json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": "Liquid Asset Registry",
+            "items": items,
+        }),
+    ))
+}
+
+fn handle_stats(registry: &Registry) -> Result<Resp> {
+    Ok(Resp::json(
+        StatusCode::OK,
+        json!({ "asset_count": registry.count()? }),
+    ))
+}
+
+fn handle_verification_methods(registry: &Registry) -> Result<Resp> {
+    let default_method = registry
+        .validation()
+        .default_verification_method
+        .unwrap_or(DomainVerificationMethod::Http);
+    let example_asset_id = AssetId::from_hex(EXAMPLE_ASSET_ID)?;
+    Ok(Resp::json(
+        StatusCode::OK,
+        describe_verification_methods(default_method, "example.com", &example_asset_id),
+    ))
+}
+
+fn handle_metrics(registry: &Registry) -> Result<Resp> {
+    let metrics = registry.metrics();
+    Ok(Resp::json(
+        StatusCode::OK,
+        json!({
+            "verification_failures": metrics.verification_failures(),
+            "request_latencies": metrics.request_latencies(),
+        }),
+    ))
+}
+
+// Export all assets in the format of the public assets.blockstream.info registry feed, as a JSON
+// object keyed by the asset id. Usable as a drop-in mirror of the canonical registry.
+fn handle_export_blockstream(registry: &Registry) -> Result<Resp> {
+    let mut assets = serde_json::Map::new();
+    for asset in registry.iter()? {
+        let asset = asset?;
+        let mut asset_json = json!({
+            "contract": asset.contract,
+            "issuance_txin": asset.issuance_txin,
+            "issuance_prevout": asset.issuance_prevout,
+            "name": asset.fields.name,
+            "ticker": asset.fields.ticker,
+            "precision": asset.fields.precision,
+            "entity": asset.fields.entity,
+        });
+        if asset.entity().is_none() {
+            asset_json["unverified"] = json!(true);
+        }
+        assets.insert(asset.asset_id.to_hex(), asset_json);
+    }
+    Ok(Resp::json(StatusCode::OK, Value::Object(assets)))
+}
+
+// Export all assets as newline-delimited JSON, sorted by asset id. Supports resuming
+// interrupted downloads using single `Range` requests, guarded by `If-Range` to avoid
+// resuming from a different version of the export.
+fn handle_export(headers: &HeaderMap, registry: &Registry) -> Result<Resp> {
+    let mut body = Vec::new();
+    for asset in registry.iter()? {
+        serde_json::to_writer(&mut body, &asset_json(&asset?)?)?;
+        body.push(b'\n');
+    }
+
+    let etag = make_etag(&body);
+    let mut resp_headers = vec![
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+        (header::ETAG, etag.clone()),
+    ];
+
+    let range = headers
+        .get(header::RANGE)
+        .filter(|_| {
+            headers
+                .get(header::IF_RANGE)
+                .map_or(true, |if_range| if_range == etag.as_str())
+        })
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.strip_prefix("bytes="))
+        // multiple ranges are not supported, the full content is served instead
+        .filter(|range| !range.contains(','));
+
+    let content_type = "application/x-ndjson";
+
+    Ok(match range {
+        None => Resp::raw(StatusCode::OK, content_type, resp_headers, body),
+        Some(range) => match parse_byte_range(range, body.len()) {
+            Some((start, end)) => {
+                resp_headers.push((
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, body.len()),
+                ));
+                let partial = body[start..=end].to_vec();
+                Resp::raw(
+                    StatusCode::PARTIAL_CONTENT,
+                    content_type,
+                    resp_headers,
+                    partial,
+                )
+            }
+            None => {
+                resp_headers.push((header::CONTENT_RANGE, format!("bytes */{}", body.len())));
+                Resp::raw(
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    content_type,
+                    resp_headers,
+                    vec![],
+                )
+            }
+        },
+    })
+}
+
+// Parse a single byte range spec (`start-end`, `start-` or `-suffix`) into an inclusive
+// (start, end) range. Returns None if the range is invalid or not satisfiable.
+fn parse_byte_range(spec: &str, len: usize) -> Option<(usize, usize)> {
+    let last = len.checked_sub(1)?;
+    let sep = spec.find('-')?;
+    let (start, end) = (spec[..sep].trim(), spec[sep + 1..].trim());
+
+    let (start, end) = if start.is_empty() {
+        // suffix range, for the last N bytes
+        let suffix: usize = end.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        (len.saturating_sub(suffix), last)
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = if end.is_empty() {
+            last
+        } else {
+            end.parse::<usize>().ok()?.min(last)
+        };
+        (start, end)
+    };
+
+    if start > end {
+        None
+    } else {
+        Some((start, end))
+    }
+}
+
 fn handle_update(body: hyper::Chunk, registry: &Registry) -> Result<Resp> {
-    let asset = Asset::from_request(
+    let mut asset = Asset::from_request(
         serde_json::from_slice(&body.to_vec()).context("failed parsing json request")?,
         registry.chain(),
     )?;
 
     debug!("write asset: {:?}", asset);
 
-    registry.write(&asset)?;
+    registry.write(&mut asset)?;
 
     Ok(Resp::json(StatusCode::CREATED, &asset))
 }
 
+// Register multiple assets atomically, replying with the per-asset outcomes
+fn handle_register_batch(body: hyper::Chunk, registry: &Registry) -> Result<Resp> {
+    let reqs: Vec<AssetRequest> =
+        serde_json::from_slice(&body.to_vec()).context("failed parsing json request")?;
+    ensure!(!reqs.is_empty(), "empty batch");
+    ensure!(
+        reqs.len() <= MAX_BATCH_SIZE,
+        "batches are limited to {} assets",
+        MAX_BATCH_SIZE
+    );
+
+    let asset_ids: Vec<AssetId> = reqs.iter().map(|req| req.asset_id).collect();
+    let mut assets = vec![];
+    let mut outcomes = vec![];
+    for req in reqs {
+        match Asset::from_request(req, registry.chain()) {
+            Ok(asset) => {
+                assets.push(asset);
+                outcomes.push(BatchOutcome::Aborted);
+            }
+            Err(err) => outcomes.push(BatchOutcome::Failed {
+                error: join_err(&err),
+            }),
+        }
+    }
+
+    if assets.len() == outcomes.len() {
+        debug!("write asset batch: {:?}", assets);
+        outcomes = registry.write_batch(&mut assets)?;
+    }
+
+    let status = if outcomes.iter().any(BatchOutcome::is_failed) {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::CREATED
+    };
+
+    let results: Vec<Value> = asset_ids
+        .iter()
+        .zip(outcomes)
+        .enumerate()
+        .map(|(i, (asset_id, outcome))| {
+            let mut result = json!(outcome);
+            result["asset_id"] = json!(asset_id.to_hex());
+            if status == StatusCode::CREATED {
+                result["asset"] = json!(assets[i]);
+            }
+            result
+        })
+        .collect();
+
+    Ok(Resp::json(status, results))
+}
+
+fn handle_preview(body: hyper::Chunk, registry: &Registry) -> Result<Resp> {
+    let mut asset = Asset::from_request(
+        serde_json::from_slice(&body.to_vec()).context("failed parsing json request")?,
+        registry.chain(),
+    )?;
+
+    debug!("preview asset: {:?}", asset);
+
+    registry.preview(&mut asset)?;
+
+    Ok(Resp::json(StatusCode::OK, &asset))
+}
+
 fn handle_delete(asset_id: &str, body: hyper::Chunk, registry: &Registry) -> Result<Resp> {
     let asset_id = AssetId::from_hex(asset_id)?;
     let asset = match registry.load(&asset_id)? {
@@ -215,12 +1559,21 @@ fn handle_delete(asset_id: &str, body: hyper::Chunk, registry: &Registry) -> Res
     Ok(Resp::plain(StatusCode::OK, "Asset deleted"))
 }
 
-fn handle_contract_validate(body: hyper::Chunk) -> Result<Resp> {
+fn handle_contract_validate(body: hyper::Chunk, registry: &Registry) -> Result<Resp> {
     let request: ValidationRequest =
         serde_json::from_slice(&body.to_vec()).context("invalid validation request")?;
 
-    Asset::validate_contract(&request.contract, &request.contract_hash)?;
-    Ok(Resp::plain(StatusCode::OK, "valid"))
+    let asset_id = Asset::validate_contract(
+        &request.contract,
+        &request.contract_hash,
+        request.prevout.as_ref(),
+        registry.validation(),
+    )?;
+
+    Ok(match asset_id {
+        Some(asset_id) => Resp::json(StatusCode::OK, json!({ "asset_id": asset_id })),
+        None => Resp::plain(StatusCode::OK, "valid"),
+    })
 }
 
 #[derive(Deserialize)]
@@ -233,39 +1586,86 @@ struct DeletionRequest {
 struct ValidationRequest {
     contract: Value,
     contract_hash: ContractHash,
+    prevout: Option<OutPoint>,
 }
 
 // needs to be run with --test-threads 1
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{asset::Asset, chain, client::Client, entity, errors::OptionExt};
-    use bitcoin::util::misc::signed_msg_hash;
+    use crate::{
+        asset::{contract_json_canonical, format_deletion_sig_msg, Asset, VerificationStep},
+        chain,
+        client::{AssetSummary, Client, RegistrationError},
+        entity, fixtures,
+    };
     use bitcoin::PrivateKey;
-    use bitcoin_hashes::{hex::ToHex, Hash};
+    use bitcoin_hashes::hex::ToHex;
     use secp256k1::Secp256k1;
     use std::{str::FromStr, thread, time::Duration};
 
     lazy_static! {
         static ref CLIENT: Client = Client::new("http://localhost:49013".parse().unwrap());
         static ref EC: Secp256k1<secp256k1::SignOnly> = Secp256k1::signing_only();
-        static ref ISSUER_KEY: PrivateKey =
-            PrivateKey::from_wif("cRmFPw94iHgnmUMui5brPsbH5F7wNmvgVkAGJYqZaK33F5vzCAST").unwrap();
+        static ref ISSUER_KEY: PrivateKey = fixtures::test_key();
     }
 
-    fn spawn_test_server() {
-        let config = Config {
+    fn test_config() -> Config {
+        Config {
             verbose: 1,
             hook_cmd: None,
             addr: "127.0.0.1:49013".parse().unwrap(),
             esplora_url: "http://localhost:58713".to_string(),
+            esplora_max_concurrency: Some(4),
             db_path: std::env::temp_dir()
                 .join(format!("asset-registry-testdb-{}", std::process::id())),
-        };
+            registry: RegistryConfig::default(),
+            cors_allow_origin: None,
+            cors_allow_credentials: false,
+            cors_max_age: None,
+            legal_hold_message: "unavailable for legal reasons".to_string(),
+            signing_key: None,
+            read_only: false,
+            read_only_allow_validation: false,
+            serve_confirmations: false,
+            reverify_interval: None,
+            watch: false,
+            rate_limit: None,
+            rate_limit_burst: None,
+            rate_limit_exempt: vec![],
+            trusted_proxies: vec![],
+            client_ip_header: "X-Forwarded-For".to_string(),
+            response_headers: vec!["X-Content-Type-Options: nosniff".parse().unwrap()],
+            asset_cache_control: "public, max-age=60".to_string(),
+            slow_request_ms: Some(5000),
+        }
+    }
+
+    fn spawn_test_server() {
+        let config = test_config();
+
+        std::fs::create_dir_all(&config.db_path).unwrap();
+
+        thread::spawn(|| start_server(config).unwrap());
+    }
 
-        std::fs::create_dir_all(&config.db_path).unwrap();
+    // Spawn another server on its own port and database directory, for tests that need a
+    // different configuration than the shared test server. Returns its base url.
+    fn spawn_custom_server(port: u16, name: &str, customize: impl FnOnce(&mut Config)) -> String {
+        let mut config = test_config();
+        config.addr = ([127, 0, 0, 1], port).into();
+        config.db_path = std::env::temp_dir().join(format!(
+            "asset-registry-testdb-{}-{}",
+            name,
+            std::process::id()
+        ));
+        customize(&mut config);
 
+        std::fs::create_dir_all(&config.db_path).unwrap();
         thread::spawn(|| start_server(config).unwrap());
+        thread::sleep(Duration::from_millis(250));
+
+        format!("http://localhost:{}", port)
     }
 
     #[test]
@@ -280,6 +1680,58 @@ mod tests {
         thread::sleep(Duration::from_millis(250));
     }
 
+    #[test]
+    fn test0_client_ip() -> Result<()> {
+        let ip = |ip: &str| ip.parse::<net::IpAddr>().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "203.0.113.9, 192.0.2.1, 10.1.2.3".parse()?,
+        );
+
+        // the header is ignored unless the connection comes from a trusted proxy
+        let mut config = test_config();
+        assert_eq!(
+            client_ip(&headers, ip("127.0.0.1"), &config),
+            ip("127.0.0.1")
+        );
+
+        config.trusted_proxies = vec!["127.0.0.1".parse()?, "10.0.0.0/8".parse()?];
+        assert_eq!(
+            client_ip(&headers, ip("198.51.100.7"), &config),
+            ip("198.51.100.7")
+        );
+
+        // the rightmost untrusted entry is used, the ones before it may be forged
+        assert_eq!(
+            client_ip(&headers, ip("127.0.0.1"), &config),
+            ip("192.0.2.1")
+        );
+        assert_eq!(
+            client_ip(&headers, ip("::ffff:127.0.0.1"), &config),
+            ip("192.0.2.1")
+        );
+
+        headers.insert("x-forwarded-for", "192.0.2.1, garbage".parse()?);
+        assert_eq!(
+            client_ip(&headers, ip("127.0.0.1"), &config),
+            ip("127.0.0.1")
+        );
+
+        headers.remove("x-forwarded-for");
+        assert_eq!(
+            client_ip(&headers, ip("127.0.0.1"), &config),
+            ip("127.0.0.1")
+        );
+
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("fd00::/8".parse::<TrustedProxy>()?.contains(ip("fd12::1")));
+        assert!("0.0.0.0/0"
+            .parse::<TrustedProxy>()?
+            .contains(ip("192.0.2.1")));
+        Ok(())
+    }
+
     #[test]
     fn test1_register_then_delete() -> Result<()> {
         // Register
@@ -297,13 +1749,19 @@ mod tests {
 
         let asset = CLIENT.register(&asset_req)?;
         assert_eq!(asset.name(), "PPP coin");
+        assert!(asset.created_at.is_some());
+        assert_eq!(asset.http_proof_version, Some(entity::HttpProofVersion::V1));
+        let issuance_details = asset.issuance_details.as_ref().unwrap();
+        assert_eq!(issuance_details.block.as_ref().unwrap().block_height, 999);
+        assert_eq!(
+            issuance_details.issuance_type,
+            chain::IssuanceType::Issuance
+        );
         info!("asset created successfully");
 
         // Delete
-        let msg_to_sign = format!("remove {} from registry", asset.asset_id);
-        let msg_hash = signed_msg_hash(&msg_to_sign);
-        let msg_secp = secp256k1::Message::from_slice(&msg_hash.into_inner())?;
-        let signature = EC.sign(&msg_secp, &ISSUER_KEY.key).serialize_compact();
+        let msg_to_sign = format_deletion_sig_msg(&asset, true)?;
+        let signature = fixtures::sign_message(&msg_to_sign);
 
         CLIENT.delete(&asset.asset_id, &signature)?;
 
@@ -352,6 +1810,57 @@ mod tests {
     }
     */
 
+    #[test]
+    fn test2_preview() -> Result<()> {
+        let asset_req = json!({
+            "asset_id":"b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05",
+            "contract":{
+                "entity":{"domain":"test.dev"},
+                "issuer_pubkey": "037c7db0528e8b7b58e698ac104764f6852d74b5a7335bffcdad0ce799dd7742ec",
+                "name":"PPP coin",
+                "ticker":"PPP",
+                "version":0
+            },
+        });
+
+        // already registered by test1, the preview should report the conflict
+        let resp = reqwest::blocking::Client::new()
+            .post("http://localhost:49013/preview")
+            .json(&asset_req)
+            .send()?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert!(resp.text()?.contains("updates are not allowed"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test2_register_error() -> Result<()> {
+        // a contract that doesn't match the one committed to in the issuance
+        let asset_req = serde_json::from_value(json!({
+            "asset_id":"b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05",
+            "contract":{
+                "entity":{"domain":"test.dev"},
+                "issuer_pubkey": "037c7db0528e8b7b58e698ac104764f6852d74b5a7335bffcdad0ce799dd7742ec",
+                "name":"QQQ coin",
+                "ticker":"QQQ",
+                "version":0
+            },
+        }))?;
+
+        let err = CLIENT
+            .register(&asset_req)
+            .expect_err("mismatching contract should be rejected");
+        match err.downcast_ref::<RegistrationError>() {
+            Some(RegistrationError::Verification { step, .. }) => {
+                assert_eq!(*step, VerificationStep::IssuanceCommitment)
+            }
+            other => bail!("unexpected registration error {:?}", other),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test4_get() -> Result<()> {
         let asset_id =
@@ -371,6 +1880,83 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test4_get_raw() -> Result<()> {
+        let asset_id = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let resp = reqwest::blocking::get(&format!("http://localhost:49013/{}/raw", asset_id))?
+            .error_for_status()?;
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        assert!(resp.headers().get("etag").is_some());
+
+        let raw = resp.bytes()?;
+        let asset: Asset = serde_json::from_slice(&raw)?;
+        assert_eq!(asset.id().to_hex(), asset_id);
+
+        let resp = reqwest::blocking::get(&format!(
+            "http://localhost:49013/{}/raw",
+            "0000000000000000000000000000000000000000000000000000000000000000"
+        ))?;
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[test]
+    fn test4_response_headers() -> Result<()> {
+        let resp = reqwest::blocking::get(
+            "http://localhost:49013/b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05",
+        )?;
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(resp.headers()["Cache-Control"], "public, max-age=60");
+        assert_eq!(resp.headers()["X-Content-Type-Options"], "nosniff");
+
+        // the configured headers apply to all responses, but only asset responses are cacheable
+        let resp = reqwest::blocking::get("http://localhost:49013/stats")?;
+        assert_eq!(resp.headers()["X-Content-Type-Options"], "nosniff");
+        assert!(resp.headers().get("Cache-Control").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test4_head() -> Result<()> {
+        let url = "http://localhost:49013/b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let rclient = reqwest::blocking::Client::new();
+
+        let get_resp = rclient.get(url).send()?.error_for_status()?;
+        let head_resp = rclient.head(url).send()?.error_for_status()?;
+
+        for name in &["content-length", "etag"] {
+            assert_eq!(
+                get_resp.headers().get(*name),
+                head_resp.headers().get(*name)
+            );
+        }
+        assert!(head_resp.headers().contains_key("etag"));
+        assert_eq!(head_resp.text()?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test4_pretty() -> Result<()> {
+        let url = "http://localhost:49013/b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let rclient = reqwest::blocking::Client::new();
+
+        let compact = rclient.get(url).send()?.error_for_status()?.text()?;
+        let pretty = rclient
+            .get(&format!("{}?pretty=1", url))
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        assert!(!compact.contains('\n'));
+        assert!(pretty.contains('\n'));
+        assert_eq!(
+            serde_json::from_str::<Value>(&compact)?,
+            serde_json::from_str::<Value>(&pretty)?
+        );
+        Ok(())
+    }
+
     #[test]
     fn test5_multiple_tickerless() -> Result<()> {
         let asset1 = CLIENT.register(&serde_json::from_value(json!({
@@ -414,10 +2000,673 @@ mod tests {
         }))?)?;
 
         assert_eq!(asset.fields.collection, Some("TAZ/ZAT".to_string()));
+        // served by the mock verifier under the v2 proof path
+        assert_eq!(asset.http_proof_version, Some(entity::HttpProofVersion::V2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test7_collections() -> Result<()> {
+        let collections: Value = reqwest::blocking::get("http://localhost:49013/collections")?
+            .error_for_status()?
+            .json()?;
+        assert_eq!(
+            collections,
+            json!([{ "name": "TAZ/ZAT", "entity": { "domain": "test.dev" }, "asset_count": 1 }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test7_stats() -> Result<()> {
+        let stats: Value = reqwest::blocking::get("http://localhost:49013/stats")?
+            .error_for_status()?
+            .json()?;
+        assert_eq!(stats["asset_count"], 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test7_metrics() -> Result<()> {
+        let metrics: Value = reqwest::blocking::get("http://localhost:49013/metrics")?
+            .error_for_status()?
+            .json()?;
+        // counted by the step that failed, like the mismatching contract of test2_register_error
+        let failures = metrics["verification_failures"].as_array().req()?;
+        assert!(failures.iter().any(|failure| {
+            failure["domain"] == "test.dev" && failure["reason"] == "issuance_commitment"
+        }));
+        let codes: Vec<&str> = VerificationStep::ALL
+            .iter()
+            .map(|step| step.code())
+            .collect();
+        for failure in failures {
+            let reason = failure["reason"].as_str().req()?;
+            assert!(reason == "other" || codes.contains(&reason), "{}", reason);
+        }
+
+        // earlier requests are tracked by their endpoint
+        let latencies = metrics["request_latencies"].as_array().req()?;
+        for endpoint in &["GET /:asset_id", "POST /", "GET /ticker/:ticker"] {
+            assert!(
+                latencies
+                    .iter()
+                    .any(|latency| latency["endpoint"] == *endpoint),
+                "missing {}",
+                endpoint
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_endpoint_label() {
+        assert_eq!(endpoint_label(&Method::GET, "/"), "GET /");
+        assert_eq!(endpoint_label(&Method::GET, "/stats"), "GET /stats");
+        assert_eq!(
+            endpoint_label(&Method::GET, &format!("/{}", EXAMPLE_ASSET_ID)),
+            "GET /:asset_id"
+        );
+        assert_eq!(
+            endpoint_label(&Method::HEAD, &format!("/{}/raw", EXAMPLE_ASSET_ID)),
+            "HEAD /:asset_id/raw"
+        );
+        assert_eq!(
+            endpoint_label(&Method::DELETE, &format!("/{}", EXAMPLE_ASSET_ID)),
+            "DELETE /:asset_id"
+        );
+        assert_eq!(
+            endpoint_label(&Method::GET, "/ticker/PPP"),
+            "GET /ticker/:ticker"
+        );
+    }
+
+    #[test]
+    fn test7_feed() -> Result<()> {
+        let feed: Value = reqwest::blocking::get("http://localhost:49013/feed.json?limit=2")?
+            .error_for_status()?
+            .json()?;
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+
+        // newest first
+        let items = feed["items"].as_array().req()?;
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0]["id"],
+            "38dac0ec084ebc86cae69bd50ad1c46f1b9b6791dc77762e63baeb0548b0df69"
+        );
+        assert_eq!(items[0]["title"], "Qux");
+        assert_eq!(items[0]["_liquid_asset"]["name"], "Qux");
+        assert!(items[0]["date_published"].as_str() >= items[1]["date_published"].as_str());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test7_index() -> Result<()> {
+        let index: Value = reqwest::blocking::get("http://localhost:49013/")?
+            .error_for_status()?
+            .json()?;
+        let assets = index["assets"].as_object().req()?;
+        assert_eq!(assets.len(), 4);
+        assert!(index.get("next").is_none());
+        let asset_id = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        assert_eq!(assets[asset_id]["name"], "PPP coin");
+        let asset_ids: Vec<&String> = assets.keys().collect();
+
+        let minimal: Value = reqwest::blocking::get("http://localhost:49013/?minimal=1")?
+            .error_for_status()?
+            .json()?;
+        assert_eq!(
+            minimal["assets"][asset_id],
+            json!({ "name": "PPP coin", "ticker": "PPP" })
+        );
+
+        // paging through with the cursor returns every asset once, in order
+        let mut paged = vec![];
+        let mut url = "http://localhost:49013/?limit=3".to_string();
+        loop {
+            let page: Value = reqwest::blocking::get(&url)?.error_for_status()?.json()?;
+            paged.extend(page["assets"].as_object().req()?.keys().cloned());
+            match page["next"].as_str() {
+                Some(next) => url = format!("http://localhost:49013/?limit=3&after={}", next),
+                None => break,
+            }
+        }
+        assert_eq!(paged.iter().collect::<Vec<_>>(), asset_ids);
+
+        let resp = reqwest::blocking::get("http://localhost:49013/?after=foo")?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        // filtered by the linked domain
+        let by_domain: Value = reqwest::blocking::get("http://localhost:49013/?domain=test.dev")?
+            .error_for_status()?
+            .json()?;
+        assert_eq!(by_domain["assets"].as_object().req()?.len(), 4);
+        let by_domain: Value = reqwest::blocking::get("http://localhost:49013/?domain=other.dev")?
+            .error_for_status()?
+            .json()?;
+        assert_eq!(by_domain["assets"], json!({}));
+        let resp = reqwest::blocking::get("http://localhost:49013/?domain=foo%20bar")?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        // the domain filter isn't paged
+        let after = format!("domain=test.dev&after={}", asset_id);
+        for query in &["domain=test.dev&limit=1", after.as_str()] {
+            let resp = reqwest::blocking::get(&format!("http://localhost:49013/?{}", query))?;
+            assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        }
 
         Ok(())
     }
 
+    #[test]
+    fn test7_get_by_ticker() -> Result<()> {
+        let asset: Asset =
+            reqwest::blocking::get("http://localhost:49013/ticker/PPP?domain=test.dev")?
+                .error_for_status()?
+                .json()?;
+        assert_eq!(
+            asset.asset_id.to_hex(),
+            "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05"
+        );
+
+        let resp = reqwest::blocking::get("http://localhost:49013/ticker/PPP?domain=other.dev")?;
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+        let resp = reqwest::blocking::get("http://localhost:49013/ticker/NOPE?domain=test.dev")?;
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let resp = reqwest::blocking::get("http://localhost:49013/ticker/P?domain=test.dev")?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert!(resp.text()?.contains("invalid `ticker`"));
+        let resp = reqwest::blocking::get("http://localhost:49013/ticker/PPP")?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test7_domain_proofs() -> Result<()> {
+        let asset_id = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let proofs: Value = reqwest::blocking::get(&format!(
+            "http://localhost:49013/proofs?domain=example.com&ids={},{}",
+            asset_id, EXAMPLE_ASSET_ID
+        ))?
+        .error_for_status()?
+        .json()?;
+        assert_eq!(proofs.as_array().req()?.len(), 2);
+        assert_eq!(
+            proofs[0],
+            json!({
+                "asset_id": asset_id,
+                "body": format!("Authorize linking the domain name example.com to the Liquid asset {}", asset_id),
+                "http_path": format!("/.well-known/liquid-asset-proof-{}", asset_id),
+                "dns_record_name": "_liquid-asset-proof.example.com",
+            })
+        );
+
+        let resp = reqwest::blocking::get(&format!(
+            "http://localhost:49013/proofs?domain=foo%20bar&ids={}",
+            asset_id
+        ))?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test7_assets_by_ids() -> Result<()> {
+        let found = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let missing = "0000000000000000000000000000000000000000000000000000000000000000";
+
+        let resp = reqwest::blocking::get(&format!(
+            "http://localhost:49013/assets?ids={},{}",
+            found, missing
+        ))?
+        .error_for_status()?;
+        assert!(resp.headers().get("cache-control").is_none());
+        let assets: Value = resp.json()?;
+        assert_eq!(assets.as_object().req()?.len(), 2);
+        assert_eq!(assets[found]["name"], "PPP coin");
+        assert_eq!(assets[missing], Value::Null);
+
+        let resp = reqwest::blocking::get(&format!("http://localhost:49013/assets?ids={}", found))?
+            .error_for_status()?;
+        assert!(resp.headers().get("cache-control").is_some());
+
+        let too_many = vec![missing; ASSETS_MAX_IDS + 1].join(",");
+        let resp =
+            reqwest::blocking::get(&format!("http://localhost:49013/assets?ids={}", too_many))?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test7_export_blockstream() -> Result<()> {
+        let export: Value = reqwest::blocking::get("http://localhost:49013/export.json")?
+            .error_for_status()?
+            .json()?;
+        let assets = export.as_object().req()?;
+        assert_eq!(assets.len(), 4);
+
+        let asset = &assets["b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05"];
+        assert_eq!(asset["name"], "PPP coin");
+        assert_eq!(asset["ticker"], "PPP");
+        assert_eq!(asset["entity"], json!({ "domain": "test.dev" }));
+        assert!(asset.get("issuer_pubkey").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test7_export() -> Result<()> {
+        let url = "http://localhost:49013/export.ndjson";
+        let rclient = reqwest::blocking::Client::new();
+
+        let full_resp = rclient.get(url).send()?.error_for_status()?;
+        let etag = full_resp.headers()["etag"].clone();
+        let full = full_resp.text()?;
+        assert_eq!(full.lines().count(), 4);
+
+        let resp = rclient
+            .get(url)
+            .header("range", "bytes=10-")
+            .header("if-range", etag)
+            .send()?;
+        assert_eq!(resp.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.text()?, &full[10..]);
+
+        // a stale If-Range serves the full content
+        let resp = rclient
+            .get(url)
+            .header("range", "bytes=10-")
+            .header("if-range", "\"stale\"")
+            .send()?;
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(resp.text()?, full);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test8_register_batch() -> Result<()> {
+        let asset_req = json!({
+            "asset_id": "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05",
+            "contract": {
+                "entity": { "domain": "test.dev" },
+                "issuer_pubkey": ISSUER_KEY.public_key(&EC),
+                "name": "PPP coin",
+                "ticker": "PPP",
+                "version": 0
+            },
+        });
+        let rclient = reqwest::blocking::Client::new();
+
+        // duplicates within the batch fail before anything gets written
+        let resp = rclient
+            .post("http://localhost:49013/register-batch")
+            .json(&json!([asset_req, asset_req]))
+            .send()?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let results: Value = resp.json()?;
+        assert_eq!(results[0]["status"], "aborted");
+        assert_eq!(results[1]["status"], "failed");
+        assert_eq!(results[1]["error"], "duplicate asset in batch");
+
+        // the asset is already registered, so the batch fails and nothing changes
+        let resp = rclient
+            .post("http://localhost:49013/register-batch")
+            .json(&json!([asset_req]))
+            .send()?;
+        assert_eq!(resp.status(), reqwest::StatusCode::BAD_REQUEST);
+        let results: Value = resp.json()?;
+        assert_eq!(results[0]["status"], "failed");
+        assert_eq!(
+            results[0]["asset_id"],
+            "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05"
+        );
+        assert!(CLIENT
+            .get(&AssetId::from_hex(results[0]["asset_id"].as_str().req()?)?)?
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test9_client_index() -> Result<()> {
+        let mut registered = vec![];
+        for name in &["Indexed 1", "Indexed 2"] {
+            let fixture = fixtures::Fixture::named(name)?;
+            fixture.write(&chain::tests::mock_fixtures_dir())?;
+            registered.push(CLIENT.register(&serde_json::from_value(json!({
+                "asset_id": fixture.asset.asset_id,
+                "contract": fixture.asset.contract,
+            }))?)?);
+        }
+
+        let index = CLIENT.index()?;
+        let minimal = CLIENT.index_minimal()?;
+        for asset in &registered {
+            assert_eq!(index[&asset.asset_id].fields.name, asset.fields.name);
+            assert_eq!(
+                minimal[&asset.asset_id],
+                AssetSummary {
+                    name: asset.fields.name.clone(),
+                    ticker: None
+                }
+            );
+        }
+        assert_eq!(index.len(), minimal.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test9_unverified_flag() -> Result<()> {
+        let url = spawn_custom_server(49014, "entityless", |config| {
+            config.registry.validation.allow_entityless_assets = true
+        });
+
+        let fixture = fixtures::Fixture::new(
+            json!({ "name": "Self-declared", "version": 1 }),
+            &ISSUER_KEY,
+            b"self-declared",
+        )?;
+        fixture.write(&chain::tests::mock_fixtures_dir())?;
+        let signature = fixtures::sign_message(&contract_json_canonical(&fixture.asset.contract)?);
+        Client::new(url.parse()?).register(&serde_json::from_value(json!({
+            "asset_id": fixture.asset.asset_id,
+            "contract": fixture.asset.contract,
+            "contract_signature": base64::encode(&signature),
+        }))?)?;
+
+        let asset_id = fixture.asset.asset_id.to_hex();
+        let get = |path: &str| -> Result<reqwest::blocking::Response> {
+            Ok(reqwest::blocking::get(&format!("{}{}", url, path))?.error_for_status()?)
+        };
+
+        let asset: Value = get(&format!("/{}", asset_id))?.json()?;
+        assert_eq!(asset["unverified"], true);
+        let index: Value = get("/")?.json()?;
+        assert_eq!(index["assets"][&asset_id]["unverified"], true);
+        let minimal: Value = get("/?minimal=1")?.json()?;
+        assert_eq!(minimal["assets"][&asset_id]["unverified"], true);
+        let assets: Value = get(&format!("/assets?ids={}", asset_id))?.json()?;
+        assert_eq!(assets[&asset_id]["unverified"], true);
+        let feed: Value = get("/feed.json")?.json()?;
+        assert_eq!(feed["items"][0]["_liquid_asset"]["unverified"], true);
+        let export: Value = get("/export.json")?.json()?;
+        assert_eq!(export[&asset_id]["unverified"], true);
+        let ndjson = get("/export.ndjson")?.text()?;
+        let line: Value = serde_json::from_str(ndjson.lines().next().req()?)?;
+        assert_eq!(line["unverified"], true);
+
+        // the raw file is served verbatim, with the flag as a header
+        let raw = get(&format!("/{}/raw", asset_id))?;
+        assert_eq!(raw.headers()["x-asset-unverified"], "true");
+        let raw: Value = serde_json::from_slice(&raw.bytes()?)?;
+        assert!(raw.get("unverified").is_none());
+
+        // assets linked to a domain aren't flagged
+        let index: Value = reqwest::blocking::get("http://localhost:49013/")?.json()?;
+        for asset in index["assets"].as_object().req()?.values() {
+            assert!(asset.get("unverified").is_none());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test9_serve_confirmations() -> Result<()> {
+        // serves the assets registered on the shared test server
+        let url = spawn_custom_server(49015, "confirmations", |config| {
+            config.db_path = test_config().db_path;
+            config.serve_confirmations = true;
+        });
+        let asset_id = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+
+        // the mock esplora has the issuance at height 999 and the tip at 1004
+        let asset: Value = reqwest::blocking::get(&format!("{}/{}", url, asset_id))?
+            .error_for_status()?
+            .json()?;
+        assert_eq!(asset["confirmations"], 6);
+
+        let asset: Value = reqwest::blocking::get(&format!("http://localhost:49013/{}", asset_id))?
+            .error_for_status()?
+            .json()?;
+        assert!(asset.get("confirmations").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test9_cors() -> Result<()> {
+        let url = spawn_custom_server(49016, "cors", |config| {
+            config.db_path = test_config().db_path;
+            config.cors_allow_origin = Some("https://app.example".to_string());
+            config.cors_allow_credentials = true;
+            config.cors_max_age = Some(600);
+        });
+        let asset_url = format!(
+            "{}/b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05",
+            url
+        );
+        let client = reqwest::blocking::Client::new();
+
+        let preflight = client
+            .request(reqwest::Method::OPTIONS, &asset_url)
+            .header("origin", "https://app.example")
+            .header("access-control-request-method", "GET")
+            .send()?;
+        assert_eq!(preflight.status(), reqwest::StatusCode::NO_CONTENT);
+        let headers = preflight.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://app.example"
+        );
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(
+            headers["access-control-allow-methods"],
+            "GET, HEAD, POST, DELETE, OPTIONS"
+        );
+        assert_eq!(headers["access-control-allow-headers"], "Content-Type");
+        assert_eq!(headers["access-control-max-age"], "600");
+        assert_eq!(headers["vary"], "Origin");
+
+        let resp = client
+            .get(&asset_url)
+            .header("origin", "https://app.example")
+            .send()?
+            .error_for_status()?;
+        let headers = resp.headers();
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://app.example"
+        );
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert!(headers.get("access-control-allow-methods").is_none());
+        assert!(headers.get("access-control-max-age").is_none());
+
+        // credentials are only allowed for the configured origin
+        let resp = client
+            .get(&asset_url)
+            .header("origin", "https://other.example")
+            .send()?
+            .error_for_status()?;
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+        assert!(resp
+            .headers()
+            .get("access-control-allow-credentials")
+            .is_none());
+
+        // and CORS is disabled unless configured
+        let resp = client
+            .get(&asset_url.replace(&url, "http://localhost:49013"))
+            .header("origin", "https://app.example")
+            .send()?;
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+        let resp = client
+            .request(
+                reqwest::Method::OPTIONS,
+                &asset_url.replace(&url, "http://localhost:49013"),
+            )
+            .header("origin", "https://app.example")
+            .header("access-control-request-method", "GET")
+            .send()?;
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test9_legal_hold() -> Result<()> {
+        let mut db_path = None;
+        let url = spawn_custom_server(49017, "legal-hold", |config| {
+            config.legal_hold_message = "withheld by court order".to_string();
+            db_path = Some(config.db_path.clone());
+        });
+        let asset_id = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let legal_hold_dir = db_path.req()?.join("_legal_hold");
+        std::fs::create_dir_all(&legal_hold_dir)?;
+        std::fs::copy(
+            "test/asset-b1405e.json",
+            legal_hold_dir.join(format!("{}.json", asset_id)),
+        )?;
+
+        for path in &[format!("/{}", asset_id), format!("/{}/raw", asset_id)] {
+            let resp = reqwest::blocking::get(&format!("{}{}", url, path))?;
+            assert_eq!(
+                resp.status(),
+                reqwest::StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS
+            );
+            assert_eq!(resp.headers()["cache-control"], "no-store");
+            assert_eq!(resp.text()?, "withheld by court order");
+        }
+
+        // the hold also applies when the asset id is given in the reversed byte order
+        let reversed_id = reverse_asset_id(&AssetId::from_hex(asset_id)?);
+        let resp = reqwest::blocking::get(&format!("{}/{}", url, reversed_id.to_hex()))?;
+        assert_eq!(
+            resp.status(),
+            reqwest::StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test9_read_only() -> Result<()> {
+        let url = spawn_custom_server(49018, "read-only", |config| {
+            config.db_path = test_config().db_path;
+            config.read_only = true;
+            config.read_only_allow_validation = true;
+        });
+        let asset_id = "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05";
+        let client = reqwest::blocking::Client::new();
+
+        let rejected = vec![
+            client.post(&url).json(&json!({})),
+            client
+                .post(&format!("{}/register-batch", url))
+                .json(&json!([])),
+            client.delete(&format!("{}/{}", url, asset_id)),
+        ];
+        for req in rejected {
+            let resp = req.send()?;
+            assert_eq!(resp.status(), reqwest::StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(resp.headers()["allow"], "GET, HEAD, OPTIONS");
+            assert_eq!(resp.text()?, "The registry is read-only");
+        }
+
+        // reads and (with --read-only-allow-validation) validation are still served
+        client
+            .get(&format!("{}/{}", url, asset_id))
+            .send()?
+            .error_for_status()?;
+        let resp = client
+            .post(&format!("{}/contract/validate", url))
+            .json(&json!({}))
+            .send()?;
+        assert_ne!(resp.status(), reqwest::StatusCode::METHOD_NOT_ALLOWED);
+
+        let mut config = test_config();
+        config.read_only = true;
+        assert!(!allowed_read_only(&Method::POST, "/preview", &config));
+        assert!(!allowed_read_only(
+            &Method::POST,
+            "/contract/validate",
+            &config
+        ));
+        assert!(allowed_read_only(&Method::OPTIONS, "/", &config));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test9_registry_full() -> Result<()> {
+        let url = spawn_custom_server(49019, "full", |config| {
+            config.registry.max_assets = Some(0);
+        });
+        let asset_req = json!({
+            "asset_id": "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05",
+            "contract": {
+                "entity": { "domain": "test.dev" },
+                "issuer_pubkey": ISSUER_KEY.public_key(&EC),
+                "name": "PPP coin",
+                "ticker": "PPP",
+                "version": 0
+            },
+        });
+        let rclient = reqwest::blocking::Client::new();
+
+        for (path, body) in &[
+            ("/", asset_req.clone()),
+            ("/register-batch", json!([asset_req])),
+        ] {
+            let resp = rclient
+                .post(&format!("{}{}", url, path))
+                .json(body)
+                .send()?;
+            assert_eq!(resp.status(), reqwest::StatusCode::INSUFFICIENT_STORAGE);
+            assert_eq!(
+                resp.text()?,
+                "the registry is full (0 assets), new registrations are not accepted"
+            );
+        }
+
+        // reads keep working
+        let resp = reqwest::blocking::get(&format!("{}/stats", url))?;
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_asset_file() {
+        let db_path = Path::new("/home/.registry/db");
+        let is_asset_file = |path: &str| is_asset_file(db_path, Path::new(path));
+        assert!(is_asset_file("/home/.registry/db/b1/b1405e.json"));
+        assert!(!is_asset_file("/home/.registry/db/b1/b1405e.json.tmp"));
+        assert!(!is_asset_file("/home/.registry/db/_map/ppp.json"));
+        assert!(!is_asset_file("/home/.registry/db/.git/b1405e.json"));
+        assert!(!is_asset_file("/home/.registry/other/b1405e.json"));
+    }
+
+    #[test]
+    fn test_parse_byte_range() {
+        assert_eq!(parse_byte_range("0-9", 100), Some((0, 9)));
+        assert_eq!(parse_byte_range("10-", 100), Some((10, 99)));
+        assert_eq!(parse_byte_range("-10", 100), Some((90, 99)));
+        assert_eq!(parse_byte_range("90-200", 100), Some((90, 99)));
+        assert_eq!(parse_byte_range("100-", 100), None);
+        assert_eq!(parse_byte_range("9-0", 100), None);
+        assert_eq!(parse_byte_range("0-", 0), None);
+        assert_eq!(parse_byte_range("foo", 100), None);
+    }
+
     #[test]
     fn test_validate_contract() -> Result<()> {
         try_contract(
@@ -479,17 +2728,45 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_validate_contract_asset_id() -> Result<()> {
+        let prevout = serde_json::from_value(json!({
+            "txid": "c1854811ffe022a023e42769a703d434a40cb3dc16407e1a47aa6279d6cd48b4",
+            "vout": 2
+        }))?;
+
+        let asset_id = CLIENT.validate_contract(
+            &json!({
+                "entity":{"domain":"test.dev"},
+                "issuer_pubkey": "037c7db0528e8b7b58e698ac104764f6852d74b5a7335bffcdad0ce799dd7742ec",
+                "name":"PPP coin",
+                "ticker":"PPP",
+                "version":0
+            }),
+            &ContractHash::from_str(
+                "ac5a08996e50a12b38e2ad9e5e3ff2899db889b08422361d9fbed65d7b9c209b",
+            )?,
+            Some(&prevout),
+        )?;
+
+        assert_eq!(
+            asset_id.or_err("missing asset id")?.to_hex(),
+            "b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05"
+        );
+        Ok(())
+    }
+
     fn try_contract(
         contract: Value,
         contract_hash: ContractHash,
         expected_error: Option<&str>,
     ) -> Result<()> {
         match (
-            CLIENT.validate_contract(&contract, &contract_hash),
+            CLIENT.validate_contract(&contract, &contract_hash, None),
             expected_error,
         ) {
-            (Ok(()), None) => Ok(()),
-            (Ok(()), Some(expected)) => bail!("expected {} to fail with '{}'", contract, expected),
+            (Ok(_), None) => Ok(()),
+            (Ok(_), Some(expected)) => bail!("expected {} to fail with '{}'", contract, expected),
             (Err(err), None) => bail!("{} failed with '{}'", contract, err),
             (Err(err), Some(expected)) => {
                 if err.to_string().contains(expected) {
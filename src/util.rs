@@ -2,7 +2,9 @@ use std::fmt;
 
 use bitcoin::util::misc::signed_msg_hash;
 use bitcoin::Txid;
-use bitcoin_hashes::{hex::ToHex, Hash};
+use bitcoin_hashes::hex::{FromHex, ToHex};
+use bitcoin_hashes::{sha256, Hash};
+use elements::AssetId;
 use regex::RegexSet;
 use secp256k1::Secp256k1;
 use serde::{Deserialize, Deserializer, Serializer};
@@ -44,6 +46,41 @@ pub fn verify_bitcoin_msg(
         .context("signature veritification failed")?)
 }
 
+// Registry responses are signed over the SHA256 of the exact response body bytes
+pub fn response_sig_msg(body: &[u8]) -> secp256k1::Message {
+    secp256k1::Message::from_slice(&sha256::Hash::hash(body).into_inner()).unwrap()
+}
+
+pub fn sign_response(
+    ec: &Secp256k1<secp256k1::SignOnly>,
+    key: &secp256k1::SecretKey,
+    body: &[u8],
+) -> Vec<u8> {
+    ec.sign(&response_sig_msg(body), key)
+        .serialize_compact()
+        .to_vec()
+}
+
+pub fn verify_response_sig(
+    ec: &Secp256k1<secp256k1::VerifyOnly>,
+    pubkey: &secp256k1::PublicKey,
+    body: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let signature = secp256k1::Signature::from_compact(signature)?;
+    Ok(ec
+        .verify(&response_sig_msg(body), &signature, pubkey)
+        .context("response signature verification failed")?)
+}
+
+// The asset id with its byte order reversed. Asset ids are displayed in reverse byte order, and
+// ids copied from tools that use the internal order are commonly mistaken for the display form.
+pub fn reverse_asset_id(asset_id: &AssetId) -> AssetId {
+    let mut bytes = hex::decode(asset_id.to_hex()).unwrap();
+    bytes.reverse();
+    AssetId::from_hex(&hex::encode(bytes)).unwrap()
+}
+
 pub fn verify_pubkey(pubkey: &[u8]) -> Result<()> {
     secp256k1::PublicKey::from_slice(pubkey)?;
     Ok(())
@@ -78,7 +115,24 @@ lazy_static! {
     };
 }
 
+pub const MAX_DOMAIN_LENGTH: usize = 255;
+pub const MAX_DOMAIN_LABELS: usize = 127;
+pub const MAX_LABEL_LENGTH: usize = 63;
+
 pub fn verify_domain_name(domain: &str) -> Result<()> {
+    verify_domain_name_with_limits(domain, None, None)
+}
+
+// Like `verify_domain_name`, with operator-configured limits on the overall length and
+// number of labels. These can only tighten the built-in limits.
+pub fn verify_domain_name_with_limits(
+    domain: &str,
+    max_length: Option<usize>,
+    max_labels: Option<usize>,
+) -> Result<()> {
+    let max_length = max_length.map_or(MAX_DOMAIN_LENGTH, |max| max.min(MAX_DOMAIN_LENGTH));
+    let max_labels = max_labels.map_or(MAX_DOMAIN_LABELS, |max| max.min(MAX_DOMAIN_LABELS));
+
     ensure!(!domain.starts_with('.'), "cannot start with a dot");
     ensure!(
         idna_to_ascii(domain)? == domain.to_string(),
@@ -88,14 +142,22 @@ pub fn verify_domain_name(domain: &str) -> Result<()> {
         domain.to_lowercase() == domain,
         "should be provided in lower-case"
     );
-    ensure!(domain.len() <= 255, "must be up to 255 characters");
+    ensure!(
+        domain.len() <= max_length,
+        "must be up to {} characters",
+        max_length
+    );
 
     let mut labels: Vec<&str> = domain.split('.').collect();
     // strip of the first dot from a domain to support fully qualified domain names
     if domain.ends_with(".") {
         labels.pop();
     }
-    ensure!(labels.len() <= 127, "must not have more than 127 labels");
+    ensure!(
+        labels.len() <= max_labels,
+        "must not have more than {} labels",
+        max_labels
+    );
 
     // prevents using "localhost"
     ensure!(labels.len() > 1, "must have at least two labels");
@@ -105,6 +167,11 @@ pub fn verify_domain_name(domain: &str) -> Result<()> {
         if i == 0 && label.parse::<f64>().is_ok() {
             bail!("the tld must not be a number");
         }
+        ensure!(
+            label.len() <= MAX_LABEL_LENGTH,
+            "labels must be up to {} characters",
+            MAX_LABEL_LENGTH
+        );
         ensure!(
             DOMAIN_LABEL.is_match(label),
             "must only contain allowed characters"
@@ -113,6 +180,23 @@ pub fn verify_domain_name(domain: &str) -> Result<()> {
     Ok(())
 }
 
+// Verify the domain's TLD is one of the `allowed_tlds`. An empty list allows any TLD.
+pub fn verify_domain_tld(domain: &str, allowed_tlds: &[String]) -> Result<()> {
+    if allowed_tlds.is_empty() {
+        return Ok(());
+    }
+
+    let tld = domain.trim_end_matches('.').rsplit('.').next().req()?;
+    ensure!(
+        allowed_tlds
+            .iter()
+            .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(tld)),
+        "the tld `{}` is not accepted by this registry",
+        tld
+    );
+    Ok(())
+}
+
 fn idna_to_ascii(domain: &str) -> Result<String> {
     Ok(idna::domain_to_ascii(domain)
         .ok()
@@ -129,6 +213,57 @@ where
         .and_then(|string| base64::decode(&string).map_err(|err| Error::custom(err.to_string())))
 }
 
+/// Deserializes an optional hex string to a 32 bytes array, like a sha256 digest.
+pub fn serde_from_hex32_opt<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<[u8; 32]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    Option::<String>::deserialize(deserializer)?
+        .map(|string| {
+            let bytes = hex::decode(&string).map_err(|err| Error::custom(err.to_string()))?;
+            ensure_len32(&bytes).map_err(|err| Error::custom(err.to_string()))
+        })
+        .transpose()
+}
+
+fn ensure_len32(bytes: &[u8]) -> Result<[u8; 32]> {
+    ensure!(bytes.len() == 32, "expected 32 bytes, got {}", bytes.len());
+    let mut array = [0u8; 32];
+    array.copy_from_slice(bytes);
+    Ok(array)
+}
+
+/// Deserializes a public key to a `Vec<u8>`. Accepts hex (optionally `0x`-prefixed) or base64,
+/// and normalizes valid keys to their compressed 33-byte form. Invalid keys are left as-is, to
+/// be rejected by `verify_pubkey`.
+pub fn serde_from_pubkey<'de, D>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    String::deserialize(deserializer).and_then(|string| {
+        parse_pubkey_bytes(&string).map_err(|err| Error::custom(err.to_string()))
+    })
+}
+
+pub fn parse_pubkey_bytes(s: &str) -> Result<Vec<u8>> {
+    let hex_str = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    let bytes = match hex::decode(hex_str) {
+        Ok(bytes) => bytes,
+        Err(_) => base64::decode(s).context("invalid pubkey encoding (expected hex or base64)")?,
+    };
+    Ok(match secp256k1::PublicKey::from_slice(&bytes) {
+        Ok(pubkey) => pubkey.serialize().to_vec(),
+        Err(_) => bytes,
+    })
+}
+
 /// Deserializes a hex string to a `Vec<u8>`.
 pub fn serde_from_hex<'de, D>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error>
 where
@@ -148,10 +283,48 @@ where
     serializer.serialize_str(&buffer.as_ref().to_hex())
 }
 
+/// Serializes an optional 32 bytes array into a hex string.
+pub fn serde_to_hex32_opt<S>(
+    buffer: &Option<[u8; 32]>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match buffer {
+        Some(buffer) => serializer.serialize_str(&buffer.to_hex()),
+        None => serializer.serialize_none(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_response_sig() {
+        let key = secp256k1::SecretKey::from_slice(&[0xcd; 32]).unwrap();
+        let pubkey = secp256k1::PublicKey::from_secret_key(&Secp256k1::signing_only(), &key);
+        let ec = Secp256k1::verification_only();
+
+        let signature = sign_response(&Secp256k1::signing_only(), &key, b"{}");
+        assert!(verify_response_sig(&ec, &pubkey, b"{}", &signature).is_ok());
+        assert!(verify_response_sig(&ec, &pubkey, b"{ }", &signature).is_err());
+    }
+
+    #[test]
+    fn test_reverse_asset_id() {
+        let asset_id =
+            AssetId::from_hex("b1405e4eefa91c6690198b4f85d73e8e0babee08f73b2c8af411486dc28dbc05")
+                .unwrap();
+        let reversed = reverse_asset_id(&asset_id);
+        assert_eq!(
+            reversed.to_hex(),
+            "05bc8dc26d4811f48a2c3bf708eeab0b8e3ed7854f8b1990661ca9ef4e5e40b1"
+        );
+        assert_eq!(reverse_asset_id(&reversed), asset_id);
+    }
+
     #[test]
     fn test_verify_domain_name() {
         assert!(verify_domain_name("foo.com").is_ok());
@@ -159,6 +332,52 @@ mod tests {
         assert!(verify_domain_name(">foo.com").is_err());
         assert!(verify_domain_name("δοκιμή.com").is_err());
         assert!(verify_domain_name("xn--jxalpdlp.com").is_ok());
+
+        assert!(verify_domain_name(&format!("{}.com", "a".repeat(63))).is_ok());
+        assert!(verify_domain_name(&format!("{}.com", "a".repeat(64))).is_err());
+        assert!(verify_domain_name(&format!("{}com", "a.".repeat(126))).is_ok());
+        assert!(verify_domain_name(&format!("{}com", "a.".repeat(127))).is_err());
+
+        assert!(verify_domain_name_with_limits("foo.bar.com", Some(11), Some(3)).is_ok());
+        assert!(verify_domain_name_with_limits("foo.bar.com", Some(10), None).is_err());
+        assert!(verify_domain_name_with_limits("foo.bar.com", None, Some(2)).is_err());
+        // configured limits cannot loosen the built-in ones
+        let long_label = format!("{}.com", "a".repeat(64));
+        assert!(verify_domain_name_with_limits(&long_label, Some(1000), None).is_err());
+    }
+
+    #[test]
+    fn test_parse_pubkey_bytes() -> Result<()> {
+        let hex_key = "026be637f97bc191c27522577bd6fe284b54404321652fcc4eb62aa0f4cfd6d172";
+        let expected = hex::decode(hex_key)?;
+
+        assert_eq!(parse_pubkey_bytes(hex_key)?, expected);
+        assert_eq!(parse_pubkey_bytes(&hex_key.to_uppercase())?, expected);
+        assert_eq!(parse_pubkey_bytes(&format!("0x{}", hex_key))?, expected);
+        assert_eq!(parse_pubkey_bytes(&base64::encode(&expected))?, expected);
+
+        // uncompressed keys are normalized to the compressed form
+        let uncompressed = secp256k1::PublicKey::from_slice(&expected)?.serialize_uncompressed();
+        assert_eq!(
+            parse_pubkey_bytes(&hex::encode(&uncompressed[..]))?,
+            expected
+        );
+
+        assert!(parse_pubkey_bytes("not a pubkey!").is_err());
+        // invalid points are left for verify_pubkey to reject
+        assert!(verify_pubkey(&parse_pubkey_bytes("0011")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_domain_tld() {
+        let allowed = vec!["com".to_string(), ".org".to_string()];
+        assert!(verify_domain_tld("foo.com", &[]).is_ok());
+        assert!(verify_domain_tld("foo.onion", &[]).is_ok());
+        assert!(verify_domain_tld("foo.com", &allowed).is_ok());
+        assert!(verify_domain_tld("foo.bar.org.", &allowed).is_ok());
+        assert!(verify_domain_tld("foo.onion", &allowed).is_err());
+        assert!(verify_domain_tld("com.net", &allowed).is_err());
     }
 
     #[test]
@@ -0,0 +1,274 @@
+use std::{convert::Infallible, fmt, str::FromStr, thread, time::Duration};
+
+use bitcoin_hashes::{hmac, sha256, Hash, HashEngine};
+use elements::AssetId;
+use reqwest::{blocking::Client as ReqClient, header};
+
+use crate::asset::Asset;
+use crate::errors::{Result, ResultExt};
+use crate::queue::{PendingEvent, RetryQueue, Target};
+
+// timeout for a single delivery attempt
+const TIMEOUT: Duration = Duration::from_secs(10);
+// delivery attempts before giving up, with exponential backoff starting at RETRY_DELAY
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+// header holding the HMAC-SHA256 of the payload, as `sha256=<hex>`
+const SIGNATURE_HEADER: &str = "X-Signature";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventType {
+    Add,
+    Delete,
+    Quarantine,
+    Unquarantine,
+}
+
+impl EventType {
+    // The update type, as passed to the hook script
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Add => "add",
+            EventType::Delete => "delete",
+            EventType::Quarantine => "quarantine",
+            EventType::Unquarantine => "unquarantine",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub event_type: EventType,
+    pub asset_id: AssetId,
+    pub asset: Asset,
+}
+
+impl Event {
+    pub fn new(event_type: EventType, asset: &Asset) -> Self {
+        Event {
+            event_type,
+            asset_id: asset.asset_id,
+            asset: asset.clone(),
+        }
+    }
+}
+
+// Shared secret for signing webhook payloads. Kept out of the Debug output, which gets logged.
+#[derive(Clone, Default)]
+pub struct WebhookSecret(String);
+
+impl FromStr for WebhookSecret {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Infallible> {
+        Ok(WebhookSecret(s.to_string()))
+    }
+}
+
+impl fmt::Debug for WebhookSecret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WebhookSecret(<redacted>)")
+    }
+}
+
+// HTTP webhook notified about registry updates, the network-native complement to the hook script
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    secret: Option<WebhookSecret>,
+    retry_queue: Option<RetryQueue>,
+    rclient: ReqClient,
+}
+
+impl Webhook {
+    pub fn new(
+        url: String,
+        secret: Option<WebhookSecret>,
+        retry_queue: Option<RetryQueue>,
+    ) -> Self {
+        Webhook {
+            url,
+            secret,
+            retry_queue,
+            rclient: ReqClient::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .expect("failed initializing http client"),
+        }
+    }
+
+    // Deliver the event in the background, without blocking the caller. Failures are logged,
+    // and queued for later retry when the retry queue is enabled.
+    pub fn notify(&self, event: Event) {
+        let webhook = self.clone();
+        thread::spawn(move || {
+            if let Err(err) = webhook.deliver(&event) {
+                warn!("webhook delivery for {} failed: {:?}", event.asset_id, err);
+                if let Some(queue) = &webhook.retry_queue {
+                    let pending = PendingEvent::new(
+                        Target::Webhook,
+                        event.event_type,
+                        &event.asset,
+                        None,
+                        None,
+                    );
+                    if let Err(err) = queue.push(&pending) {
+                        warn!("failed queueing webhook for retry: {:?}", err);
+                    }
+                }
+            }
+        });
+    }
+
+    fn deliver(&self, event: &Event) -> Result<()> {
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.send(event) {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt < MAX_ATTEMPTS => {
+                    debug!(
+                        "webhook attempt {} for {} failed, retrying in {:?}: {:?}",
+                        attempt, event.asset_id, delay, err
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Send the event once, without retrying
+    pub fn send(&self, event: &Event) -> Result<()> {
+        debug!("sending webhook to {} for {}", self.url, event.asset_id);
+
+        let payload = serde_json::to_vec(event)?;
+
+        let mut req = self
+            .rclient
+            .post(&self.url)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.secret {
+            req = req.header(SIGNATURE_HEADER, sign_payload(secret, &payload));
+        }
+
+        req.body(payload)
+            .send()
+            .context("failed sending webhook")?
+            .error_for_status()
+            .context("webhook failed")?;
+        Ok(())
+    }
+}
+
+// Sign the payload using HMAC-SHA256 keyed with the shared secret, formatted as `sha256=<hex>`
+fn sign_payload(secret: &WebhookSecret, payload: &[u8]) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.0.as_bytes());
+    engine.input(payload);
+    let mac = hmac::Hmac::<sha256::Hash>::from_engine(engine);
+    format!("sha256={}", hex::encode(mac.into_inner()))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+
+    // A request received by the mock receiver
+    #[derive(Debug)]
+    pub struct Delivery {
+        pub signature: Option<String>,
+        pub body: serde_json::Value,
+    }
+
+    // A webhook receiver replying with the given statuses in order (and 200 once they run out),
+    // reporting the requests it receives. Returns its url.
+    pub fn spawn_mock_receiver(
+        mut statuses: Vec<u16>,
+    ) -> Result<(String, mpsc::Receiver<Delivery>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/webhook", listener.local_addr()?);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            statuses.reverse();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let (mut content_length, mut signature) = (0, None);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    let mut parts = line.trim_end().splitn(2, ": ");
+                    let name = parts.next().unwrap().to_lowercase();
+                    let value = parts.next().unwrap_or_default().to_string();
+                    if name == "content-length" {
+                        content_length = value.parse().unwrap();
+                    } else if name == SIGNATURE_HEADER.to_lowercase() {
+                        signature = Some(value);
+                    }
+                    line.clear();
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let status = statuses.pop().unwrap_or(200);
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                let body = serde_json::from_slice(&body).unwrap();
+                sender.send(Delivery { signature, body }).ok();
+            }
+        });
+        Ok((url, receiver))
+    }
+
+    #[test]
+    fn test_delivery() -> Result<()> {
+        let asset = Asset::load(PathBuf::from("test/asset-b1405e.json"))?;
+        let secret = WebhookSecret("secret".to_string());
+        let timeout = Duration::from_secs(5);
+
+        let (url, deliveries) = spawn_mock_receiver(vec![])?;
+        let webhook = Webhook::new(url, Some(secret.clone()), None);
+        webhook.notify(Event::new(EventType::Add, &asset));
+
+        let delivery = deliveries.recv_timeout(timeout)?;
+        assert_eq!(delivery.body["type"], "add");
+        assert_eq!(
+            delivery.body["asset_id"],
+            serde_json::to_value(asset.asset_id)?
+        );
+        assert_eq!(delivery.body["asset"]["name"], "PPP coin");
+        let payload = serde_json::to_vec(&Event::new(EventType::Add, &asset))?;
+        assert_eq!(delivery.signature, Some(sign_payload(&secret, &payload)));
+
+        // failed attempts are retried
+        let (url, deliveries) = spawn_mock_receiver(vec![500])?;
+        let webhook = Webhook::new(url, None, None);
+        webhook.deliver(&Event::new(EventType::Delete, &asset))?;
+        assert_eq!(deliveries.recv_timeout(timeout)?.body["type"], "delete");
+        let retried = deliveries.recv_timeout(timeout)?;
+        assert_eq!(retried.body["type"], "delete");
+        assert_eq!(retried.signature, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        let secret = WebhookSecret("secret".to_string());
+        assert_eq!(
+            sign_payload(&secret, br#"{"type":"add"}"#),
+            "sha256=b22062e84d3302321800d234f6513b95bf005240b9476aeb4f3a10ee1cf4c13c"
+        );
+    }
+}